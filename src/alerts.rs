@@ -0,0 +1,393 @@
+use crate::email_sender;
+use crate::models::MarketData;
+use crate::rules::{self, Metric, Rule, RuleKind};
+use crate::store::{data_dir, write_atomic};
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Last evaluation of a rule for an instrument
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RuleState {
+    trade_date: NaiveDate,
+    /// Whether the rule held at that bar
+    active: bool,
+    fired_at: Option<DateTime<Utc>>,
+}
+
+/// Rule states in `$DATA_DIR/alerts.json`, keyed by rule and instrument
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AlertState {
+    rules: BTreeMap<String, RuleState>,
+}
+
+impl AlertState {
+    fn path() -> PathBuf {
+        data_dir().join("alerts.json")
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Evaluate `rules` at the latest bar of `data` and record the results
+    ///
+    /// Returns the alerts that fired. Their rules are recorded as not holding
+    /// yet, so they fire again on the next run until [`Self::delivered`]
+    /// records them.
+    fn update(&mut self, rules: &[Rule], data: &MarketData) -> Vec<Alert> {
+        let Some(latest) = data.bars.last() else {
+            return Vec::new();
+        };
+        let code = data.instrument.tushare_code();
+        let trade_date = latest.trade_date();
+        let mut alerts = Vec::new();
+
+        for rule in rules.iter().filter(|rule| rule.applies_to(&code)) {
+            let key = state_key(&rule.name, &code);
+            let evaluation = rule.condition.evaluate(&data.bars);
+            let Some(current) = evaluation.current else {
+                debug!(
+                    "Alert rule '{}' is not defined for {} yet, skipping",
+                    rule.name, code
+                );
+                continue;
+            };
+
+            let last = self.rules.get(&key);
+            let was_active = match last {
+                Some(last) => last.active,
+                None => evaluation.previous.unwrap_or(false),
+            };
+            let fired_at = last.and_then(|last| last.fired_at);
+
+            let fired = current && !was_active;
+            if fired {
+                alerts.push(Alert {
+                    rule: rule.name.clone(),
+                    condition: rule.condition.to_string(),
+                    trade_date,
+                    close: latest.close,
+                    values: evaluation.values,
+                });
+            }
+
+            self.rules.insert(
+                key,
+                RuleState {
+                    trade_date,
+                    active: current && !fired,
+                    fired_at,
+                },
+            );
+        }
+
+        alerts
+    }
+
+    /// Record a fired alert as delivered, so it does not fire again while its
+    /// rule keeps holding
+    fn delivered(&mut self, alert: &Alert, code: &str, at: DateTime<Utc>) {
+        if let Some(state) = self.rules.get_mut(&state_key(&alert.rule, code)) {
+            state.active = true;
+            state.fired_at = Some(at);
+        }
+    }
+}
+
+fn state_key(rule: &str, code: &str) -> String {
+    format!("{}|{}", rule, code)
+}
+
+/// A rule that started to hold on the latest bar
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub rule: String,
+    pub condition: String,
+    pub trade_date: NaiveDate,
+    pub close: f64,
+    /// Latest value of every metric the condition uses
+    pub values: Vec<(Metric, Option<f64>)>,
+}
+
+/// Evaluate the configured alert rules against freshly fetched data
+///
+/// A rule fires when it holds on the latest bar but did not on the bar it
+/// was last evaluated at; without a recorded state, the previous bar is
+/// evaluated instead, so deploying a rule does not fire it for a condition
+/// that has held for a while. Rule states are saved on every run. With
+/// `notify`, fired alerts are emailed right away; an alert that was only
+/// logged, or whose email failed, is recorded as not yet holding so the next
+/// run fires it again. Mock data is never evaluated.
+pub async fn check(data: &MarketData, notify: bool) -> Result<Vec<Alert>> {
+    let rules: Vec<_> = rules::configured_rules()?
+        .into_iter()
+        .filter(|rule| rule.kind == RuleKind::Alert)
+        .collect();
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+    if data.is_mock {
+        info!("Not evaluating alert rules on mock data");
+        return Ok(Vec::new());
+    }
+
+    let path = AlertState::path();
+    let mut state = AlertState::load(&path).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable alert state: {:#}", e);
+        AlertState::default()
+    });
+    let alerts = state.update(&rules, data);
+
+    let code = data.instrument.tushare_code();
+    for alert in &alerts {
+        info!("{}", format_alert(data, alert));
+        if notify {
+            match send_alert(data, alert).await {
+                Ok(()) => state.delivered(alert, &code, Utc::now()),
+                Err(e) => warn!("Failed to send alert '{}': {:#}", alert.rule, e),
+            }
+        }
+    }
+
+    if let Err(e) = state.save(&path) {
+        warn!("Failed to save alert state: {:#}", e);
+    }
+
+    Ok(alerts)
+}
+
+fn format_alert(data: &MarketData, alert: &Alert) -> String {
+    let values = alert
+        .values
+        .iter()
+        .map(|(metric, value)| match value {
+            Some(value) => format!("{} {:.2}", metric, value),
+            None => format!("{} n/a", metric),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "🔔 Alert: {} - {}\n\n\
+        📅 Date: {}\n\
+        📐 Condition: {}\n\
+        📊 Values: {}\n\
+        💰 Close: {:.2} {}\n\
+        🔌 Data Source: {}\n",
+        alert.rule,
+        data.instrument,
+        alert.trade_date,
+        alert.condition,
+        values,
+        alert.close,
+        data.instrument.price_unit(),
+        data.source
+    )
+}
+
+async fn send_alert(data: &MarketData, alert: &Alert) -> Result<()> {
+    let subject = format!("🔔 {} - {}", alert.rule, data.instrument.name);
+    let job = format!("alert:{}:{}", alert.rule, data.instrument.tushare_code());
+    email_sender::send_email_once(
+        &job,
+        alert.trade_date,
+        &subject,
+        &format_alert(data, alert),
+        false,
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Instrument, StockData, market_close_utc};
+    use crate::validator::ValidationReport;
+    use chrono::Days;
+
+    fn date(i: usize) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, 1).unwrap() + Days::new(i as u64)
+    }
+
+    fn data(closes: &[f64]) -> MarketData {
+        MarketData {
+            instrument: Instrument::csi300(),
+            source: "Test".to_string(),
+            is_mock: false,
+            bars: closes
+                .iter()
+                .enumerate()
+                .map(|(i, &close)| StockData {
+                    date: market_close_utc(date(i)),
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    volume: 1_000,
+                    amount: None,
+                })
+                .collect(),
+            validation: ValidationReport::default(),
+        }
+    }
+
+    fn above_100() -> Vec<Rule> {
+        vec![Rule {
+            name: "Above 100".to_string(),
+            kind: RuleKind::Alert,
+            condition: "close > 100".parse().unwrap(),
+            instruments: Vec::new(),
+        }]
+    }
+
+    const KEY: &str = "Above 100|000300.SH";
+
+    fn state(active: bool) -> AlertState {
+        let mut state = AlertState::default();
+        state.rules.insert(
+            KEY.to_string(),
+            RuleState {
+                trade_date: date(0),
+                active,
+                fired_at: None,
+            },
+        );
+        state
+    }
+
+    fn fired(alerts: &[Alert]) -> Vec<&str> {
+        alerts.iter().map(|alert| alert.rule.as_str()).collect()
+    }
+
+    #[test]
+    fn fires_when_a_rule_starts_to_hold() {
+        let mut state = state(false);
+
+        let alerts = state.update(&above_100(), &data(&[101.0, 102.0]));
+
+        assert_eq!(fired(&alerts), ["Above 100"]);
+        assert_eq!(alerts[0].trade_date, date(1));
+        assert_eq!(alerts[0].close, 102.0);
+        assert_eq!(alerts[0].condition, "close > 100");
+        // Pending until delivered
+        assert!(!state.rules[KEY].active);
+        assert_eq!(state.rules[KEY].trade_date, date(1));
+
+        let at = market_close_utc(date(1));
+        state.delivered(&alerts[0], "000300.SH", at);
+        assert!(state.rules[KEY].active);
+        assert_eq!(state.rules[KEY].fired_at, Some(at));
+    }
+
+    #[test]
+    fn does_not_fire_while_a_rule_keeps_holding() {
+        let mut state = state(true);
+
+        let alerts = state.update(&above_100(), &data(&[99.0, 102.0]));
+
+        assert!(alerts.is_empty());
+        assert!(state.rules[KEY].active);
+        assert_eq!(state.rules[KEY].trade_date, date(1));
+    }
+
+    #[test]
+    fn records_a_rule_that_stops_holding() {
+        let mut state = state(true);
+
+        assert!(state.update(&above_100(), &data(&[101.0, 99.0])).is_empty());
+        assert!(!state.rules[KEY].active);
+
+        // And fires once it holds again
+        let alerts = state.update(&above_100(), &data(&[101.0, 99.0, 101.0]));
+        assert_eq!(fired(&alerts), ["Above 100"]);
+    }
+
+    #[test]
+    fn falls_back_to_the_previous_bar_without_state() {
+        // Held on the bar before: a new rule does not fire for it
+        let mut state = AlertState::default();
+        assert!(
+            state
+                .update(&above_100(), &data(&[101.0, 102.0]))
+                .is_empty()
+        );
+        assert!(state.rules[KEY].active);
+
+        // Started to hold on the latest bar
+        let mut state = AlertState::default();
+        let alerts = state.update(&above_100(), &data(&[99.0, 102.0]));
+        assert_eq!(fired(&alerts), ["Above 100"]);
+
+        // A single bar has no previous one to compare against
+        let mut state = AlertState::default();
+        let alerts = state.update(&above_100(), &data(&[102.0]));
+        assert_eq!(fired(&alerts), ["Above 100"]);
+    }
+
+    #[test]
+    fn fires_an_undelivered_alert_again() {
+        let mut state = state(false);
+
+        assert_eq!(state.update(&above_100(), &data(&[99.0, 102.0])).len(), 1);
+        // Not delivered, e.g. a run without --send-email or a failed email
+        let alerts = state.update(&above_100(), &data(&[99.0, 102.0, 103.0]));
+
+        assert_eq!(fired(&alerts), ["Above 100"]);
+        assert_eq!(state.rules[KEY].fired_at, None);
+    }
+
+    #[test]
+    fn skips_rules_for_other_instruments_and_undefined_ones() {
+        let rules = vec![
+            Rule {
+                instruments: vec!["000905.SH".to_string()],
+                ..above_100().remove(0)
+            },
+            Rule {
+                name: "Above SMA".to_string(),
+                kind: RuleKind::Alert,
+                condition: "close > sma(5)".parse().unwrap(),
+                instruments: Vec::new(),
+            },
+        ];
+        let mut state = AlertState::default();
+
+        assert!(state.update(&rules, &data(&[99.0, 102.0])).is_empty());
+        assert!(state.rules.is_empty());
+    }
+
+    #[test]
+    fn saves_and_loads_the_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("alerts.json");
+        assert!(AlertState::load(&path).unwrap().rules.is_empty());
+
+        let mut state = state(false);
+        let alerts = state.update(&above_100(), &data(&[99.0, 102.0]));
+        let at = market_close_utc(date(1));
+        state.delivered(&alerts[0], "000300.SH", at);
+        state.save(&path).unwrap();
+
+        let loaded = AlertState::load(&path).unwrap();
+        assert_eq!(loaded.rules.len(), 1);
+        assert!(loaded.rules[KEY].active);
+        assert_eq!(loaded.rules[KEY].trade_date, date(1));
+        assert_eq!(loaded.rules[KEY].fired_at, Some(at));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(AlertState::load(&path).is_err());
+    }
+}
@@ -1,10 +1,27 @@
-use crate::models::{DailyAnalysis, MonthlyAnalysis, StockData, WeeklyAnalysis};
+use crate::calendar::{Period, TradingCalendar, quarter_of};
+use crate::models::{
+    DailyAnalysis, IndicatorSnapshot, MacdCross, MarketData, MonthlyAnalysis, MovingAverage,
+    PeriodSummary, QuarterlyAnalysis, SNAPSHOT_MA_PERIODS, StockData, WeeklyAnalysis, market_date,
+};
+use crate::risk::{self, RiskMetrics};
+use crate::rules;
+use crate::streaming::{
+    BollingerState, KdjState, MacdState, RsiMethod, RsiState, Seed, SmaState, last_two,
+};
+use crate::valuation::{self, ValuationPoint};
 use anyhow::Result;
 use chrono::Datelike;
 use tracing::info;
 
 /// Analyze daily data
-pub async fn analyze_daily_data(data: &[StockData]) -> Result<DailyAnalysis> {
+///
+/// Valuation is taken from `valuation_history`, the index's TuShare
+/// valuation history.
+pub async fn analyze_daily_data(
+    market_data: &MarketData,
+    valuation_history: Option<&[ValuationPoint]>,
+) -> Result<DailyAnalysis> {
+    let data = &market_data.bars;
     if data.is_empty() {
         return Err(anyhow::anyhow!("No data available for analysis"));
     }
@@ -33,6 +50,10 @@ pub async fn analyze_daily_data(data: &[StockData]) -> Result<DailyAnalysis> {
         ((historical_high - latest.close) / (historical_high - historical_low)) * 100.0;
 
     let analysis = DailyAnalysis {
+        instrument: market_data.instrument.clone(),
+        data_source: market_data.source.clone(),
+        is_mock: market_data.is_mock,
+        data_quality: market_data.validation.clone(),
         date: latest.date,
         current_price: latest.close,
         previous_price: previous.close,
@@ -42,10 +63,15 @@ pub async fn analyze_daily_data(data: &[StockData]) -> Result<DailyAnalysis> {
         historical_high,
         historical_low,
         volume: latest.volume,
+        indicators: indicator_snapshot(data),
+        signals: rules::active_signals(market_data),
+        valuation: valuation_history
+            .and_then(|history| valuation::analyze(history, latest.trade_date())),
     };
 
     info!(
-        "Daily analysis completed: Price {:.2}, Change {:.2}%, Relative to High {:.2}%, Relative to Low {:.2}%",
+        "{} daily analysis completed: Price {:.2}, Change {:.2}%, Relative to High {:.2}%, Relative to Low {:.2}%",
+        analysis.instrument.name,
         analysis.current_price,
         analysis.price_change_pct,
         analysis.relative_to_high,
@@ -55,225 +81,381 @@ pub async fn analyze_daily_data(data: &[StockData]) -> Result<DailyAnalysis> {
     Ok(analysis)
 }
 
-/// Analyze weekly data
-pub async fn analyze_weekly_data(data: &[StockData]) -> Result<WeeklyAnalysis> {
-    if data.is_empty() {
-        return Err(anyhow::anyhow!("No data available for analysis"));
+/// Technical indicators at the latest bar
+///
+/// Only the latest values are needed, so the bars are fed through streaming
+/// states rather than building whole series.
+fn indicator_snapshot(bars: &[StockData]) -> IndicatorSnapshot {
+    let Some(close) = bars.last().map(|bar| bar.close) else {
+        return IndicatorSnapshot::default();
+    };
+
+    let moving_averages = SNAPSHOT_MA_PERIODS
+        .iter()
+        .filter_map(|&period| {
+            let value = last_two(SmaState::new(period), bars).1?;
+            Some(MovingAverage {
+                period,
+                value,
+                distance_pct: (close - value) / value * 100.0,
+            })
+        })
+        .collect();
+
+    let (previous_macd, macd) = last_two(MacdState::new(12, 26, 9, Seed::Sma), bars);
+    let macd_cross = macd.map(|latest| {
+        let previous = previous_macd.map(|previous| previous.histogram);
+        match (previous, latest.histogram) {
+            (Some(previous), current) if previous <= 0.0 && current > 0.0 => MacdCross::Bullish,
+            (Some(previous), current) if previous >= 0.0 && current < 0.0 => MacdCross::Bearish,
+            (_, current) if current >= 0.0 => MacdCross::Above,
+            _ => MacdCross::Below,
+        }
+    });
+
+    IndicatorSnapshot {
+        moving_averages,
+        rsi: last_two(RsiState::new(14, RsiMethod::Wilder(Seed::Sma)), bars).1,
+        macd,
+        macd_cross,
+        bollinger_percent_b: last_two(BollingerState::new(20, 2.0), bars)
+            .1
+            .map(|bands| bands.percent_b),
+        kdj: last_two(KdjState::new(9, 3, 3), bars).1,
     }
+}
 
-    let start_data = &data[0];
-    let end_data = data.last().unwrap();
+/// Risk over the `risk::lookback_days()` bars of `history` up to `end`
+fn trailing_risk(history: Option<&MarketData>, end: &StockData) -> Option<RiskMetrics> {
+    let bars = &history?.bars;
+    let end_date = end.trade_date();
+    let last = bars.partition_point(|bar| bar.trade_date() <= end_date);
+    let first = last.saturating_sub(risk::lookback_days() + 1);
+    risk::analyze(&bars[first..last], risk::risk_free_rate())
+}
 
-    let weekly_change_pct = ((end_data.close - start_data.close) / start_data.close) * 100.0;
+/// Summarize the bars of a reporting period
+///
+/// Risk metrics are measured over `history`, the instrument's daily history,
+/// and valuation is taken from `valuation_history`, both as of the period's
+/// last bar.
+fn summarize_period(
+    market_data: &MarketData,
+    history: Option<&MarketData>,
+    valuation_history: Option<&[ValuationPoint]>,
+) -> Result<PeriodSummary> {
+    let data = &market_data.bars;
+    let (Some(start_data), Some(end_data)) = (data.first(), data.last()) else {
+        return Err(anyhow::anyhow!("No data available for analysis"));
+    };
 
-    // Find highest and lowest prices within the week
-    let mut highest_price = f64::NEG_INFINITY;
-    let mut lowest_price = f64::INFINITY;
-    let mut highest_date = start_data.date;
-    let mut lowest_date = start_data.date;
+    let mut highest = start_data;
+    let mut lowest = start_data;
     let mut total_volume = 0u64;
 
     for stock_data in data {
-        if stock_data.high > highest_price {
-            highest_price = stock_data.high;
-            highest_date = stock_data.date;
+        if stock_data.high > highest.high {
+            highest = stock_data;
         }
-        if stock_data.low < lowest_price {
-            lowest_price = stock_data.low;
-            lowest_date = stock_data.date;
+        if stock_data.low < lowest.low {
+            lowest = stock_data;
         }
         total_volume += stock_data.volume;
     }
 
-    let average_volume = total_volume as f64 / data.len() as f64;
-
-    let analysis = WeeklyAnalysis {
+    Ok(PeriodSummary {
         start_date: start_data.date,
         end_date: end_data.date,
         start_price: start_data.close,
         end_price: end_data.close,
-        weekly_change_pct,
-        highest_price,
-        highest_date,
-        lowest_price,
-        lowest_date,
-        average_volume,
+        change_pct: ((end_data.close - start_data.close) / start_data.close) * 100.0,
+        highest_price: highest.high,
+        highest_date: highest.date,
+        lowest_price: lowest.low,
+        lowest_date: lowest.date,
+        average_volume: total_volume as f64 / data.len() as f64,
         total_volume,
+        risk: trailing_risk(history, end_data),
+        valuation: valuation_history
+            .and_then(|history| valuation::analyze(history, end_data.trade_date())),
+    })
+}
+
+/// Analyze weekly data
+pub async fn analyze_weekly_data(
+    market_data: &MarketData,
+    history: Option<&MarketData>,
+    valuation_history: Option<&[ValuationPoint]>,
+) -> Result<WeeklyAnalysis> {
+    let analysis = WeeklyAnalysis {
+        instrument: market_data.instrument.clone(),
+        data_source: market_data.source.clone(),
+        is_mock: market_data.is_mock,
+        data_quality: market_data.validation.clone(),
+        summary: summarize_period(market_data, history, valuation_history)?,
     };
 
     info!(
-        "Weekly analysis completed: Weekly change {:.2}%, Highest price {:.2}, Lowest price {:.2}",
-        analysis.weekly_change_pct, analysis.highest_price, analysis.lowest_price
+        "{} weekly analysis completed: Weekly change {:.2}%, Highest price {:.2}, Lowest price {:.2}",
+        analysis.instrument.name,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        analysis.summary.lowest_price
     );
 
     Ok(analysis)
 }
 
 /// Analyze monthly data
-pub async fn analyze_monthly_data(data: &[StockData]) -> Result<MonthlyAnalysis> {
-    if data.is_empty() {
-        return Err(anyhow::anyhow!("No data available for analysis"));
-    }
-
-    let start_data = &data[0];
-    let end_data = data.last().unwrap();
-
-    let monthly_change_pct = ((end_data.close - start_data.close) / start_data.close) * 100.0;
-
-    // Find highest and lowest prices within the month
-    let mut highest_price = f64::NEG_INFINITY;
-    let mut lowest_price = f64::INFINITY;
-    let mut highest_date = start_data.date;
-    let mut lowest_date = start_data.date;
-    let mut total_volume = 0u64;
-
-    for stock_data in data {
-        if stock_data.high > highest_price {
-            highest_price = stock_data.high;
-            highest_date = stock_data.date;
-        }
-        if stock_data.low < lowest_price {
-            lowest_price = stock_data.low;
-            lowest_date = stock_data.date;
-        }
-        total_volume += stock_data.volume;
-    }
-
-    let average_volume = total_volume as f64 / data.len() as f64;
+pub async fn analyze_monthly_data(
+    market_data: &MarketData,
+    history: Option<&MarketData>,
+    valuation_history: Option<&[ValuationPoint]>,
+) -> Result<MonthlyAnalysis> {
+    let summary = summarize_period(market_data, history, valuation_history)?;
+    let end_date = market_date(summary.end_date);
 
     let analysis = MonthlyAnalysis {
-        year: end_data.date.year(),
-        month: end_data.date.month(),
-        start_date: start_data.date,
-        end_date: end_data.date,
-        start_price: start_data.close,
-        end_price: end_data.close,
-        monthly_change_pct,
-        highest_price,
-        highest_date,
-        lowest_price,
-        lowest_date,
-        average_volume,
-        total_volume,
+        instrument: market_data.instrument.clone(),
+        data_source: market_data.source.clone(),
+        is_mock: market_data.is_mock,
+        data_quality: market_data.validation.clone(),
+        year: end_date.year(),
+        month: end_date.month(),
+        complete: TradingCalendar::global().is_last_trading_day(Period::Month, end_date),
+        summary,
     };
 
     info!(
-        "Monthly analysis completed: Monthly change {:.2}%, Highest price {:.2}, Lowest price {:.2}",
-        analysis.monthly_change_pct, analysis.highest_price, analysis.lowest_price
+        "{} monthly analysis completed: Monthly change {:.2}%, Highest price {:.2}, Lowest price {:.2}",
+        analysis.instrument.name,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        analysis.summary.lowest_price
     );
 
     Ok(analysis)
 }
 
-/// Calculate moving average
-#[allow(dead_code)]
-pub fn calculate_moving_average(data: &[StockData], period: usize) -> Vec<f64> {
-    let mut averages = Vec::new();
-
-    for i in 0..data.len() {
-        if i < period - 1 {
-            averages.push(0.0);
-            continue;
-        }
+/// Analyze quarterly data
+pub async fn analyze_quarterly_data(
+    market_data: &MarketData,
+    history: Option<&MarketData>,
+    valuation_history: Option<&[ValuationPoint]>,
+) -> Result<QuarterlyAnalysis> {
+    let summary = summarize_period(market_data, history, valuation_history)?;
+    let end_date = market_date(summary.end_date);
+
+    let analysis = QuarterlyAnalysis {
+        instrument: market_data.instrument.clone(),
+        data_source: market_data.source.clone(),
+        is_mock: market_data.is_mock,
+        data_quality: market_data.validation.clone(),
+        year: end_date.year(),
+        quarter: quarter_of(end_date),
+        complete: TradingCalendar::global().is_last_trading_day(Period::Quarter, end_date),
+        summary,
+    };
 
-        let sum: f64 = data[i - period + 1..=i].iter().map(|d| d.close).sum();
-        let average = sum / period as f64;
-        averages.push(average);
-    }
+    info!(
+        "{} quarterly analysis completed: Quarterly change {:.2}%, Highest price {:.2}, Lowest price {:.2}",
+        analysis.instrument.name,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        analysis.summary.lowest_price
+    );
 
-    averages
+    Ok(analysis)
 }
 
-/// Calculate RSI indicator
-#[allow(dead_code)]
-pub fn calculate_rsi(data: &[StockData], period: usize) -> Vec<f64> {
-    let mut rsi_values = Vec::new();
-
-    if data.len() < period + 1 {
-        return rsi_values;
-    }
-
-    let mut gains = Vec::new();
-    let mut losses = Vec::new();
-
-    // Calculate price changes
-    for i in 1..data.len() {
-        let change = data[i].close - data[i - 1].close;
-        if change > 0.0 {
-            gains.push(change);
-            losses.push(0.0);
-        } else {
-            gains.push(0.0);
-            losses.push(change.abs());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators;
+    use crate::models::{Instrument, market_close_utc};
+    use chrono::{Days, NaiveDate};
+
+    fn bar(day: u32, high: f64, low: f64, close: f64, volume: u64) -> StockData {
+        StockData {
+            date: market_close_utc(NaiveDate::from_ymd_opt(2025, 9, day).unwrap()),
+            open: close,
+            high,
+            low,
+            close,
+            volume,
+            amount: None,
         }
     }
 
-    // Calculate RSI
-    for i in period - 1..gains.len() {
-        let avg_gain = gains[i - period + 1..=i].iter().sum::<f64>() / period as f64;
-        let avg_loss = losses[i - period + 1..=i].iter().sum::<f64>() / period as f64;
-
-        if avg_loss == 0.0 {
-            rsi_values.push(100.0);
-        } else {
-            let rs = avg_gain / avg_loss;
-            let rsi = 100.0 - (100.0 / (1.0 + rs));
-            rsi_values.push(rsi);
-        }
+    #[test]
+    fn summarizes_a_period() {
+        let market_data = MarketData {
+            instrument: Instrument::csi300(),
+            source: "Test".to_string(),
+            is_mock: false,
+            bars: vec![
+                bar(22, 101.0, 99.0, 100.0, 1_000),
+                bar(23, 106.0, 100.0, 105.0, 3_000),
+                bar(24, 106.0, 95.0, 96.0, 2_000),
+                bar(25, 103.0, 95.0, 102.0, 2_000),
+            ],
+            validation: Default::default(),
+        };
+
+        let summary = summarize_period(&market_data, None, None).unwrap();
+
+        assert_eq!(summary.start_price, 100.0);
+        assert_eq!(summary.end_price, 102.0);
+        assert!((summary.change_pct - 2.0).abs() < 1e-12);
+        // Ties keep the earliest bar
+        assert_eq!(summary.highest_price, 106.0);
+        assert_eq!(summary.highest_date, market_data.bars[1].date);
+        assert_eq!(summary.lowest_price, 95.0);
+        assert_eq!(summary.lowest_date, market_data.bars[2].date);
+        assert_eq!(summary.total_volume, 8_000);
+        assert_eq!(summary.average_volume, 2_000.0);
+        assert!(summary.risk.is_none());
+        assert!(summary.valuation.is_none());
     }
 
-    rsi_values
-}
-
-/// Calculate MACD indicator
-#[allow(dead_code)]
-pub fn calculate_macd(
-    data: &[StockData],
-    fast_period: usize,
-    slow_period: usize,
-    signal_period: usize,
-) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
-    let fast_ema = calculate_ema(
-        &data.iter().map(|d| d.close).collect::<Vec<_>>(),
-        fast_period,
-    );
-    let slow_ema = calculate_ema(
-        &data.iter().map(|d| d.close).collect::<Vec<_>>(),
-        slow_period,
-    );
-
-    let mut macd_line = Vec::new();
-    for i in 0..fast_ema.len().min(slow_ema.len()) {
-        macd_line.push(fast_ema[i] - slow_ema[i]);
+    #[test]
+    fn rejects_an_empty_period() {
+        let market_data = MarketData {
+            instrument: Instrument::csi300(),
+            source: "Test".to_string(),
+            is_mock: false,
+            bars: Vec::new(),
+            validation: Default::default(),
+        };
+        assert!(summarize_period(&market_data, None, None).is_err());
     }
 
-    let signal_line = calculate_ema(&macd_line, signal_period);
-
-    let mut histogram = Vec::new();
-    for i in 0..macd_line.len().min(signal_line.len()) {
-        histogram.push(macd_line[i] - signal_line[i]);
+    /// Daily bars with a high and low 1% around each close
+    fn closes(closes: impl IntoIterator<Item = f64>) -> Vec<StockData> {
+        let first = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        closes
+            .into_iter()
+            .enumerate()
+            .map(|(i, close)| StockData {
+                date: market_close_utc(first + Days::new(i as u64)),
+                open: close,
+                high: close * 1.01,
+                low: close * 0.99,
+                close,
+                volume: 1_000,
+                amount: None,
+            })
+            .collect()
     }
 
-    (macd_line, signal_line, histogram)
-}
-
-/// Calculate EMA
-#[allow(dead_code)]
-fn calculate_ema(data: &[f64], period: usize) -> Vec<f64> {
-    let mut ema = Vec::new();
-    let multiplier = 2.0 / (period as f64 + 1.0);
+    /// A wave that keeps every indicator away from its extremes
+    fn wave(count: usize) -> Vec<StockData> {
+        closes((0..count).map(|i| 100.0 + 10.0 * (i as f64 / 9.0).sin() + i as f64 * 0.05))
+    }
 
-    if data.is_empty() {
-        return ema;
+    fn assert_near(actual: Option<f64>, expected: Option<f64>) {
+        let (actual, expected) = (actual.unwrap(), expected.unwrap());
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
     }
 
-    // 第一个EMA值等于第一个价格
-    ema.push(data[0]);
+    #[test]
+    fn snapshots_only_indicators_with_enough_history() {
+        let snapshot = indicator_snapshot(&wave(30));
+
+        let periods: Vec<usize> = snapshot
+            .moving_averages
+            .iter()
+            .map(|ma| ma.period)
+            .collect();
+        assert_eq!(periods, [5, 10, 20]);
+        assert!(snapshot.rsi.is_some());
+        assert!(snapshot.bollinger_percent_b.is_some());
+        assert!(snapshot.kdj.is_some());
+        // MACD(12, 26, 9) needs 34 bars
+        assert!(snapshot.macd.is_none());
+        assert!(snapshot.macd_cross.is_none());
+        assert!(snapshot.to_string().contains("MACD(12,26,9): n/a"));
+        assert!(indicator_snapshot(&wave(34)).macd.is_some());
+
+        let empty = indicator_snapshot(&[]);
+        assert!(empty.moving_averages.is_empty());
+        assert!(empty.rsi.is_none());
+    }
 
-    for i in 1..data.len() {
-        let ema_value = (data[i] * multiplier) + (ema[i - 1] * (1.0 - multiplier));
-        ema.push(ema_value);
+    #[test]
+    fn snapshot_matches_the_batch_indicators() {
+        let bars = wave(300);
+        let last = |series: indicators::Series<f64>| series.values().last().flatten();
+
+        let snapshot = indicator_snapshot(&bars);
+
+        assert_eq!(snapshot.moving_averages.len(), SNAPSHOT_MA_PERIODS.len());
+        for ma in &snapshot.moving_averages {
+            let value = last(indicators::sma(&bars, ma.period));
+            assert_near(Some(ma.value), value);
+            let close = bars[bars.len() - 1].close;
+            assert_near(
+                Some(ma.distance_pct),
+                Some((close - ma.value) / ma.value * 100.0),
+            );
+        }
+        assert_near(
+            snapshot.rsi,
+            last(indicators::rsi(&bars, 14, RsiMethod::Wilder(Seed::Sma))),
+        );
+        let macd = indicators::macd(&bars, 12, 26, 9, Seed::Sma);
+        assert_near(
+            snapshot.macd.map(|macd| macd.histogram),
+            macd.values().last().flatten().map(|macd| macd.histogram),
+        );
+        let bollinger = indicators::bollinger(&bars, 20, 2.0);
+        assert_near(
+            snapshot.bollinger_percent_b,
+            bollinger
+                .values()
+                .last()
+                .flatten()
+                .map(|bands| bands.percent_b),
+        );
+        let kdj = indicators::kdj(&bars, 9, 3, 3);
+        assert_near(
+            snapshot.kdj.map(|kdj| kdj.j),
+            kdj.values().last().flatten().map(|kdj| kdj.j),
+        );
     }
 
-    ema
+    #[test]
+    fn classifies_macd_crossovers() {
+        // A quickening slide then a recovery, which turns the histogram positive
+        let bars = closes((0..80).map(|i| match i {
+            0..50 => 100.0 - (i * i) as f64 * 0.01,
+            _ => 75.0 + (i - 50) as f64 * 2.0,
+        }));
+        let histogram: Vec<Option<f64>> = indicators::macd(&bars, 12, 26, 9, Seed::Sma)
+            .values()
+            .map(|macd| Some(macd?.histogram))
+            .collect();
+        let cross = (1..bars.len())
+            .find(|&i| {
+                matches!((histogram[i - 1], histogram[i]), (Some(before), Some(after)) if before < 0.0 && after > 0.0)
+            })
+            .unwrap();
+
+        let cross_at = |end: usize| indicator_snapshot(&bars[..end]).macd_cross;
+        assert_eq!(cross_at(cross), Some(MacdCross::Below));
+        assert_eq!(cross_at(cross + 1), Some(MacdCross::Bullish));
+        assert_eq!(cross_at(cross + 2), Some(MacdCross::Above));
+
+        // And the mirror image turns it negative
+        let mirrored = closes(bars.iter().map(|bar| 200.0 - bar.close));
+        assert_eq!(
+            indicator_snapshot(&mirrored[..cross + 1]).macd_cross,
+            Some(MacdCross::Bearish)
+        );
+    }
 }
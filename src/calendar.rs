@@ -0,0 +1,522 @@
+use anyhow::{Context, Result, anyhow};
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing::{info, warn};
+
+/// SSE/SZSE closures, one entry per date or inclusive `start..end` range
+///
+/// Weekends inside the ranges are included for readability; the exchanges
+/// never trade on weekends anyway.
+const BUNDLED_HOLIDAYS: &[(i32, &[&str])] = &[
+    (
+        2024,
+        &[
+            "2024-01-01",
+            "2024-02-09..2024-02-17",
+            "2024-04-04..2024-04-06",
+            "2024-05-01..2024-05-05",
+            "2024-06-10",
+            "2024-09-15..2024-09-17",
+            "2024-10-01..2024-10-07",
+        ],
+    ),
+    (
+        2025,
+        &[
+            "2025-01-01",
+            "2025-01-28..2025-02-04",
+            "2025-04-04..2025-04-06",
+            "2025-05-01..2025-05-05",
+            "2025-05-31..2025-06-02",
+            "2025-10-01..2025-10-08",
+        ],
+    ),
+    (
+        2026,
+        &[
+            "2026-01-01..2026-01-03",
+            "2026-02-15..2026-02-23",
+            "2026-04-04..2026-04-06",
+            "2026-05-01..2026-05-05",
+            "2026-06-19..2026-06-21",
+            "2026-09-25..2026-09-27",
+            "2026-10-01..2026-10-07",
+        ],
+    ),
+];
+
+/// Weekend make-up workdays (调休). Offices work on these days but the
+/// exchanges stay closed, so they are not trading days.
+const BUNDLED_MAKEUP_WORKDAYS: &[&str] = &[
+    "2024-02-04",
+    "2024-02-18",
+    "2024-04-07",
+    "2024-04-28",
+    "2024-05-11",
+    "2024-09-14",
+    "2024-09-29",
+    "2024-10-12",
+    "2025-01-26",
+    "2025-02-08",
+    "2025-04-27",
+    "2025-09-28",
+    "2025-10-11",
+    "2026-01-04",
+    "2026-02-14",
+    "2026-02-28",
+    "2026-05-09",
+    "2026-09-20",
+    "2026-10-10",
+];
+
+/// Holiday table file format, see `TRADING_CALENDAR_FILE`
+#[derive(Debug, Deserialize)]
+struct CalendarFile {
+    /// Years the file fully describes
+    years: Vec<i32>,
+    #[serde(default)]
+    holidays: Vec<String>,
+    #[serde(default)]
+    makeup_workdays: Vec<String>,
+}
+
+/// Calendar period for first, last and nth trading day queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Period {
+    /// First and last calendar dates of the period containing `date`
+    pub fn bounds(self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        let (first_month, months) = match self {
+            Period::Week => {
+                let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+                return (monday, monday + Duration::days(6));
+            }
+            Period::Month => (date.month(), 1),
+            Period::Quarter => (date.month0() / 3 * 3 + 1, 3),
+            Period::Year => (1, 12),
+        };
+
+        let start = NaiveDate::from_ymd_opt(date.year(), first_month, 1).unwrap();
+        let end = start + Months::new(months) - Duration::days(1);
+        (start, end)
+    }
+}
+
+/// Quarter (1-4) of a date
+pub fn quarter_of(date: NaiveDate) -> u32 {
+    date.month0() / 3 + 1
+}
+
+/// A-share trading calendar (SSE/SZSE)
+///
+/// A trading day is a weekday that is not an exchange holiday. Years without
+/// a holiday table fall back to a weekday-only calendar.
+#[derive(Debug, Clone, Default)]
+pub struct TradingCalendar {
+    holidays: BTreeSet<NaiveDate>,
+    makeup_workdays: BTreeSet<NaiveDate>,
+    covered_years: BTreeSet<i32>,
+}
+
+impl TradingCalendar {
+    /// Calendar with the bundled SSE holiday tables
+    pub fn bundled() -> Self {
+        let mut calendar = Self::default();
+
+        for (year, entries) in BUNDLED_HOLIDAYS {
+            calendar.covered_years.insert(*year);
+            for entry in *entries {
+                let dates = parse_entry(entry).expect("bundled holiday table is valid");
+                calendar.holidays.extend(dates);
+            }
+        }
+        for entry in BUNDLED_MAKEUP_WORKDAYS {
+            let dates = parse_entry(entry).expect("bundled make-up workdays are valid");
+            calendar.makeup_workdays.extend(dates);
+        }
+
+        calendar
+    }
+
+    /// Merge a JSON holiday table into the calendar
+    ///
+    /// The file replaces the bundled table for every year it lists:
+    ///
+    /// ```json
+    /// {
+    ///   "years": [2027],
+    ///   "holidays": ["2027-01-01", "2027-02-05..2027-02-13"],
+    ///   "makeup_workdays": ["2027-02-20"]
+    /// }
+    /// ```
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read trading calendar {}", path.display()))?;
+        let file: CalendarFile = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse trading calendar {}", path.display()))?;
+
+        let years: BTreeSet<i32> = file.years.iter().copied().collect();
+        self.holidays.retain(|d| !years.contains(&d.year()));
+        self.makeup_workdays.retain(|d| !years.contains(&d.year()));
+
+        for entry in &file.holidays {
+            self.holidays.extend(parse_entry(entry)?);
+        }
+        for entry in &file.makeup_workdays {
+            self.makeup_workdays.extend(parse_entry(entry)?);
+        }
+        self.covered_years.extend(years);
+
+        Ok(())
+    }
+
+    /// Process-wide calendar: the bundled tables plus `TRADING_CALENDAR_FILE`
+    pub fn global() -> &'static TradingCalendar {
+        static CALENDAR: OnceLock<TradingCalendar> = OnceLock::new();
+
+        CALENDAR.get_or_init(|| {
+            let mut calendar = Self::bundled();
+
+            if let Ok(path) = std::env::var("TRADING_CALENDAR_FILE")
+                && !path.trim().is_empty()
+            {
+                match calendar.load_file(Path::new(path.trim())) {
+                    Ok(()) => info!("Loaded trading calendar from {}", path.trim()),
+                    Err(e) => warn!("Ignoring trading calendar file: {:#}", e),
+                }
+            }
+
+            calendar
+        })
+    }
+
+    /// Whether the calendar has a holiday table for the year
+    pub fn covers(&self, year: i32) -> bool {
+        self.covered_years.contains(&year)
+    }
+
+    /// Whether the exchanges are closed for a holiday on this date
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.holidays.contains(&date)
+    }
+
+    /// Whether this is a weekend make-up workday (调休), on which the
+    /// exchanges stay closed
+    pub fn is_makeup_workday(&self, date: NaiveDate) -> bool {
+        self.makeup_workdays.contains(&date)
+    }
+
+    /// Whether the exchanges are open on this date
+    ///
+    /// Closed on weekends, holidays and make-up workdays, even though offices
+    /// work on the latter.
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        if !self.covers(date.year()) {
+            warn_uncovered_year(date.year());
+        }
+
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+            && !self.is_holiday(date)
+            && !self.is_makeup_workday(date)
+    }
+
+    /// Last trading day strictly before `date`
+    pub fn previous_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut previous = date - Duration::days(1);
+        while !self.is_trading_day(previous) {
+            previous -= Duration::days(1);
+        }
+        previous
+    }
+
+    /// `date` itself when it is a trading day, otherwise the trading day before
+    pub fn latest_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        if self.is_trading_day(date) {
+            date
+        } else {
+            self.previous_trading_day(date)
+        }
+    }
+
+    /// First trading day of the period containing `date`
+    pub fn first_trading_day(&self, period: Period, date: NaiveDate) -> Option<NaiveDate> {
+        self.nth_trading_day(period, date, 1)
+    }
+
+    /// Last trading day of the period containing `date`
+    pub fn last_trading_day(&self, period: Period, date: NaiveDate) -> Option<NaiveDate> {
+        let (start, end) = period.bounds(date);
+        end.iter_days()
+            .rev()
+            .take_while(|day| *day >= start)
+            .find(|day| self.is_trading_day(*day))
+    }
+
+    /// The `n`th (1-based) trading day of the period containing `date`
+    ///
+    /// Returns `None` when the period has fewer than `n` trading days.
+    pub fn nth_trading_day(&self, period: Period, date: NaiveDate, n: usize) -> Option<NaiveDate> {
+        let (start, end) = period.bounds(date);
+        n.checked_sub(1).and_then(|skip| {
+            start
+                .iter_days()
+                .take_while(|day| *day <= end)
+                .filter(|day| self.is_trading_day(*day))
+                .nth(skip)
+        })
+    }
+
+    /// Whether `date` is the first trading day of its period
+    pub fn is_first_trading_day(&self, period: Period, date: NaiveDate) -> bool {
+        self.first_trading_day(period, date) == Some(date)
+    }
+
+    /// Whether `date` is the last trading day of its period
+    pub fn is_last_trading_day(&self, period: Period, date: NaiveDate) -> bool {
+        self.last_trading_day(period, date) == Some(date)
+    }
+}
+
+/// Warn once per year that only weekends are excluded for it
+fn warn_uncovered_year(year: i32) {
+    static WARNED: Mutex<BTreeSet<i32>> = Mutex::new(BTreeSet::new());
+
+    if let Ok(mut warned) = WARNED.lock()
+        && warned.insert(year)
+    {
+        warn!(
+            "No exchange holiday table for {}, only weekends are treated as closed. \
+            Set TRADING_CALENDAR_FILE to add one",
+            year
+        );
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date or an inclusive `YYYY-MM-DD..YYYY-MM-DD` range
+fn parse_entry(entry: &str) -> Result<Vec<NaiveDate>> {
+    let parse = |s: &str| {
+        NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+            .map_err(|e| anyhow!("Invalid calendar date '{}': {}", s.trim(), e))
+    };
+
+    let (start, end) = match entry.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => {
+            let date = parse(entry)?;
+            (date, date)
+        }
+    };
+
+    if end < start {
+        return Err(anyhow!(
+            "Invalid calendar range '{}': end before start",
+            entry
+        ));
+    }
+
+    Ok(start.iter_days().take_while(|d| *d <= end).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn closed_on_holidays_and_makeup_workdays() {
+        let calendar = TradingCalendar::bundled();
+        assert!(calendar.is_trading_day(date("2025-09-30")));
+        assert!(!calendar.is_trading_day(date("2025-10-01")));
+        assert!(!calendar.is_trading_day(date("2025-10-08")));
+        assert!(calendar.is_trading_day(date("2025-10-09")));
+
+        // Sunday make-up workday before National Day
+        assert!(calendar.is_makeup_workday(date("2025-09-28")));
+        assert!(!calendar.is_trading_day(date("2025-09-28")));
+    }
+
+    #[test]
+    fn previous_trading_day_skips_closures() {
+        let calendar = TradingCalendar::bundled();
+        assert_eq!(
+            calendar.previous_trading_day(date("2025-10-09")),
+            date("2025-09-30")
+        );
+        assert_eq!(
+            calendar.latest_trading_day(date("2025-10-12")),
+            date("2025-10-10")
+        );
+    }
+
+    #[test]
+    fn bounds_periods_by_calendar_dates() {
+        let day = date("2025-08-14");
+        assert_eq!(
+            Period::Week.bounds(day),
+            (date("2025-08-11"), date("2025-08-17"))
+        );
+        assert_eq!(
+            Period::Month.bounds(day),
+            (date("2025-08-01"), date("2025-08-31"))
+        );
+        assert_eq!(
+            Period::Quarter.bounds(day),
+            (date("2025-07-01"), date("2025-09-30"))
+        );
+        assert_eq!(
+            Period::Year.bounds(day),
+            (date("2025-01-01"), date("2025-12-31"))
+        );
+        assert_eq!(
+            Period::Month.bounds(date("2024-02-10")),
+            (date("2024-02-01"), date("2024-02-29"))
+        );
+        assert_eq!(quarter_of(date("2025-03-31")), 1);
+        assert_eq!(quarter_of(date("2025-10-01")), 4);
+    }
+
+    #[test]
+    fn finds_first_and_last_trading_days_around_holidays() {
+        let calendar = TradingCalendar::bundled();
+
+        // October 2025 opens after the National Day holiday
+        assert_eq!(
+            calendar.first_trading_day(Period::Month, date("2025-10-20")),
+            Some(date("2025-10-09"))
+        );
+        assert_eq!(
+            calendar.first_trading_day(Period::Quarter, date("2025-11-20")),
+            Some(date("2025-10-09"))
+        );
+        // and September closes on Tuesday the 30th, before it
+        assert_eq!(
+            calendar.last_trading_day(Period::Month, date("2025-09-01")),
+            Some(date("2025-09-30"))
+        );
+        // 2025-05-31 is a holiday Saturday; May closes on Friday the 30th
+        assert_eq!(
+            calendar.last_trading_day(Period::Month, date("2025-05-01")),
+            Some(date("2025-05-30"))
+        );
+        // The week of Spring Festival 2024 has no trading day, not even on
+        // its Sunday make-up workday
+        assert_eq!(
+            calendar.first_trading_day(Period::Week, date("2024-02-14")),
+            None
+        );
+        assert_eq!(
+            calendar.first_trading_day(Period::Week, date("2025-02-03")),
+            Some(date("2025-02-05"))
+        );
+        // The week of 2025-09-29 ends on Tuesday; Saturday 10-11 is a make-up
+        // workday but the exchanges stay closed
+        assert_eq!(
+            calendar.last_trading_day(Period::Week, date("2025-09-29")),
+            Some(date("2025-09-30"))
+        );
+        assert_eq!(
+            calendar.last_trading_day(Period::Week, date("2025-10-09")),
+            Some(date("2025-10-10"))
+        );
+    }
+
+    #[test]
+    fn counts_nth_trading_days_within_the_period() {
+        let calendar = TradingCalendar::bundled();
+        let october = date("2025-10-01");
+
+        assert_eq!(
+            calendar.nth_trading_day(Period::Month, october, 3),
+            Some(date("2025-10-13"))
+        );
+        // October 2025 has 17 trading days
+        assert_eq!(
+            calendar.nth_trading_day(Period::Month, october, 17),
+            Some(date("2025-10-31"))
+        );
+        assert_eq!(calendar.nth_trading_day(Period::Month, october, 18), None);
+        assert_eq!(calendar.nth_trading_day(Period::Month, october, 0), None);
+
+        assert!(calendar.is_first_trading_day(Period::Month, date("2025-10-09")));
+        assert!(!calendar.is_first_trading_day(Period::Month, date("2025-10-01")));
+        assert!(calendar.is_last_trading_day(Period::Quarter, date("2025-09-30")));
+        assert!(calendar.is_last_trading_day(Period::Year, date("2025-12-31")));
+        assert!(!calendar.is_last_trading_day(Period::Month, date("2025-10-30")));
+    }
+
+    #[test]
+    fn replaces_bundled_years_with_a_calendar_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calendar.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "years": [2025, 2027],
+                "holidays": ["2025-12-31", "2027-02-05..2027-02-09"],
+                "makeup_workdays": ["2027-02-20"]
+            }"#,
+        )
+        .unwrap();
+
+        let mut calendar = TradingCalendar::bundled();
+        assert!(!calendar.covers(2027));
+        calendar.load_file(&path).unwrap();
+
+        assert!(calendar.covers(2027));
+        // The file's 2025 table replaces the bundled one
+        assert!(calendar.is_trading_day(date("2025-10-01")));
+        assert!(!calendar.is_trading_day(date("2025-12-31")));
+        assert!(!calendar.is_makeup_workday(date("2025-09-28")));
+        // Other years keep the bundled tables
+        assert!(!calendar.is_trading_day(date("2024-10-01")));
+
+        assert!(calendar.is_holiday(date("2027-02-09")));
+        assert!(calendar.is_trading_day(date("2027-02-10")));
+        assert!(calendar.is_makeup_workday(date("2027-02-20")));
+    }
+
+    #[test]
+    fn rejects_invalid_calendar_entries() {
+        assert_eq!(parse_entry(" 2025-10-01 ").unwrap(), [date("2025-10-01")]);
+        assert_eq!(parse_entry("2025-10-07..2025-10-08").unwrap().len(), 2);
+        assert_eq!(
+            parse_entry("2025-10-08..2025-10-01")
+                .unwrap_err()
+                .to_string(),
+            "Invalid calendar range '2025-10-08..2025-10-01': end before start"
+        );
+        assert!(
+            parse_entry("2025-13-01")
+                .unwrap_err()
+                .to_string()
+                .starts_with("Invalid calendar date '2025-13-01'")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calendar.json");
+        std::fs::write(&path, r#"{"years": [2027], "holidays": ["2027-02-30"]}"#).unwrap();
+        let error = TradingCalendar::bundled().load_file(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("Invalid calendar date '2027-02-30'"));
+    }
+
+    #[test]
+    fn treats_uncovered_years_as_weekdays_only() {
+        let calendar = TradingCalendar::bundled();
+
+        assert!(!calendar.covers(2030));
+        assert!(calendar.is_trading_day(date("2030-01-01")));
+        assert!(!calendar.is_trading_day(date("2030-01-05")));
+    }
+}
@@ -0,0 +1,505 @@
+use crate::ledger::{self, RunLedger, RunRecord, RunStatus, RunTrigger};
+use crate::models::Instrument;
+use crate::report::{self, MODES, ReportOutcome, RunOptions};
+use crate::schedule::{Schedule, format_run};
+use crate::scheduler;
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::{Mutex, watch};
+use tracing::{error, info, warn};
+
+/// How far back missed runs are caught up when `CATCH_UP_WINDOW_HOURS` is
+/// not set
+const DEFAULT_CATCH_UP_WINDOW_HOURS: i64 = 24;
+
+/// A report mode run on a schedule by `serve`
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    pub mode: String,
+    pub schedule: Schedule,
+}
+
+/// Jobs run by `serve`
+///
+/// One job per report mode, on the mode's default schedule unless overridden
+/// with `DAILY_SCHEDULE`, `WEEKLY_SCHEDULE`, `MONTHLY_SCHEDULE` or
+/// `QUARTERLY_SCHEDULE`, followed by the jobs listed in `CUSTOM_JOBS`.
+pub fn configured_jobs() -> Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+
+    for mode in MODES {
+        let variable = format!("{}_SCHEDULE", mode.to_ascii_uppercase());
+        let schedule = match std::env::var(&variable) {
+            Ok(expression) if !expression.trim().is_empty() => expression
+                .parse()
+                .with_context(|| format!("Invalid {}", variable))?,
+            _ => Schedule::for_mode(mode)?,
+        };
+        jobs.push(Job {
+            name: mode.to_string(),
+            mode: mode.to_string(),
+            schedule,
+        });
+    }
+
+    if let Ok(custom) = std::env::var("CUSTOM_JOBS") {
+        for entry in custom.split(';').filter(|s| !s.trim().is_empty()) {
+            let job = parse_custom_job(entry)
+                .with_context(|| format!("Invalid CUSTOM_JOBS entry '{}'", entry.trim()))?;
+            if jobs.iter().any(|existing| existing.name == job.name) {
+                bail!("Duplicate job name '{}' in CUSTOM_JOBS", job.name);
+            }
+            jobs.push(job);
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// Parse a `name:mode:schedule` custom job
+fn parse_custom_job(entry: &str) -> Result<Job> {
+    let mut parts = entry.trim().splitn(3, ':');
+    let (Some(name), Some(mode), Some(schedule)) = (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("expected name:mode:schedule");
+    };
+
+    let mode = mode.trim();
+    if !MODES.contains(&mode) {
+        return Err(anyhow!(
+            "unknown mode '{}', expected one of {}",
+            mode,
+            MODES.join(", ")
+        ));
+    }
+
+    Ok(Job {
+        name: name.trim().to_string(),
+        mode: mode.to_string(),
+        schedule: schedule.parse()?,
+    })
+}
+
+/// Run a report mode as a job and record the run in the ledger
+///
+/// Returns the run's error after recording it.
+pub async fn run_job(
+    job: &str,
+    mode: &str,
+    scheduled_at: DateTime<Utc>,
+    trigger: RunTrigger,
+    instruments: &[Instrument],
+    options: &RunOptions,
+) -> Result<()> {
+    let started_at = Utc::now();
+    let result = report::run_mode(job, mode, instruments, options).await;
+
+    let mut record = RunRecord {
+        job: job.to_string(),
+        mode: mode.to_string(),
+        trigger,
+        scheduled_at,
+        started_at,
+        finished_at: Utc::now(),
+        status: RunStatus::Success,
+        data_source: None,
+        report_hash: None,
+        error: None,
+    };
+
+    match &result {
+        Ok(outcomes) => {
+            let mut sources: Vec<&str> = Vec::new();
+            let mut hasher = Sha256::new();
+            let mut any_report = false;
+
+            for outcome in outcomes {
+                match outcome {
+                    ReportOutcome::Generated {
+                        data_source,
+                        report,
+                    } => {
+                        if !sources.contains(&data_source.as_str()) {
+                            sources.push(data_source);
+                        }
+                        hasher.update(report.as_bytes());
+                        any_report = true;
+                    }
+                    ReportOutcome::DataUnavailable => record.status = RunStatus::DataUnavailable,
+                }
+            }
+
+            record.data_source = (!sources.is_empty()).then(|| sources.join(", "));
+            record.report_hash = any_report.then(|| format!("{:x}", hasher.finalize()));
+        }
+        Err(e) => {
+            record.status = RunStatus::Failed;
+            record.error = Some(format!("{:#}", e));
+        }
+    }
+
+    if let Err(e) = RunLedger::open().append(&record) {
+        warn!(job, "Failed to record run: {:#}", e);
+    }
+
+    result.map(|_| ())
+}
+
+/// Catch-up window from `CATCH_UP_WINDOW_HOURS` (default 24, 0 disables)
+fn catch_up_window() -> Duration {
+    let hours = std::env::var("CATCH_UP_WINDOW_HOURS")
+        .ok()
+        .and_then(|hours| hours.trim().parse::<i64>().ok())
+        .filter(|hours| *hours >= 0)
+        .unwrap_or(DEFAULT_CATCH_UP_WINDOW_HOURS);
+    Duration::hours(hours)
+}
+
+/// Latest scheduled run of a job within the catch-up window that the ledger
+/// has no record of
+///
+/// Reports always use the latest data, so only the most recent missed run is
+/// worth catching up; older ones are skipped.
+fn missed_run(
+    job: &Job,
+    records: &[RunRecord],
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Option<DateTime<Utc>> {
+    let mut latest = None;
+    let mut current = now - window;
+    while let Some(run) = job.schedule.next_after(current) {
+        if run > now {
+            break;
+        }
+        latest = Some(run);
+        current = run;
+    }
+
+    latest.filter(|run| !ledger::was_recorded(records, &job.name, *run))
+}
+
+/// Reports of all jobs, run one at a time in the order they were due
+///
+/// Serializing reports across jobs is deliberate: every report updates the
+/// bar cache, the delivery log, the alert state and the quota counters by
+/// reading and rewriting whole files, and two reports doing so at once would
+/// lose each other's updates. A long report therefore delays a job due
+/// while it runs; the delayed run still happens, under its scheduled time.
+#[derive(Clone, Default)]
+struct RunQueue {
+    lock: Arc<Mutex<()>>,
+}
+
+impl RunQueue {
+    /// Wait for the report in progress, if any, then run `report`
+    async fn run<F: Future>(&self, job: &str, report: F) -> F::Output {
+        if self.lock.try_lock().is_err() {
+            info!(job, "Another report is running, waiting for it to finish");
+        }
+        let _guard = self.lock.lock().await;
+        report.await
+    }
+}
+
+/// Run every job on its schedule until SIGTERM or Ctrl-C
+///
+/// Jobs are scheduled concurrently, but their reports run one at a time
+/// through a [`RunQueue`]. At startup, the latest run each job missed within
+/// the catch-up window is run first. A report in progress is finished before
+/// the process exits.
+pub async fn serve(
+    jobs: Vec<Job>,
+    instruments: Vec<Instrument>,
+    options: RunOptions,
+) -> Result<()> {
+    if jobs.is_empty() {
+        bail!("No jobs configured");
+    }
+
+    info!(
+        "Serving {} job(s) for {} instrument(s)",
+        jobs.len(),
+        instruments.len()
+    );
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let run_queue = RunQueue::default();
+    let instruments = Arc::new(instruments);
+
+    let records = RunLedger::open().load().unwrap_or_else(|e| {
+        warn!("Failed to read run history, not catching up: {:#}", e);
+        Vec::new()
+    });
+    let window = catch_up_window();
+    let now = Utc::now();
+
+    let mut tasks = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        let shutdown = shutdown_rx.clone();
+        let run_queue = run_queue.clone();
+        let instruments = Arc::clone(&instruments);
+        let missed = missed_run(&job, &records, now, window);
+
+        tasks.push(tokio::spawn(async move {
+            let execute = |scheduled_at: DateTime<Utc>, trigger: RunTrigger| {
+                let run_queue = run_queue.clone();
+                let instruments = Arc::clone(&instruments);
+                let job = &job;
+                async move {
+                    let result = run_queue
+                        .run(
+                            &job.name,
+                            run_job(
+                                &job.name,
+                                &job.mode,
+                                scheduled_at,
+                                trigger,
+                                &instruments,
+                                &options,
+                            ),
+                        )
+                        .await;
+                    match result {
+                        Ok(()) => info!(job = %job.name, "Job completed"),
+                        Err(e) => error!(job = %job.name, "Job failed: {:#}", e),
+                    }
+                }
+            };
+
+            if let Some(scheduled_at) = missed {
+                info!(
+                    job = %job.name,
+                    missed_run = %format_run(scheduled_at),
+                    "Catching up missed run"
+                );
+                execute(scheduled_at, RunTrigger::CatchUp).await;
+            }
+
+            scheduler::start_scheduler(&job.name, &job.schedule, shutdown, |scheduled_at| {
+                execute(scheduled_at, RunTrigger::Scheduled)
+            })
+            .await;
+        }));
+    }
+
+    wait_for_shutdown_signal().await?;
+    info!("Shutdown requested, waiting for running jobs to finish");
+    shutdown_tx.send(true).ok();
+
+    for task in tasks {
+        if let Err(e) = task.await {
+            warn!("Job task ended abnormally: {}", e);
+        }
+    }
+
+    info!("All jobs stopped");
+    Ok(())
+}
+
+/// Wait for SIGTERM or Ctrl-C (SIGINT)
+async fn wait_for_shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = sigterm.recv() => info!("Received SIGTERM"),
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                info!("Received Ctrl-C");
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        info!("Received Ctrl-C");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::market_datetime;
+    use chrono::NaiveDateTime;
+    use tokio::sync::oneshot;
+
+    fn at(local: &str) -> DateTime<Utc> {
+        let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M").unwrap();
+        market_datetime(local.date(), local.time())
+    }
+
+    fn job() -> Job {
+        Job {
+            name: "close".to_string(),
+            mode: "daily".to_string(),
+            schedule: "every trading day 15:30".parse().unwrap(),
+        }
+    }
+
+    fn ran(job: &str, local: &str) -> RunRecord {
+        let scheduled_at = at(local);
+        RunRecord {
+            job: job.to_string(),
+            mode: "daily".to_string(),
+            trigger: RunTrigger::Scheduled,
+            scheduled_at,
+            started_at: scheduled_at,
+            finished_at: scheduled_at,
+            status: RunStatus::Success,
+            data_source: None,
+            report_hash: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn has_nothing_to_catch_up_without_a_gap() {
+        let records = [ran("close", "2025-10-14 15:30")];
+
+        assert_eq!(
+            missed_run(
+                &job(),
+                &records,
+                at("2025-10-14 18:00"),
+                Duration::hours(24)
+            ),
+            None
+        );
+        // No run was due within the window
+        assert_eq!(
+            missed_run(&job(), &[], at("2025-10-14 12:00"), Duration::hours(2)),
+            None
+        );
+        assert_eq!(
+            missed_run(&job(), &[], at("2025-10-14 18:00"), Duration::zero()),
+            None
+        );
+    }
+
+    #[test]
+    fn catches_up_one_missed_run() {
+        assert_eq!(
+            missed_run(&job(), &[], at("2025-10-14 18:00"), Duration::hours(24)),
+            Some(at("2025-10-14 15:30"))
+        );
+        // A run due right now counts as missed
+        assert_eq!(
+            missed_run(&job(), &[], at("2025-10-14 15:30"), Duration::hours(1)),
+            Some(at("2025-10-14 15:30"))
+        );
+    }
+
+    #[test]
+    fn catches_up_only_the_latest_of_several_missed_runs() {
+        // Runs on the 14th to the 17th fall within the window
+        let records = [ran("close", "2025-10-13 15:30")];
+
+        assert_eq!(
+            missed_run(
+                &job(),
+                &records,
+                at("2025-10-17 18:00"),
+                Duration::hours(96)
+            ),
+            Some(at("2025-10-17 15:30"))
+        );
+    }
+
+    #[test]
+    fn skips_earlier_misses_once_the_latest_run_is_recorded() {
+        let records = [ran("close", "2025-10-17 15:30")];
+
+        assert_eq!(
+            missed_run(
+                &job(),
+                &records,
+                at("2025-10-17 18:00"),
+                Duration::hours(96)
+            ),
+            None
+        );
+        // Another job's run at the same time does not count
+        let records = [ran("other", "2025-10-17 15:30")];
+        assert_eq!(
+            missed_run(
+                &job(),
+                &records,
+                at("2025-10-17 18:00"),
+                Duration::hours(96)
+            ),
+            Some(at("2025-10-17 15:30"))
+        );
+    }
+
+    #[test]
+    fn misses_no_run_over_a_holiday() {
+        // 2025-10-01 to 10-08 is the National Day holiday
+        assert_eq!(
+            missed_run(&job(), &[], at("2025-10-08 18:00"), Duration::hours(72)),
+            None
+        );
+        assert_eq!(
+            missed_run(&job(), &[], at("2025-10-09 18:00"), Duration::hours(240)),
+            Some(at("2025-10-09 15:30"))
+        );
+    }
+
+    #[tokio::test]
+    async fn queues_a_report_behind_the_one_running() {
+        let queue = RunQueue::default();
+        let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let push =
+            |log: &Arc<std::sync::Mutex<Vec<&'static str>>>, entry| log.lock().unwrap().push(entry);
+
+        let (finish, finished) = oneshot::channel::<()>();
+        let quarterly = tokio::spawn({
+            let (queue, log) = (queue.clone(), Arc::clone(&log));
+            async move {
+                queue
+                    .run("quarterly", async {
+                        push(&log, "quarterly started");
+                        finished.await.ok();
+                        push(&log, "quarterly finished");
+                    })
+                    .await
+            }
+        });
+        while log.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let daily = tokio::spawn({
+            let (queue, log) = (queue.clone(), Arc::clone(&log));
+            async move { queue.run("daily", async { push(&log, "daily ran") }).await }
+        });
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(*log.lock().unwrap(), ["quarterly started"]);
+
+        finish.send(()).unwrap();
+        quarterly.await.unwrap();
+        daily.await.unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["quarterly started", "quarterly finished", "daily ran"]
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_a_report_right_away_when_idle() {
+        let queue = RunQueue::default();
+
+        assert_eq!(queue.run("daily", async { 1 }).await, 1);
+        assert_eq!(queue.run("weekly", async { 2 }).await, 2);
+    }
+}
@@ -1,78 +1,568 @@
-use crate::models::{ApiResponse, StockData};
+use crate::calendar::{Period, TradingCalendar};
+use crate::data_source::{DataSource, FetchRange};
+use crate::http::{self, HttpClient};
+use crate::mock_data::{self, MockConfig};
+use crate::models::{
+    AssetType, Exchange, Instrument, MarketData, StockData, TushareResponse, TushareTable,
+    market_close_utc, market_today,
+};
+use crate::rate_limit::RateLimiter;
+use crate::store::{BarStore, CachedSeries};
+use crate::validator::{self, ValidationConfig, ValidationReport};
+use crate::valuation::{self, ValuationPoint};
 use anyhow::{Result, anyhow};
-use chrono::{DateTime, Duration, Utc};
-use rand::{prelude::*, rng};
-use reqwest::Client;
+use async_trait::async_trait;
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc};
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
 use tracing::{debug, info, warn};
 
-const HS300_CODE: &str = "000300"; // CSI 300 Index code
+/// Default provider endpoints, overridable with the variables of the same name
 const TUSHARE_API_URL: &str = "https://api.tushare.pro";
 const ALPHA_VANTAGE_API_URL: &str = "https://www.alphavantage.co/query";
 
-/// Fetch real-time CSI 300 ETF data
-pub async fn fetch_hs300_data() -> Result<Vec<StockData>> {
-    info!("Starting to fetch CSI 300 ETF data");
+/// Years of history used by the daily analysis when `HISTORY_YEARS` is not set
+const DEFAULT_HISTORY_YEARS: i64 = 3;
 
-    // Try multiple data sources
-    match fetch_from_tushare().await {
-        Ok(data) => {
-            info!("Retrieved {} data points from TuShare", data.len());
-            Ok(data)
+/// Source name recorded when the whole range was served from the local store
+const CACHE_SOURCE_NAME: &str = "Local cache";
+
+/// Source name recorded when cached bars are served because providers failed
+const STALE_CACHE_SOURCE_NAME: &str = "Local cache (providers unavailable)";
+
+/// Data source chain used when `DATA_SOURCES` is not set
+const DEFAULT_DATA_SOURCES: &str = "tushare,alpha_vantage,mock";
+
+/// Alpha Vantage's `compact` output only covers the latest 100 trading days,
+/// so older ranges need the `full` output size
+const ALPHA_VANTAGE_COMPACT_DAYS: i64 = 140;
+
+/// Error returned when no enabled data source could serve an instrument
+///
+/// In strict mode mock sources are skipped, so this is also what a run sees
+/// when every real provider is down.
+#[derive(Debug)]
+pub struct DataUnavailable {
+    pub instrument: Instrument,
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for DataUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.errors.is_empty() {
+            return write!(f, "No configured data source supports {}", self.instrument);
         }
-        Err(e) => {
-            warn!("TuShare fetch failed: {}, trying backup data source", e);
-            match fetch_from_alpha_vantage().await {
-                Ok(data) => {
-                    info!("Retrieved {} data points from Alpha Vantage", data.len());
-                    Ok(data)
+        write!(
+            f,
+            "No data source could serve {}: {}",
+            self.instrument,
+            self.errors.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for DataUnavailable {}
+
+/// Fetch real-time data for an instrument, with `HISTORY_YEARS` of history
+///
+/// Mock sources in the chain are only used when `allow_mock` is set.
+pub async fn fetch_data(instrument: &Instrument, allow_mock: bool) -> Result<MarketData> {
+    let history_years = std::env::var("HISTORY_YEARS")
+        .ok()
+        .and_then(|years| years.trim().parse::<i64>().ok())
+        .filter(|years| *years > 0)
+        .unwrap_or(DEFAULT_HISTORY_YEARS);
+
+    let end_date = market_today();
+    let start_date = end_date - Duration::days(365 * history_years);
+    fetch_data_in_range(instrument, start_date, end_date, allow_mock).await
+}
+
+/// Fetch weekly data (from Monday of the current week up to today)
+pub async fn fetch_weekly_data(instrument: &Instrument, allow_mock: bool) -> Result<MarketData> {
+    let (start_date, end_date) = week_to_date(market_today());
+    fetch_data_in_range(instrument, start_date, end_date, allow_mock).await
+}
+
+/// Range from Monday of the week containing `today` up to `today`
+fn week_to_date(today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let days_since_monday = today.weekday().num_days_from_monday() as i64;
+    (today - Duration::days(days_since_monday), today)
+}
+
+/// Fetch monthly data (from the first trading day of the month up to the
+/// latest trading day)
+pub async fn fetch_monthly_data(instrument: &Instrument, allow_mock: bool) -> Result<MarketData> {
+    let (start_date, end_date) = period_to_date(Period::Month, market_today());
+    fetch_data_in_range(instrument, start_date, end_date, allow_mock).await
+}
+
+/// Fetch quarterly data (from the first trading day of the quarter up to the
+/// latest trading day)
+pub async fn fetch_quarterly_data(instrument: &Instrument, allow_mock: bool) -> Result<MarketData> {
+    let (start_date, end_date) = period_to_date(Period::Quarter, market_today());
+    fetch_data_in_range(instrument, start_date, end_date, allow_mock).await
+}
+
+/// Range from the first trading day of the period containing the latest
+/// trading day on or before `today` up to that day
+///
+/// Before the first trading day of a month (e.g. during the October holiday)
+/// this is the previous period, which still has the latest bars.
+fn period_to_date(period: Period, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let calendar = TradingCalendar::global();
+    let end_date = calendar.latest_trading_day(today);
+    let start_date = calendar
+        .first_trading_day(period, end_date)
+        .unwrap_or(end_date);
+    (start_date, end_date)
+}
+
+/// Fetch data within specified time range
+///
+/// Both bounds are inclusive trading dates in the market timezone. Bars already in
+/// the local store are reused and only the missing part of the range is
+/// requested. Sources from the configured chain are tried in order; whatever
+/// a source returns is validated and trimmed to the requested window; a
+/// source whose data is rejected by validation counts as failed. When every real
+/// source fails, cached bars are served before falling back to mock data.
+/// When `allow_mock` is false, mock sources are skipped and a
+/// [`DataUnavailable`] error is returned instead of fake data.
+async fn fetch_data_in_range(
+    instrument: &Instrument,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    allow_mock: bool,
+) -> Result<MarketData> {
+    info!(
+        "Starting to fetch {} data from {} to {}",
+        instrument, start_date, end_date
+    );
+
+    let sources = configured_sources()?;
+    let store = open_store();
+    fetch_from_sources(
+        &sources,
+        store.as_ref(),
+        instrument,
+        FetchRange::new(start_date, end_date),
+        latest_close(),
+        allow_mock,
+    )
+    .await
+}
+
+/// [`fetch_data_in_range`] over a given source chain and store, with bars
+/// published up to `latest_close`
+async fn fetch_from_sources(
+    sources: &[Box<dyn DataSource>],
+    store: Option<&BarStore>,
+    instrument: &Instrument,
+    range: FetchRange,
+    latest_close: NaiveDate,
+    allow_mock: bool,
+) -> Result<MarketData> {
+    let cached = match store {
+        Some(store) => store.load(instrument).unwrap_or_else(|e| {
+            warn!("Failed to load cached bars for {}: {}", instrument, e);
+            CachedSeries::default()
+        }),
+        None => CachedSeries::default(),
+    };
+    let cached_bars = cached.range(range);
+
+    let missing = cached.missing(range, latest_close);
+    if missing.is_empty() {
+        info!(
+            "Serving {} data points for {} from local cache",
+            cached_bars.len(),
+            instrument
+        );
+        return Ok(market_data(
+            instrument,
+            CACHE_SOURCE_NAME,
+            false,
+            cached_bars,
+            cached.validation(range),
+        ));
+    }
+
+    for gap in &missing {
+        debug!("Requesting {} to {} from providers", gap.start, gap.end);
+    }
+
+    let validation_config = ValidationConfig::from_env();
+    let mut errors = Vec::new();
+
+    for source in sources {
+        if !source.supports(instrument) {
+            debug!(
+                "{} does not support {}, skipping",
+                source.name(),
+                instrument
+            );
+            continue;
+        }
+
+        if source.is_mock() {
+            if !cached_bars.is_empty() {
+                break;
+            }
+            if !allow_mock {
+                info!("Strict mode: skipping mock source {}", source.name());
+                continue;
+            }
+        }
+
+        if !source.is_mock() && RateLimiter::for_provider(source.name()).is_exhausted() {
+            info!(
+                "{} daily quota used up, skipping to the next data source",
+                source.name()
+            );
+            errors.push(format!("{}: daily quota used up", source.name()));
+            continue;
+        }
+
+        // Mock data is never cached, so it always covers the whole range
+        let fetch_ranges = if source.is_mock() {
+            vec![range]
+        } else {
+            missing.clone()
+        };
+
+        let fetched = fetch_validated(
+            source.as_ref(),
+            instrument,
+            &fetch_ranges,
+            &cached,
+            &validation_config,
+        )
+        .await;
+
+        match fetched {
+            Ok(parts) => {
+                let mut validation = ValidationReport::default();
+                for part in &parts {
+                    validation.extend(part.validation.clone());
                 }
-                Err(e2) => {
-                    warn!("Alpha Vantage also failed: {}, using mock data", e2);
-                    Ok(generate_mock_data())
+                info!("{} data quality: {}", source.name(), validation);
+
+                let (bars, validation) = match (store, source.is_mock()) {
+                    (Some(store), false) => match save_parts(store, instrument, &parts) {
+                        Ok(merged) => (merged.range(range), merged.validation(range)),
+                        Err(e) => {
+                            warn!("Failed to cache bars for {}: {}", instrument, e);
+                            (merge_parts(cached_bars.clone(), parts, range), validation)
+                        }
+                    },
+                    (None, false) => (merge_parts(cached_bars.clone(), parts, range), validation),
+                    (_, true) => (merge_parts(Vec::new(), parts, range), validation),
+                };
+
+                if bars.is_empty() {
+                    warn!("{} returned no data in the requested range", source.name());
+                    errors.push(format!("{}: no data in range", source.name()));
+                    continue;
                 }
+
+                if source.is_mock() {
+                    warn!("Serving {} from mock data, prices are NOT real", instrument);
+                }
+
+                info!(
+                    "Retrieved {} data points from {}",
+                    bars.len(),
+                    source.name()
+                );
+                return Ok(market_data(
+                    instrument,
+                    source.name(),
+                    source.is_mock(),
+                    bars,
+                    validation,
+                ));
+            }
+            Err(e) => {
+                warn!(
+                    "{} fetch failed: {}, trying next data source",
+                    source.name(),
+                    e
+                );
+                errors.push(format!("{}: {}", source.name(), e));
             }
         }
     }
+
+    if !cached_bars.is_empty() {
+        warn!(
+            "All providers failed for {}, serving {} cached data points",
+            instrument,
+            cached_bars.len()
+        );
+        return Ok(market_data(
+            instrument,
+            STALE_CACHE_SOURCE_NAME,
+            false,
+            cached_bars,
+            cached.validation(range),
+        ));
+    }
+
+    Err(DataUnavailable {
+        instrument: instrument.clone(),
+        errors,
+    }
+    .into())
 }
 
-/// Fetch weekly data
-pub async fn fetch_weekly_hs300_data() -> Result<Vec<StockData>> {
-    let end_date = Utc::now();
-    let start_date = end_date - Duration::days(7);
-    fetch_hs300_data_in_range(start_date, end_date).await
+fn market_data(
+    instrument: &Instrument,
+    source: &str,
+    is_mock: bool,
+    bars: Vec<StockData>,
+    validation: ValidationReport,
+) -> MarketData {
+    MarketData {
+        instrument: instrument.clone(),
+        source: source.to_string(),
+        is_mock,
+        bars,
+        validation,
+    }
 }
 
-/// Fetch monthly data
-pub async fn fetch_monthly_hs300_data() -> Result<Vec<StockData>> {
-    let end_date = Utc::now();
-    let start_date = end_date - Duration::days(30);
-    fetch_hs300_data_in_range(start_date, end_date).await
+/// Open the local bar store, running without a cache when it is unavailable
+fn open_store() -> Option<BarStore> {
+    match BarStore::open() {
+        Ok(store) => Some(store),
+        Err(e) => {
+            warn!("Local bar store unavailable, fetching without cache: {}", e);
+            None
+        }
+    }
 }
 
-/// Fetch data within specified time range
-async fn fetch_hs300_data_in_range(
-    _start_date: DateTime<Utc>,
-    _end_date: DateTime<Utc>,
-) -> Result<Vec<StockData>> {
-    // Due to API limitations, return recent data for now
-    fetch_hs300_data().await
+/// Last trading day whose close has passed; later days cannot have bars yet
+fn latest_close() -> NaiveDate {
+    let calendar = TradingCalendar::global();
+    let today = market_today();
+    if calendar.is_trading_day(today) && Utc::now() >= market_close_utc(today) {
+        today
+    } else {
+        calendar.previous_trading_day(today)
+    }
+}
+
+/// Validated bars a source returned for one requested range
+struct FetchedPart {
+    range: FetchRange,
+    bars: Vec<StockData>,
+    validation: ValidationReport,
+}
+
+/// Fetch and validate each range from a source, failing if any range fails
+async fn fetch_validated(
+    source: &dyn DataSource,
+    instrument: &Instrument,
+    ranges: &[FetchRange],
+    cached: &CachedSeries,
+    config: &ValidationConfig,
+) -> Result<Vec<FetchedPart>> {
+    let mut parts = Vec::with_capacity(ranges.len());
+    for &range in ranges {
+        let data = source.fetch(instrument, range).await?;
+
+        // Check the seam to the cache too, not just the new bars
+        let first = data.iter().map(|d| d.trade_date()).min();
+        let previous = match (source.is_mock(), first) {
+            (false, Some(first)) => cached.last_before(first),
+            _ => None,
+        };
+        let (bars, validation) = validator::validate(data, previous, config)?;
+        parts.push(FetchedPart {
+            range,
+            bars,
+            validation,
+        });
+    }
+    Ok(parts)
+}
+
+/// Cache every fetched part, returning the merged series after the last one
+fn save_parts(
+    store: &BarStore,
+    instrument: &Instrument,
+    parts: &[FetchedPart],
+) -> Result<CachedSeries> {
+    let mut merged = CachedSeries::default();
+    for part in parts {
+        merged = store.save(instrument, &part.bars, part.range, &part.validation)?;
+    }
+    Ok(merged)
+}
+
+/// Merge fetched bars over cached ones without persisting them
+fn merge_parts(
+    cached: Vec<StockData>,
+    parts: Vec<FetchedPart>,
+    range: FetchRange,
+) -> Vec<StockData> {
+    let mut by_date: BTreeMap<_, _> = cached.into_iter().map(|d| (d.trade_date(), d)).collect();
+    for part in parts {
+        for bar in trim_to_range(part.bars, range) {
+            by_date.insert(bar.trade_date(), bar);
+        }
+    }
+    by_date.into_values().collect()
+}
+
+/// Build the data source chain from the `DATA_SOURCES` environment variable
+///
+/// `DATA_SOURCES` is a comma-separated, ordered list of source names
+/// (`tushare`, `alpha_vantage`, `mock`). Sources not listed are disabled.
+pub fn configured_sources() -> Result<Vec<Box<dyn DataSource>>> {
+    let names = match std::env::var("DATA_SOURCES") {
+        Ok(names) if !names.trim().is_empty() => names,
+        _ => DEFAULT_DATA_SOURCES.to_string(),
+    };
+
+    names
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| -> Result<Box<dyn DataSource>> {
+            match name.to_ascii_lowercase().as_str() {
+                "tushare" => Ok(Box::new(TuShareSource)),
+                "alpha_vantage" | "alphavantage" => Ok(Box::new(AlphaVantageSource)),
+                "mock" => Ok(Box::new(MockSource {
+                    config: MockConfig::from_env()?,
+                })),
+                other => Err(anyhow!(
+                    "Unknown data source '{}' in DATA_SOURCES. Supported: tushare, alpha_vantage, mock",
+                    other
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Keep only the bars whose date falls within the range
+fn trim_to_range(data: Vec<StockData>, range: FetchRange) -> Vec<StockData> {
+    let total = data.len();
+
+    let trimmed: Vec<StockData> = data.into_iter().filter(|d| range.contains(d)).collect();
+
+    if trimmed.len() != total {
+        debug!(
+            "Trimmed {} data points outside of {} to {}",
+            total - trimmed.len(),
+            range.start,
+            range.end
+        );
+    }
+
+    trimmed
+}
+
+/// TuShare Pro API, the primary source for A-share data
+struct TuShareSource;
+
+#[async_trait]
+impl DataSource for TuShareSource {
+    fn name(&self) -> &'static str {
+        "TuShare"
+    }
+
+    /// `index_daily`, `fund_daily` and `daily` cover every kind on both
+    /// exchanges
+    fn supports(&self, instrument: &Instrument) -> bool {
+        match (instrument.exchange, instrument.asset_type) {
+            (
+                Exchange::Shanghai | Exchange::Shenzhen,
+                AssetType::Index | AssetType::Etf | AssetType::Stock,
+            ) => true,
+        }
+    }
+
+    async fn fetch(&self, instrument: &Instrument, range: FetchRange) -> Result<Vec<StockData>> {
+        fetch_from_tushare(instrument, range.start, range.end).await
+    }
+}
+
+/// Alpha Vantage API, the backup source
+struct AlphaVantageSource;
+
+#[async_trait]
+impl DataSource for AlphaVantageSource {
+    fn name(&self) -> &'static str {
+        "Alpha Vantage"
+    }
+
+    /// Listed shares and ETFs on both exchanges; SSE and SZSE index codes are
+    /// not served and collide with share codes, e.g. `000300.SS`
+    fn supports(&self, instrument: &Instrument) -> bool {
+        match (instrument.exchange, instrument.asset_type) {
+            (Exchange::Shanghai | Exchange::Shenzhen, AssetType::Etf | AssetType::Stock) => true,
+            (Exchange::Shanghai | Exchange::Shenzhen, AssetType::Index) => false,
+        }
+    }
+
+    async fn fetch(&self, instrument: &Instrument, range: FetchRange) -> Result<Vec<StockData>> {
+        fetch_from_alpha_vantage(instrument, range.start, range.end).await
+    }
+}
+
+/// Generated bars, used when every real provider is unavailable and for
+/// reproducible offline runs (see `MOCK_*` in `mock_data`)
+struct MockSource {
+    config: MockConfig,
+}
+
+#[async_trait]
+impl DataSource for MockSource {
+    fn name(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn supports(&self, _instrument: &Instrument) -> bool {
+        true
+    }
+
+    fn is_mock(&self) -> bool {
+        true
+    }
+
+    async fn fetch(&self, _instrument: &Instrument, range: FetchRange) -> Result<Vec<StockData>> {
+        Ok(mock_data::generate(&self.config, range.start, range.end))
+    }
 }
 
 /// Fetch data from TuShare
-async fn fetch_from_tushare() -> Result<Vec<StockData>> {
+async fn fetch_from_tushare(
+    instrument: &Instrument,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Vec<StockData>> {
     let token = std::env::var("TUSHARE_TOKEN")
         .map_err(|_| anyhow!("TUSHARE_TOKEN environment variable not set"))?;
 
-    let client = Client::new();
+    let client = HttpClient::for_provider("TuShare")?;
+    let url = http::base_url("TUSHARE_API_URL", TUSHARE_API_URL);
+
+    let api_name = match instrument.asset_type {
+        AssetType::Index => "index_daily",
+        AssetType::Etf => "fund_daily",
+        AssetType::Stock => "daily",
+    };
 
     let payload = serde_json::json!({
-        "api_name": "index_daily",
+        "api_name": api_name,
         "token": token,
         "params": {
-            "ts_code": format!("{}.SH", HS300_CODE),
-            "start_date": "20240101",
-            "end_date": Utc::now().format("%Y%m%d").to_string()
+            "ts_code": instrument.tushare_code(),
+            "start_date": start_date.format("%Y%m%d").to_string(),
+            "end_date": end_date.format("%Y%m%d").to_string()
         }
     });
 
@@ -81,43 +571,203 @@ async fn fetch_from_tushare() -> Result<Vec<StockData>> {
         serde_json::to_string_pretty(&payload)?
     );
 
-    let response = client.post(TUSHARE_API_URL).json(&payload).send().await?;
+    let response = client
+        .send(|client| client.post(&url).json(&payload))
+        .await?;
 
     if !response.status().is_success() {
         return Err(anyhow!("TuShare API request failed: {}", response.status()));
     }
 
-    let api_response: ApiResponse = response.json().await?;
+    let api_response: TushareResponse = response.json().await?;
     debug!("TuShare response: {:?}", api_response);
 
-    if api_response.code != "0" {
+    decode_tushare_daily(api_response)
+}
+
+/// Decode a TuShare daily bar response (`index_daily`, `fund_daily`, `daily`)
+///
+/// Maps the `trade_date`, `open`, `high`, `low`, `close`, `vol` and `amount`
+/// columns into bars stamped at the Asia/Shanghai close, sorted ascending.
+/// Rows without a valid trade date are skipped.
+fn decode_tushare_daily(response: TushareResponse) -> Result<Vec<StockData>> {
+    if response.code != 0 {
+        return Err(anyhow!(
+            "TuShare API error {}: {}",
+            response.code,
+            response.msg.unwrap_or_default()
+        ));
+    }
+
+    let table = response
+        .data
+        .ok_or_else(|| anyhow!("TuShare response has no data table"))?;
+
+    let trade_date = tushare_column(&table, "trade_date")?;
+    let open = tushare_column(&table, "open")?;
+    let high = tushare_column(&table, "high")?;
+    let low = tushare_column(&table, "low")?;
+    let close = tushare_column(&table, "close")?;
+    let vol = tushare_column(&table, "vol")?;
+    let amount = tushare_column(&table, "amount").ok();
+
+    let mut data = Vec::with_capacity(table.items.len());
+
+    for row in &table.items {
+        let cell = |index: usize| row.get(index).and_then(Value::as_f64);
+
+        let Some(date) = row
+            .get(trade_date)
+            .and_then(Value::as_str)
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y%m%d").ok())
+        else {
+            warn!("Skipping TuShare row with invalid trade_date: {:?}", row);
+            continue;
+        };
+
+        // Missing prices are kept as NaN so validation can report them
+        let price = |index: usize| cell(index).unwrap_or(f64::NAN);
+
+        data.push(StockData {
+            date: market_close_utc(date),
+            open: price(open),
+            high: price(high),
+            low: price(low),
+            close: price(close),
+            volume: cell(vol).unwrap_or(0.0).round() as u64,
+            amount: amount.and_then(cell),
+        });
+    }
+
+    // TuShare returns the newest bar first
+    data.sort_by_key(|d| d.date);
+    Ok(data)
+}
+
+/// Index of a column in a TuShare table
+fn tushare_column(table: &TushareTable, name: &str) -> Result<usize> {
+    table
+        .fields
+        .iter()
+        .position(|field| field == name)
+        .ok_or_else(|| anyhow!("TuShare response is missing the '{}' column", name))
+}
+
+/// Fetch the TuShare `index_dailybasic` valuation history of an index
+///
+/// Covers the longest percentile window; sorted ascending by date.
+pub async fn fetch_valuation_history(instrument: &Instrument) -> Result<Vec<ValuationPoint>> {
+    let token = std::env::var("TUSHARE_TOKEN")
+        .map_err(|_| anyhow!("TUSHARE_TOKEN environment variable not set"))?;
+
+    let client = HttpClient::for_provider("TuShare")?;
+    let url = http::base_url("TUSHARE_API_URL", TUSHARE_API_URL);
+
+    let end_date = market_today();
+    let years = valuation::PERCENTILE_YEARS
+        .iter()
+        .max()
+        .copied()
+        .unwrap_or(10);
+    let start_date = end_date
+        .checked_sub_months(Months::new(years * 12))
+        .unwrap_or(end_date);
+
+    let payload = serde_json::json!({
+        "api_name": "index_dailybasic",
+        "token": token,
+        "params": {
+            "ts_code": instrument.tushare_code(),
+            "start_date": start_date.format("%Y%m%d").to_string(),
+            "end_date": end_date.format("%Y%m%d").to_string()
+        },
+        "fields": "ts_code,trade_date,pe,pe_ttm,pb,turnover_rate"
+    });
+
+    let response = client
+        .send(|client| client.post(&url).json(&payload))
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("TuShare API request failed: {}", response.status()));
+    }
+
+    let api_response: TushareResponse = response.json().await?;
+    debug!("TuShare valuation response: {:?}", api_response);
+
+    decode_tushare_valuation(api_response)
+}
+
+/// Decode a TuShare `index_dailybasic` response, sorted ascending
+fn decode_tushare_valuation(response: TushareResponse) -> Result<Vec<ValuationPoint>> {
+    if response.code != 0 {
         return Err(anyhow!(
-            "TuShare API error: {}",
-            api_response.message.unwrap_or_default()
+            "TuShare API error {}: {}",
+            response.code,
+            response.msg.unwrap_or_default()
         ));
     }
 
-    Ok(api_response.data)
+    let table = response
+        .data
+        .ok_or_else(|| anyhow!("TuShare response has no data table"))?;
+
+    let trade_date = tushare_column(&table, "trade_date")?;
+    let pe = tushare_column(&table, "pe")?;
+    let pe_ttm = tushare_column(&table, "pe_ttm")?;
+    let pb = tushare_column(&table, "pb")?;
+    let turnover_rate = tushare_column(&table, "turnover_rate").ok();
+
+    let mut points: Vec<ValuationPoint> = table
+        .items
+        .iter()
+        .filter_map(|row| {
+            let cell = |index: usize| row.get(index).and_then(Value::as_f64);
+            let date = row
+                .get(trade_date)
+                .and_then(Value::as_str)
+                .and_then(|s| NaiveDate::parse_from_str(s, "%Y%m%d").ok())?;
+            Some(ValuationPoint {
+                date,
+                pe: cell(pe),
+                pe_ttm: cell(pe_ttm),
+                pb: cell(pb),
+                turnover_rate: turnover_rate.and_then(cell),
+            })
+        })
+        .collect();
+
+    // TuShare returns the newest day first
+    points.sort_by_key(|point| point.date);
+    Ok(points)
 }
 
 /// Fetch data from Alpha Vantage
-async fn fetch_from_alpha_vantage() -> Result<Vec<StockData>> {
+///
+/// Unparsable prices are kept as NaN so validation can report and drop them.
+async fn fetch_from_alpha_vantage(
+    instrument: &Instrument,
+    start_date: NaiveDate,
+    _end_date: NaiveDate,
+) -> Result<Vec<StockData>> {
     let api_key = std::env::var("ALPHA_VANTAGE_API_KEY")
         .map_err(|_| anyhow!("ALPHA_VANTAGE_API_KEY environment variable not set"))?;
 
-    let client = Client::new();
+    let client = HttpClient::for_provider("Alpha Vantage")?;
+    let url = http::base_url("ALPHA_VANTAGE_API_URL", ALPHA_VANTAGE_API_URL);
+
+    let output_size = alpha_vantage_output_size(start_date, market_today());
 
+    let symbol = instrument.alpha_vantage_symbol();
     let params = [
         ("function", "TIME_SERIES_DAILY"),
-        ("symbol", "000300.SS"), // 沪深300在Alpha Vantage的代码
-        ("outputsize", "compact"),
+        ("symbol", symbol.as_str()),
+        ("outputsize", output_size),
         ("apikey", &api_key),
     ];
 
     let response = client
-        .get(ALPHA_VANTAGE_API_URL)
-        .query(&params)
-        .send()
+        .send(|client| client.get(&url).query(&params))
         .await?;
 
     if !response.status().is_success() {
@@ -140,31 +790,28 @@ async fn fetch_from_alpha_vantage() -> Result<Vec<StockData>> {
         if let Some(obj) = time_series.as_object() {
             for (date_str, values) in obj {
                 #[allow(clippy::collapsible_if)]
-                if let Ok(date) = DateTime::parse_from_str(
-                    &format!("{} 00:00:00 +0000", date_str),
-                    "%Y-%m-%d %H:%M:%S %z",
-                ) {
+                if let Ok(date) = NaiveDate::parse_from_str(date_str, "%Y-%m-%d") {
                     if let Some(day_data) = values.as_object() {
                         let open = day_data
                             .get("1. open")
                             .and_then(|v| v.as_str())
                             .and_then(|s| s.parse().ok())
-                            .unwrap_or(0.0);
+                            .unwrap_or(f64::NAN);
                         let high = day_data
                             .get("2. high")
                             .and_then(|v| v.as_str())
                             .and_then(|s| s.parse().ok())
-                            .unwrap_or(0.0);
+                            .unwrap_or(f64::NAN);
                         let low = day_data
                             .get("3. low")
                             .and_then(|v| v.as_str())
                             .and_then(|s| s.parse().ok())
-                            .unwrap_or(0.0);
+                            .unwrap_or(f64::NAN);
                         let close = day_data
                             .get("4. close")
                             .and_then(|v| v.as_str())
                             .and_then(|s| s.parse().ok())
-                            .unwrap_or(0.0);
+                            .unwrap_or(f64::NAN);
                         let volume = day_data
                             .get("5. volume")
                             .and_then(|v| v.as_str())
@@ -172,12 +819,13 @@ async fn fetch_from_alpha_vantage() -> Result<Vec<StockData>> {
                             .unwrap_or(0);
 
                         data.push(StockData {
-                            date: date.into(),
+                            date: market_close_utc(date),
                             open,
                             high,
                             low,
                             close,
                             volume,
+                            amount: None,
                         });
                     }
                 }
@@ -185,52 +833,580 @@ async fn fetch_from_alpha_vantage() -> Result<Vec<StockData>> {
         }
 
         // Sort by date
-        data.sort_by(|a, b| a.date.cmp(&b.date));
+        data.sort_by_key(|d| d.date);
         Ok(data)
     } else {
         Err(anyhow!("Alpha Vantage response format error"))
     }
 }
 
-/// Generate mock data (for testing or when all APIs are unavailable)
-fn generate_mock_data() -> Vec<StockData> {
-    let mut data = Vec::new();
-    let base_date = Utc::now() - Duration::days(30);
-    let mut current_price: f64 = 3500.0;
-    let mut rng = rng();
-
-    for i in 0..30 {
-        let date = base_date + Duration::days(i);
-        let change = (rng.random::<f64>() - 0.5) * 100.0; // Random change between -50 and +50
-        current_price += change;
-        current_price = current_price.clamp(3000.0, 4000.0); // Keep within reasonable range
-
-        let open: f64 = current_price + (rng.random::<f64>() - 0.5) * 20.0;
-        let high: f64 = open + rng.random::<f64>() * 50.0;
-        let low: f64 = open - rng.random::<f64>() * 50.0;
-        let close: f64 = current_price;
-
-        data.push(StockData {
-            date,
-            open: open.max(0.0),
-            high: high.max(0.0),
-            low: low.max(0.0),
-            close: close.max(0.0),
-            volume: (rng.random::<u64>() % 1000000) + 500000,
-        });
+/// Smallest Alpha Vantage output size that still covers `start_date`, as
+/// the API has no date filter
+fn alpha_vantage_output_size(start_date: NaiveDate, today: NaiveDate) -> &'static str {
+    if today - start_date > Duration::days(ALPHA_VANTAGE_COMPACT_DAYS) {
+        "full"
+    } else {
+        "compact"
     }
-
-    info!("Generated {} mock data points", data.len());
-    data
 }
 
-/// Get the latest stock price
+/// Get the latest price of an instrument
 #[allow(dead_code)]
-pub async fn get_current_price() -> Result<f64> {
-    let data = fetch_hs300_data().await?;
-    if let Some(latest) = data.last() {
+pub async fn get_current_price(instrument: &Instrument) -> Result<f64> {
+    let data = fetch_data(instrument, false).await?;
+    if let Some(latest) = data.bars.last() {
         Ok(latest.close)
     } else {
         Err(anyhow!("Unable to get current price"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use std::str::FromStr;
+
+    fn instrument(symbol: &str) -> Instrument {
+        Instrument::from_str(symbol).unwrap()
+    }
+
+    fn fixture(json: &str) -> TushareResponse {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn index_daily() -> TushareResponse {
+        fixture(include_str!("../tests/fixtures/tushare/index_daily.json"))
+    }
+
+    fn index_dailybasic() -> TushareResponse {
+        fixture(include_str!(
+            "../tests/fixtures/tushare/index_dailybasic.json"
+        ))
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    /// A source that serves canned bars or fails
+    struct Scripted {
+        name: &'static str,
+        mock: bool,
+        supported: bool,
+        bars: std::result::Result<Vec<StockData>, &'static str>,
+    }
+
+    impl Scripted {
+        fn serving(name: &'static str, bars: Vec<StockData>) -> Box<dyn DataSource> {
+            Box::new(Self {
+                name,
+                mock: false,
+                supported: true,
+                bars: Ok(bars),
+            })
+        }
+
+        fn failing(name: &'static str, error: &'static str) -> Box<dyn DataSource> {
+            Box::new(Self {
+                name,
+                mock: false,
+                supported: true,
+                bars: Err(error),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl DataSource for Scripted {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn supports(&self, _instrument: &Instrument) -> bool {
+            self.supported
+        }
+
+        fn is_mock(&self) -> bool {
+            self.mock
+        }
+
+        async fn fetch(
+            &self,
+            _instrument: &Instrument,
+            _range: FetchRange,
+        ) -> Result<Vec<StockData>> {
+            self.bars.clone().map_err(|e| anyhow!(e))
+        }
+    }
+
+    /// Bars closing at `close` on each date
+    fn bars(dates: &[&str], close: f64) -> Vec<StockData> {
+        dates
+            .iter()
+            .map(|d| StockData {
+                date: market_close_utc(date(d)),
+                open: close,
+                high: close + 1.0,
+                low: close - 1.0,
+                close,
+                volume: 1_000,
+                amount: None,
+            })
+            .collect()
+    }
+
+    /// The trading week of 2025-10-13
+    const WEEK: [&str; 5] = [
+        "2025-10-13",
+        "2025-10-14",
+        "2025-10-15",
+        "2025-10-16",
+        "2025-10-17",
+    ];
+
+    async fn fetch_week(sources: &[Box<dyn DataSource>], allow_mock: bool) -> Result<MarketData> {
+        fetch_from_sources(
+            sources,
+            None,
+            &Instrument::csi300(),
+            FetchRange::new(date("2025-10-13"), date("2025-10-17")),
+            date("2025-10-17"),
+            allow_mock,
+        )
+        .await
+    }
+
+    #[test]
+    fn decodes_daily_columns_by_name() {
+        let bars = decode_tushare_daily(index_daily()).unwrap();
+        assert_eq!(bars.len(), 3);
+
+        let last = &bars[2];
+        assert_eq!(last.open, 4622.9316);
+        assert_eq!(last.high, 4655.512);
+        assert_eq!(last.low, 4611.153);
+        assert_eq!(last.close, 4640.6873);
+        assert_eq!(last.volume, 219_843_012);
+        assert_eq!(last.amount, Some(517654321.123));
+    }
+
+    #[test]
+    fn stamps_daily_bars_at_the_shanghai_close_in_ascending_order() {
+        let bars = decode_tushare_daily(index_daily()).unwrap();
+        let dates: Vec<_> = bars.iter().map(|bar| bar.date).collect();
+        assert_eq!(
+            dates,
+            [
+                utc("2025-09-26T07:00:00Z"),
+                utc("2025-09-29T07:00:00Z"),
+                utc("2025-09-30T07:00:00Z"),
+            ]
+        );
+        assert_eq!(
+            bars[0].trade_date(),
+            NaiveDate::from_ymd_opt(2025, 9, 26).unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_api_errors_with_their_message() {
+        let error = decode_tushare_daily(fixture(include_str!(
+            "../tests/fixtures/tushare/error.json"
+        )))
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("TuShare API error 40203: "), "{}", error);
+        assert!(error.contains("每分钟最多访问该接口500次"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_a_missing_required_column() {
+        let mut response = index_daily();
+        let table = response.data.as_mut().unwrap();
+        let vol = tushare_column(table, "vol").unwrap();
+        table.fields.remove(vol);
+        for row in &mut table.items {
+            row.remove(vol);
+        }
+
+        let error = decode_tushare_daily(response).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "TuShare response is missing the 'vol' column"
+        );
+    }
+
+    #[test]
+    fn decodes_without_an_amount_column() {
+        let mut response = index_daily();
+        let table = response.data.as_mut().unwrap();
+        let amount = tushare_column(table, "amount").unwrap();
+        table.fields.remove(amount);
+        for row in &mut table.items {
+            row.remove(amount);
+        }
+
+        let bars = decode_tushare_daily(response).unwrap();
+        assert_eq!(bars.len(), 3);
+        assert!(bars.iter().all(|bar| bar.amount.is_none()));
+    }
+
+    #[test]
+    fn keeps_null_cells_for_validation() {
+        let bars = decode_tushare_daily(fixture(include_str!(
+            "../tests/fixtures/tushare/fund_daily_nulls.json"
+        )))
+        .unwrap();
+
+        // The row without a trade date is skipped
+        assert_eq!(bars.len(), 2);
+
+        let gap = &bars[0];
+        assert_eq!(
+            gap.trade_date(),
+            NaiveDate::from_ymd_opt(2025, 9, 29).unwrap()
+        );
+        assert!(gap.open.is_nan());
+        assert!(gap.close.is_nan());
+        assert_eq!(gap.high, 4.712);
+        assert_eq!(gap.volume, 0);
+        assert_eq!(gap.amount, Some(3795123.456));
+
+        let full = &bars[1];
+        assert_eq!(full.close, 4.727);
+        assert_eq!(full.volume, 8_123_456);
+        assert_eq!(full.amount, None);
+    }
+
+    #[test]
+    fn tushare_supports_every_kind() {
+        for symbol in [
+            "000300.SH",
+            "399006.SZ",
+            "510300.SH",
+            "159919.SZ",
+            "600519.SH",
+        ] {
+            assert!(TuShareSource.supports(&instrument(symbol)), "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn alpha_vantage_skips_indexes() {
+        assert!(!AlphaVantageSource.supports(&instrument("000300.SH")));
+        assert!(!AlphaVantageSource.supports(&instrument("399001.SZ")));
+        assert!(AlphaVantageSource.supports(&instrument("510300.SH")));
+        assert!(AlphaVantageSource.supports(&instrument("159915.SZ")));
+        assert!(AlphaVantageSource.supports(&instrument("600519.SH")));
+    }
+
+    #[test]
+    fn unavailable_without_supporting_source() {
+        let error = DataUnavailable {
+            instrument: instrument("000300.SH"),
+            errors: Vec::new(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "No configured data source supports CSI 300 Index (000300.SH)"
+        );
+    }
+
+    #[test]
+    fn decodes_valuation_in_ascending_order() {
+        let points = decode_tushare_valuation(index_dailybasic()).unwrap();
+
+        // The row without a trade date is dropped
+        let dates: Vec<String> = points.iter().map(|p| p.date.to_string()).collect();
+        assert_eq!(dates, ["2025-09-26", "2025-09-29", "2025-09-30"]);
+
+        let latest = points[2];
+        assert_eq!(latest.pe, Some(14.21));
+        assert_eq!(latest.pe_ttm, Some(13.87));
+        assert_eq!(latest.pb, Some(1.49));
+        assert_eq!(latest.turnover_rate, Some(0.6235));
+
+        // Null ratios stay missing rather than dropping the day
+        let oldest = points[0];
+        assert_eq!(oldest.pe, None);
+        assert_eq!(oldest.pe_ttm, Some(13.6));
+        assert_eq!(oldest.pb, Some(1.46));
+        assert_eq!(oldest.turnover_rate, None);
+    }
+
+    #[test]
+    fn decodes_valuation_columns_by_name() {
+        let mut response = index_dailybasic();
+        let table = response.data.as_mut().unwrap();
+        table.fields.reverse();
+        for row in &mut table.items {
+            row.reverse();
+        }
+
+        let points = decode_tushare_valuation(response).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[2].pe, Some(14.21));
+        assert_eq!(points[2].pe_ttm, Some(13.87));
+        assert_eq!(points[2].pb, Some(1.49));
+        assert_eq!(points[2].turnover_rate, Some(0.6235));
+    }
+
+    #[test]
+    fn valuation_requires_every_ratio() {
+        for column in ["trade_date", "pe", "pe_ttm", "pb"] {
+            let mut response = index_dailybasic();
+            let table = response.data.as_mut().unwrap();
+            let index = tushare_column(table, column).unwrap();
+            table.fields.remove(index);
+            for row in &mut table.items {
+                row.remove(index);
+            }
+
+            let error = decode_tushare_valuation(response).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("TuShare response is missing the '{}' column", column)
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_valuation_without_turnover() {
+        let mut response = index_dailybasic();
+        let table = response.data.as_mut().unwrap();
+        let index = tushare_column(table, "turnover_rate").unwrap();
+        table.fields.remove(index);
+        for row in &mut table.items {
+            row.remove(index);
+        }
+
+        let points = decode_tushare_valuation(response).unwrap();
+        assert_eq!(points.len(), 3);
+        assert!(points.iter().all(|point| point.turnover_rate.is_none()));
+        assert_eq!(points[2].pb, Some(1.49));
+    }
+
+    #[test]
+    fn reports_valuation_api_errors() {
+        let error = decode_tushare_valuation(fixture(include_str!(
+            "../tests/fixtures/tushare/error.json"
+        )))
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("TuShare API error 40203: "), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_next_source() {
+        let sources = [
+            Scripted::failing("Primary", "connection refused"),
+            Scripted::serving("Secondary", bars(&WEEK, 100.0)),
+            Scripted::serving("Tertiary", bars(&WEEK, 200.0)),
+        ];
+
+        let data = fetch_week(&sources, false).await.unwrap();
+
+        assert_eq!(data.source, "Secondary");
+        assert!(!data.is_mock);
+        assert_eq!(data.bars, bars(&WEEK, 100.0));
+    }
+
+    #[tokio::test]
+    async fn skips_sources_that_do_not_support_the_instrument() {
+        let sources = [
+            Box::new(Scripted {
+                name: "Unsupported",
+                mock: false,
+                supported: false,
+                bars: Ok(bars(&WEEK, 100.0)),
+            }) as Box<dyn DataSource>,
+            Scripted::serving("Supported", bars(&WEEK, 200.0)),
+        ];
+
+        assert_eq!(
+            fetch_week(&sources, false).await.unwrap().source,
+            "Supported"
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_empty_and_rejected_data_as_failed() {
+        let mut garbage = bars(&WEEK, 100.0);
+        for bar in &mut garbage[..3] {
+            bar.close = f64::NAN;
+        }
+        let sources = [
+            // Only bars outside the requested range
+            Scripted::serving("Stale", bars(&["2025-10-10"], 100.0)),
+            Scripted::serving("Garbage", garbage),
+        ];
+
+        let error = fetch_week(&sources, false).await.unwrap_err();
+
+        let unavailable = error.downcast_ref::<DataUnavailable>().unwrap();
+        assert_eq!(
+            unavailable.errors,
+            [
+                "Stale: no data in range",
+                "Garbage: Data rejected: 3 of 5 bars have invalid prices"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn trims_what_a_source_returns_to_the_range() {
+        let dates = [
+            "2025-10-10",
+            WEEK[0],
+            WEEK[1],
+            WEEK[2],
+            WEEK[3],
+            WEEK[4],
+            "2025-10-20",
+        ];
+        let sources = [Scripted::serving("Wide", bars(&dates, 100.0))];
+
+        let data = fetch_week(&sources, false).await.unwrap();
+
+        assert_eq!(data.bars, bars(&WEEK, 100.0));
+        assert_eq!(data.validation.input_bars, 7);
+    }
+
+    #[tokio::test]
+    async fn reports_every_failure_when_no_source_serves() {
+        let sources = [
+            Scripted::failing("Primary", "connection refused"),
+            Scripted::failing("Secondary", "HTTP 500"),
+        ];
+
+        let error = fetch_week(&sources, false).await.unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "No data source could serve CSI 300 Index (000300.SH): \
+             Primary: connection refused; Secondary: HTTP 500"
+        );
+        let error = fetch_week(&[], false).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No configured data source supports CSI 300 Index (000300.SH)"
+        );
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // Thursday, Monday and Sunday of the week of 2025-10-13
+        assert_eq!(
+            week_to_date(date("2025-10-16")),
+            (date("2025-10-13"), date("2025-10-16"))
+        );
+        assert_eq!(
+            week_to_date(date("2025-10-13")),
+            (date("2025-10-13"), date("2025-10-13"))
+        );
+        assert_eq!(
+            week_to_date(date("2025-10-19")),
+            (date("2025-10-13"), date("2025-10-19"))
+        );
+    }
+
+    #[test]
+    fn trims_to_the_inclusive_range() {
+        let dates = ["2025-10-10", WEEK[0], WEEK[2], WEEK[4], "2025-10-20"];
+        let range = FetchRange::new(date("2025-10-13"), date("2025-10-17"));
+
+        let trimmed = trim_to_range(bars(&dates, 100.0), range);
+
+        assert_eq!(trimmed, bars(&[WEEK[0], WEEK[2], WEEK[4]], 100.0));
+        assert!(trim_to_range(bars(&["2025-10-10"], 100.0), range).is_empty());
+    }
+
+    #[test]
+    fn requests_alpha_vantage_full_output_only_for_old_ranges() {
+        let today = date("2025-10-17");
+
+        assert_eq!(
+            alpha_vantage_output_size(date("2025-10-13"), today),
+            "compact"
+        );
+        assert_eq!(
+            alpha_vantage_output_size(today - Duration::days(140), today),
+            "compact"
+        );
+        assert_eq!(
+            alpha_vantage_output_size(today - Duration::days(141), today),
+            "full"
+        );
+    }
+
+    #[test]
+    fn periods_run_from_their_first_trading_day_to_the_latest() {
+        let month = |today| period_to_date(Period::Month, date(today));
+        let quarter = |today| period_to_date(Period::Quarter, date(today));
+
+        // October trading starts after the National Day holiday
+        assert_eq!(
+            month("2025-10-16"),
+            (date("2025-10-09"), date("2025-10-16"))
+        );
+        assert_eq!(
+            month("2025-10-18"),
+            (date("2025-10-09"), date("2025-10-17"))
+        );
+        assert_eq!(
+            quarter("2025-10-16"),
+            (date("2025-10-09"), date("2025-10-16"))
+        );
+
+        // During the holiday the latest bars still belong to September
+        assert_eq!(
+            month("2025-10-05"),
+            (date("2025-09-01"), date("2025-09-30"))
+        );
+        assert_eq!(
+            quarter("2025-10-05"),
+            (date("2025-07-01"), date("2025-09-30"))
+        );
+    }
+
+    fn mock(bars: Vec<StockData>) -> Box<dyn DataSource> {
+        Box::new(Scripted {
+            name: "Mock",
+            mock: true,
+            supported: true,
+            bars: Ok(bars),
+        })
+    }
+
+    #[tokio::test]
+    async fn strict_mode_never_serves_mock_data() {
+        let sources = [
+            Scripted::failing("Primary", "connection refused"),
+            mock(bars(&WEEK, 100.0)),
+        ];
+
+        let error = fetch_week(&sources, false).await.unwrap_err();
+
+        let unavailable = error.downcast_ref::<DataUnavailable>().unwrap();
+        assert_eq!(unavailable.errors, ["Primary: connection refused"]);
+    }
+
+    #[tokio::test]
+    async fn flags_mock_data_when_allowed() {
+        let sources = [
+            Scripted::failing("Primary", "connection refused"),
+            mock(bars(&WEEK, 100.0)),
+        ];
+
+        let data = fetch_week(&sources, true).await.unwrap();
+
+        assert_eq!(data.source, "Mock");
+        assert!(data.is_mock);
+        assert_eq!(data.bars.len(), 5);
+    }
+}
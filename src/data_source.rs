@@ -0,0 +1,45 @@
+use crate::models::{Instrument, StockData};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+
+/// Inclusive range of trading dates to fetch, in the market timezone
+#[derive(Debug, Clone, Copy)]
+pub struct FetchRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl FetchRange {
+    pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+        Self { start, end }
+    }
+
+    /// Check whether a bar falls within the range
+    pub fn contains(&self, bar: &StockData) -> bool {
+        (self.start..=self.end).contains(&bar.trade_date())
+    }
+}
+
+/// A provider of daily OHLCV bars
+///
+/// Sources are tried in the order configured by `DATA_SOURCES` until one of
+/// them returns data for the requested range.
+#[async_trait]
+pub trait DataSource: Send + Sync {
+    /// Display name, also recorded in reports as the serving source
+    fn name(&self) -> &'static str;
+
+    /// Whether this source can serve bars for the given instrument
+    fn supports(&self, instrument: &Instrument) -> bool;
+
+    /// Whether this source fabricates data instead of reporting real prices
+    fn is_mock(&self) -> bool {
+        false
+    }
+
+    /// Fetch daily bars covering `range`, sorted by date
+    ///
+    /// Sources may return bars outside of the range; the caller trims them.
+    async fn fetch(&self, instrument: &Instrument, range: FetchRange) -> Result<Vec<StockData>>;
+}
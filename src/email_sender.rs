@@ -1,9 +1,156 @@
-use anyhow::{Result, anyhow};
+use crate::models::market_today;
+use crate::store::{data_dir, write_atomic};
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use lettre::message::MessageBuilder;
 use lettre::message::header::ContentType;
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use tracing::{info, warn};
 
+/// Deliveries older than this many days are forgotten
+const DELIVERY_RETENTION_DAYS: i64 = 90;
+
+/// Successful deliveries by delivery key, persisted in
+/// `$DATA_DIR/deliveries.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DeliveryLog {
+    deliveries: BTreeMap<String, Delivery>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Delivery {
+    trade_date: NaiveDate,
+    subject: String,
+    delivered_at: DateTime<Utc>,
+}
+
+impl DeliveryLog {
+    fn path() -> PathBuf {
+        data_dir().join("deliveries.json")
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        self.prune(market_today());
+        write_atomic(&Self::path(), &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Forget deliveries for trading dates more than
+    /// `DELIVERY_RETENTION_DAYS` before `today`, a market date like the
+    /// delivery keys
+    fn prune(&mut self, today: NaiveDate) {
+        let cutoff = today - Duration::days(DELIVERY_RETENTION_DAYS);
+        self.deliveries
+            .retain(|_, delivery| delivery.trade_date >= cutoff);
+    }
+}
+
+/// Recipients from `TO_EMAILS`
+fn configured_recipients() -> Result<Vec<String>> {
+    let to_emails = std::env::var("TO_EMAILS")
+        .map_err(|_| anyhow!("TO_EMAILS environment variable not set"))?;
+    let recipients: Vec<String> = to_emails
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    if recipients.is_empty() {
+        return Err(anyhow!("No recipient email addresses configured"));
+    }
+    Ok(recipients)
+}
+
+/// Message builder from the sender to every recipient
+fn addressed(from_email: &str, recipients: &[String]) -> Result<MessageBuilder> {
+    let mut builder = Message::builder().from(
+        from_email
+            .parse()
+            .map_err(|e| anyhow!("Invalid sender email format: {}", e))?,
+    );
+    for recipient in recipients {
+        builder = builder.to(recipient
+            .parse()
+            .map_err(|e| anyhow!("Invalid recipient email format '{}': {}", recipient, e))?);
+    }
+    Ok(builder)
+}
+
+/// Key identifying one delivery of a job's report for a trading date to a
+/// recipient list
+///
+/// Recipients are normalized so that reordering `TO_EMAILS` does not change
+/// the key, while adding a recipient does.
+fn delivery_key(job: &str, trade_date: NaiveDate, recipients: &[String]) -> String {
+    let mut recipients: Vec<String> = recipients.iter().map(|r| r.to_lowercase()).collect();
+    recipients.sort();
+    recipients.dedup();
+    format!("{}|{}|{}", job, trade_date, recipients.join(","))
+}
+
+/// Send an email unless the same job already delivered it for the trading
+/// date to the same recipients
+///
+/// `force` sends regardless. Returns whether the email was sent. Only
+/// successful sends are recorded, so a failed send is retried by the next
+/// run.
+pub async fn send_email_once(
+    job: &str,
+    trade_date: NaiveDate,
+    subject: &str,
+    body: &str,
+    force: bool,
+) -> Result<bool> {
+    let key = delivery_key(job, trade_date, &configured_recipients()?);
+    let mut log = DeliveryLog::load().unwrap_or_else(|e| {
+        warn!("Ignoring unreadable delivery log: {:#}", e);
+        DeliveryLog::default()
+    });
+
+    if let Some(delivery) = log.deliveries.get(&key) {
+        if !force {
+            info!(
+                "Skipping email already delivered at {} ({}), use --force to resend",
+                delivery.delivered_at, key
+            );
+            return Ok(false);
+        }
+        info!(
+            "Resending already delivered email ({}) because of --force",
+            key
+        );
+    }
+
+    send_email(subject, body).await?;
+
+    log.deliveries.insert(
+        key,
+        Delivery {
+            trade_date,
+            subject: subject.to_string(),
+            delivered_at: Utc::now(),
+        },
+    );
+    if let Err(e) = log.save() {
+        warn!("Failed to record email delivery: {:#}", e);
+    }
+
+    Ok(true)
+}
+
 /// Send email notification
 pub async fn send_email(subject: &str, body: &str) -> Result<()> {
     let smtp_server = std::env::var("SMTP_SERVER").unwrap_or_else(|_| "smtp.gmail.com".to_string());
@@ -13,27 +160,12 @@ pub async fn send_email(subject: &str, body: &str) -> Result<()> {
         .map_err(|_| anyhow!("SMTP_PASSWORD environment variable not set"))?;
     let from_email = std::env::var("FROM_EMAIL")
         .map_err(|_| anyhow!("FROM_EMAIL environment variable not set"))?;
-    let to_emails_str = std::env::var("TO_EMAILS")
-        .map_err(|_| anyhow!("TO_EMAILS environment variable not set"))?;
-
-    let to_emails: Vec<&str> = to_emails_str.split(',').map(|s| s.trim()).collect();
-
-    if to_emails.is_empty() {
-        return Err(anyhow!("No recipient email addresses configured"));
-    }
+    let to_emails = configured_recipients()?;
 
     info!("Preparing to send email to {} recipients", to_emails.len());
 
     // Create email message
-    let email = Message::builder()
-        .from(
-            from_email
-                .parse()
-                .map_err(|e| anyhow!("Invalid sender email format: {}", e))?,
-        )
-        .to(to_emails[0]
-            .parse()
-            .map_err(|e| anyhow!("Invalid recipient email format: {}", e))?)
+    let email = addressed(&from_email, &to_emails)?
         .subject(subject)
         .header(ContentType::TEXT_PLAIN)
         .body(body.to_string())?;
@@ -68,14 +200,7 @@ pub async fn send_html_email(subject: &str, html_body: &str, text_body: &str) ->
         .map_err(|_| anyhow!("SMTP_PASSWORD environment variable not set"))?;
     let from_email = std::env::var("FROM_EMAIL")
         .map_err(|_| anyhow!("FROM_EMAIL environment variable not set"))?;
-    let to_emails_str = std::env::var("TO_EMAILS")
-        .map_err(|_| anyhow!("TO_EMAILS environment variable not set"))?;
-
-    let to_emails: Vec<&str> = to_emails_str.split(',').map(|s| s.trim()).collect();
-
-    if to_emails.is_empty() {
-        return Err(anyhow!("No recipient email addresses configured"));
-    }
+    let to_emails = configured_recipients()?;
 
     info!(
         "Preparing to send HTML email to {} recipients",
@@ -83,15 +208,7 @@ pub async fn send_html_email(subject: &str, html_body: &str, text_body: &str) ->
     );
 
     // Create multipart email (HTML + plain text)
-    let email = Message::builder()
-        .from(
-            from_email
-                .parse()
-                .map_err(|e| anyhow!("Invalid sender email format: {}", e))?,
-        )
-        .to(to_emails[0]
-            .parse()
-            .map_err(|e| anyhow!("Invalid recipient email format: {}", e))?)
+    let email = addressed(&from_email, &to_emails)?
         .subject(subject)
         .multipart(
             lettre::message::MultiPart::alternative()
@@ -202,3 +319,79 @@ pub async fn validate_email_config() -> Result<bool> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_every_recipient() {
+        let recipients = vec!["a@example.com".to_string(), "B@example.org".to_string()];
+        let email = addressed("reports@example.com", &recipients)
+            .unwrap()
+            .subject("Test")
+            .body("Body".to_string())
+            .unwrap();
+
+        let to: Vec<String> = email
+            .envelope()
+            .to()
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(to, ["a@example.com", "B@example.org"]);
+    }
+
+    #[test]
+    fn rejects_an_invalid_recipient() {
+        let recipients = vec!["a@example.com".to_string(), "not an address".to_string()];
+        let error = addressed("reports@example.com", &recipients).unwrap_err();
+        assert!(error.to_string().contains("'not an address'"), "{}", error);
+    }
+
+    #[test]
+    fn delivery_key_ignores_recipient_order_and_case() {
+        let date = NaiveDate::from_ymd_opt(2025, 9, 30).unwrap();
+        let first = delivery_key(
+            "daily",
+            date,
+            &["b@example.com".to_string(), "A@example.com".to_string()],
+        );
+        let second = delivery_key(
+            "daily",
+            date,
+            &["a@example.com".to_string(), "b@example.com".to_string()],
+        );
+        assert_eq!(first, second);
+        assert_eq!(first, "daily|2025-09-30|a@example.com,b@example.com");
+    }
+
+    #[test]
+    fn prunes_deliveries_by_market_date() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let delivery = |trade_date: &str| Delivery {
+            trade_date: date(trade_date),
+            subject: "Report".to_string(),
+            delivered_at: Utc::now(),
+        };
+
+        let mut log = DeliveryLog::default();
+        for trade_date in ["2025-07-01", "2025-07-02", "2025-09-30"] {
+            log.deliveries.insert(
+                format!("daily|{}|a@example.com", trade_date),
+                delivery(trade_date),
+            );
+        }
+
+        // 90 days before 2025-09-30 is 2025-07-02
+        log.prune(date("2025-09-30"));
+        let kept: Vec<&str> = log.deliveries.keys().map(|key| key.as_str()).collect();
+        assert_eq!(
+            kept,
+            [
+                "daily|2025-07-02|a@example.com",
+                "daily|2025-09-30|a@example.com"
+            ]
+        );
+    }
+}
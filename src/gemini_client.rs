@@ -1,9 +1,17 @@
-use crate::models::{DailyAnalysis, MonthlyAnalysis, WeeklyAnalysis};
+use crate::http::{self, HttpClient};
+use crate::models::{
+    DailyAnalysis, MonthlyAnalysis, QuarterlyAnalysis, WeeklyAnalysis, market_date,
+};
+use crate::risk::RiskMetrics;
+use crate::valuation::Valuation;
 use anyhow::Result;
 
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 
+/// Default Gemini endpoint, overridable with `GEMINI_API_URL`
+const GEMINI_API_URL: &str = "https://generativelanguage.googleapis.com";
+
 #[derive(Debug, Serialize, Deserialize)]
 struct GeminiRequest {
     contents: Vec<Content>,
@@ -29,49 +37,100 @@ struct Candidate {
     content: Content,
 }
 
+/// Prompt preamble for analyses built from mock data
+const MOCK_DATA_NOTICE: &str = "IMPORTANT: The data below is SIMULATED mock data, not real market data. \
+    State this clearly at the start of your response and do not present it as a real market view.\n\n";
+
+/// Prefix a prompt with the mock data notice when needed
+fn with_mock_notice(prompt: String, is_mock: bool) -> String {
+    if is_mock {
+        format!("{}{}", MOCK_DATA_NOTICE, prompt)
+    } else {
+        prompt
+    }
+}
+
 /// Generate daily analysis report
 pub async fn generate_daily_analysis(analysis: &DailyAnalysis) -> Result<String> {
+    let unit = analysis.instrument.price_unit();
     let prompt = format!(
-        "You are a professional stock analyst. Please analyze the following CSI 300 ETF data:\n\n\
+        "You are a professional stock analyst. Please analyze the following {} data:\n\n\
         Date: {}\n\
-        Current Price: {:.2} CNY\n\
+        Current Price: {:.2} {}\n\
         Price Change: {:.2}%\n\
         Relative to High: {:.2}%\n\
         Relative to Low: {:.2}%\n\
-        Historical High: {:.2} CNY\n\
-        Historical Low: {:.2} CNY\n\
+        Historical High: {:.2} {}\n\
+        Historical Low: {:.2} {}\n\
         Volume: {}\n\n\
+        Technical Indicators:\n{}\n\n\
+        {}{}\
         Please provide professional investment advice including:\n\
-        1. Market trend analysis\n\
+        1. Market trend analysis, referring to the technical indicators\n\
         2. Risk assessment\n\
         3. Investment recommendations\n\
         4. Key points to watch\n\n\
         Please respond in English, maintaining professionalism and objectivity.",
-        analysis.date.format("%Y-%m-%d"),
+        analysis.instrument,
+        market_date(analysis.date),
         analysis.current_price,
+        unit,
         analysis.price_change_pct,
         analysis.relative_to_high,
         analysis.relative_to_low,
         analysis.historical_high,
+        unit,
         analysis.historical_low,
-        analysis.volume
+        unit,
+        analysis.volume,
+        analysis.indicators,
+        valuation_section(analysis.valuation.as_ref()),
+        signals_section(&analysis.signals)
     );
 
-    generate_gemini_response(&prompt).await
+    generate_gemini_response(&with_mock_notice(prompt, analysis.is_mock)).await
+}
+
+/// Active signals block of the daily prompt, empty without signals
+fn signals_section(signals: &[String]) -> String {
+    if signals.is_empty() {
+        return String::new();
+    }
+    format!("Active Signals: {}\n\n", signals.join(", "))
+}
+
+/// Valuation block of a prompt, empty without valuation
+fn valuation_section(valuation: Option<&Valuation>) -> String {
+    valuation
+        .map(|valuation| {
+            format!(
+                "Valuation as of {} (percentiles rank the latest value within its own history):\n{}\n\n",
+                valuation.trade_date, valuation
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Risk metrics block of a period prompt, empty without metrics
+fn risk_section(risk: Option<&RiskMetrics>) -> String {
+    risk.map(|risk| format!("Risk Metrics:\n{}\n\n", risk))
+        .unwrap_or_default()
 }
 
 /// Generate weekly analysis report
 pub async fn generate_weekly_analysis(analysis: &WeeklyAnalysis) -> Result<String> {
+    let unit = analysis.instrument.price_unit();
     let prompt = format!(
-        "You are a professional stock analyst. Please analyze the following CSI 300 ETF weekly data:\n\n\
+        "You are a professional stock analyst. Please analyze the following {} weekly data:\n\n\
         Period: {} to {}\n\
-        Start Price: {:.2} CNY\n\
-        End Price: {:.2} CNY\n\
+        Start Price: {:.2} {}\n\
+        End Price: {:.2} {}\n\
         Weekly Change: {:.2}%\n\
-        Highest: {:.2} CNY ({})\n\
-        Lowest: {:.2} CNY ({})\n\
+        Highest: {:.2} {} ({})\n\
+        Lowest: {:.2} {} ({})\n\
         Average Volume: {:.0}\n\
         Total Volume: {}\n\n\
+        {}{}\
         Please analyze this week's market performance including:\n\
         1. Weekly trend analysis\n\
         2. Key price breakouts\n\
@@ -79,34 +138,43 @@ pub async fn generate_weekly_analysis(analysis: &WeeklyAnalysis) -> Result<Strin
         4. Next week outlook\n\
         5. Investment strategy recommendations\n\n\
         Please respond in English, maintaining professionalism and objectivity.",
-        analysis.start_date.format("%Y-%m-%d"),
-        analysis.end_date.format("%Y-%m-%d"),
-        analysis.start_price,
-        analysis.end_price,
-        analysis.weekly_change_pct,
-        analysis.highest_price,
-        analysis.highest_date.format("%Y-%m-%d"),
-        analysis.lowest_price,
-        analysis.lowest_date.format("%Y-%m-%d"),
-        analysis.average_volume,
-        analysis.total_volume
+        analysis.instrument,
+        market_date(analysis.summary.start_date),
+        market_date(analysis.summary.end_date),
+        analysis.summary.start_price,
+        unit,
+        analysis.summary.end_price,
+        unit,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        unit,
+        market_date(analysis.summary.highest_date),
+        analysis.summary.lowest_price,
+        unit,
+        market_date(analysis.summary.lowest_date),
+        analysis.summary.average_volume,
+        analysis.summary.total_volume,
+        risk_section(analysis.summary.risk.as_ref()),
+        valuation_section(analysis.summary.valuation.as_ref())
     );
 
-    generate_gemini_response(&prompt).await
+    generate_gemini_response(&with_mock_notice(prompt, analysis.is_mock)).await
 }
 
 /// Generate monthly analysis report
 pub async fn generate_monthly_analysis(analysis: &MonthlyAnalysis) -> Result<String> {
+    let unit = analysis.instrument.price_unit();
     let prompt = format!(
-        "You are a professional stock analyst. Please analyze the following CSI 300 ETF monthly data:\n\n\
-        Month: {}-{}\n\
-        Start Price: {:.2} CNY\n\
-        End Price: {:.2} CNY\n\
+        "You are a professional stock analyst. Please analyze the following {} monthly data:\n\n\
+        Month: {}-{}{}\n\
+        Start Price: {:.2} {}\n\
+        End Price: {:.2} {}\n\
         Monthly Change: {:.2}%\n\
-        Highest: {:.2} CNY ({})\n\
-        Lowest: {:.2} CNY ({})\n\
+        Highest: {:.2} {} ({})\n\
+        Lowest: {:.2} {} ({})\n\
         Average Volume: {:.0}\n\
         Total Volume: {}\n\n\
+        {}{}\
         Please analyze this month's market performance including:\n\
         1. Overall monthly trend\n\
         2. Important support and resistance levels\n\
@@ -114,20 +182,84 @@ pub async fn generate_monthly_analysis(analysis: &MonthlyAnalysis) -> Result<Str
         4. Next month market outlook\n\
         5. Long-term investment recommendations\n\n\
         Please respond in English, maintaining professionalism and objectivity.",
+        analysis.instrument,
         analysis.year,
         analysis.month,
-        analysis.start_price,
-        analysis.end_price,
-        analysis.monthly_change_pct,
-        analysis.highest_price,
-        analysis.highest_date.format("%Y-%m-%d"),
-        analysis.lowest_price,
-        analysis.lowest_date.format("%Y-%m-%d"),
-        analysis.average_volume,
-        analysis.total_volume
+        if analysis.complete {
+            ""
+        } else {
+            " (month to date)"
+        },
+        analysis.summary.start_price,
+        unit,
+        analysis.summary.end_price,
+        unit,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        unit,
+        market_date(analysis.summary.highest_date),
+        analysis.summary.lowest_price,
+        unit,
+        market_date(analysis.summary.lowest_date),
+        analysis.summary.average_volume,
+        analysis.summary.total_volume,
+        risk_section(analysis.summary.risk.as_ref()),
+        valuation_section(analysis.summary.valuation.as_ref())
     );
 
-    generate_gemini_response(&prompt).await
+    generate_gemini_response(&with_mock_notice(prompt, analysis.is_mock)).await
+}
+
+/// Generate quarterly analysis report
+pub async fn generate_quarterly_analysis(analysis: &QuarterlyAnalysis) -> Result<String> {
+    let unit = analysis.instrument.price_unit();
+    let prompt = format!(
+        "You are a professional stock analyst. Please analyze the following {} quarterly data:\n\n\
+        Quarter: {} Q{}{}\n\
+        Period: {} to {}\n\
+        Start Price: {:.2} {}\n\
+        End Price: {:.2} {}\n\
+        Quarterly Change: {:.2}%\n\
+        Highest: {:.2} {} ({})\n\
+        Lowest: {:.2} {} ({})\n\
+        Average Volume: {:.0}\n\
+        Total Volume: {}\n\n\
+        {}{}\
+        Please analyze this quarter's market performance including:\n\
+        1. Overall quarterly trend and how it compares to the previous quarter\n\
+        2. Key support and resistance levels\n\
+        3. Quarterly volume analysis\n\
+        4. Next quarter market outlook\n\
+        5. Asset allocation recommendations\n\n\
+        Please respond in English, maintaining professionalism and objectivity.",
+        analysis.instrument,
+        analysis.year,
+        analysis.quarter,
+        if analysis.complete {
+            ""
+        } else {
+            " (quarter to date)"
+        },
+        market_date(analysis.summary.start_date),
+        market_date(analysis.summary.end_date),
+        analysis.summary.start_price,
+        unit,
+        analysis.summary.end_price,
+        unit,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        unit,
+        market_date(analysis.summary.highest_date),
+        analysis.summary.lowest_price,
+        unit,
+        market_date(analysis.summary.lowest_date),
+        analysis.summary.average_volume,
+        analysis.summary.total_volume,
+        risk_section(analysis.summary.risk.as_ref()),
+        valuation_section(analysis.summary.valuation.as_ref())
+    );
+
+    generate_gemini_response(&with_mock_notice(prompt, analysis.is_mock)).await
 }
 
 /// Call Gemini API to generate response
@@ -145,9 +277,9 @@ async fn generate_gemini_response(prompt: &str) -> Result<String> {
         prompt.len()
     );
 
-    let client = reqwest::Client::new();
     let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-pro:generateContent?key={}",
+        "{}/v1beta/models/gemini-pro:generateContent?key={}",
+        http::base_url("GEMINI_API_URL", GEMINI_API_URL),
         api_key
     );
 
@@ -159,7 +291,14 @@ async fn generate_gemini_response(prompt: &str) -> Result<String> {
         }],
     };
 
-    let response = client.post(&url).json(&request_body).send().await;
+    let response = match HttpClient::for_provider("Gemini") {
+        Ok(client) => {
+            client
+                .send(|client| client.post(&url).json(&request_body))
+                .await
+        }
+        Err(e) => Err(e),
+    };
 
     match response {
         Ok(resp) => {
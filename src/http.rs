@@ -0,0 +1,581 @@
+use crate::rate_limit::RateLimiter;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Timeouts, retry and circuit breaker settings shared by all providers
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub connect_timeout: Duration,
+    /// Timeout of a whole request, including reading the response
+    pub request_timeout: Duration,
+    /// Retries after the first attempt on 429, 5xx and transport errors
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled on every further retry
+    pub initial_backoff: Duration,
+    /// Upper bound for backoff and `Retry-After` delays
+    pub max_backoff: Duration,
+    /// Consecutive failed calls that open a provider's circuit
+    pub breaker_threshold: u32,
+    /// How long an open circuit rejects calls before letting one through
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(30),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(300),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Defaults overridden by the `HTTP_*` and `CIRCUIT_BREAKER_*` variables
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let secs = |name: &str, default: Duration| {
+            env_parse::<u64>(name).map_or(default, Duration::from_secs)
+        };
+        let millis = |name: &str, default: Duration| {
+            env_parse::<u64>(name).map_or(default, Duration::from_millis)
+        };
+
+        Self {
+            connect_timeout: secs("HTTP_CONNECT_TIMEOUT_SECS", defaults.connect_timeout),
+            request_timeout: secs("HTTP_TIMEOUT_SECS", defaults.request_timeout),
+            max_retries: env_parse("HTTP_MAX_RETRIES").unwrap_or(defaults.max_retries),
+            initial_backoff: millis("HTTP_BACKOFF_MS", defaults.initial_backoff),
+            max_backoff: millis("HTTP_MAX_BACKOFF_MS", defaults.max_backoff),
+            breaker_threshold: env_parse::<u32>("CIRCUIT_BREAKER_THRESHOLD")
+                .unwrap_or(defaults.breaker_threshold)
+                .max(1),
+            breaker_cooldown: secs("CIRCUIT_BREAKER_COOLDOWN_SECS", defaults.breaker_cooldown),
+        }
+    }
+}
+
+/// Parse an environment variable, warning about and ignoring invalid values
+pub fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            warn!("Ignoring invalid {}: '{}'", name, value);
+            None
+        }
+    }
+}
+
+/// Base URL of a provider API, overridable e.g. to point at a local mock
+/// server
+///
+/// `variable` is read first, falling back to `default`. A trailing slash is
+/// removed.
+pub fn base_url(variable: &str, default: &str) -> String {
+    match std::env::var(variable) {
+        Ok(url) if !url.trim().is_empty() => url.trim().trim_end_matches('/').to_string(),
+        _ => default.to_string(),
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// HTTP client of one provider, with rate limiting, retries and a circuit
+/// breaker
+///
+/// Calls go through [`HttpClient::send`]. Every attempt waits for the
+/// provider's [`RateLimiter`] and counts against its daily quota. Responses with status 429 or 5xx
+/// and transport errors are retried with jittered exponential backoff,
+/// honouring `Retry-After`. A call that still fails counts against the
+/// provider's circuit breaker; once it opens, calls fail immediately until
+/// the cooldown has passed, after which one trial call is let through.
+pub struct HttpClient {
+    provider: &'static str,
+    client: Client,
+    config: HttpConfig,
+    limiter: Arc<RateLimiter>,
+    breaker: Mutex<BreakerState>,
+}
+
+impl HttpClient {
+    pub fn new(provider: &'static str, config: HttpConfig) -> Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.request_timeout)
+            .build()?;
+
+        Ok(Self {
+            provider,
+            client,
+            config,
+            limiter: RateLimiter::for_provider(provider),
+            breaker: Mutex::new(BreakerState::default()),
+        })
+    }
+
+    /// Shared client of a provider, configured from the environment
+    pub fn for_provider(provider: &'static str) -> Result<Arc<HttpClient>> {
+        static CLIENTS: OnceLock<Mutex<HashMap<&'static str, Arc<HttpClient>>>> = OnceLock::new();
+
+        let mut clients = CLIENTS
+            .get_or_init(Default::default)
+            .lock()
+            .map_err(|_| anyhow!("HTTP client registry poisoned"))?;
+        if let Some(client) = clients.get(provider) {
+            return Ok(Arc::clone(client));
+        }
+
+        let client = Arc::new(HttpClient::new(provider, HttpConfig::from_env())?);
+        clients.insert(provider, Arc::clone(&client));
+        Ok(client)
+    }
+
+    /// Send a request built by `build`, retrying as configured
+    ///
+    /// `build` is called once per attempt. The final response is returned
+    /// whatever its status, so callers keep handling error statuses
+    /// themselves; an error is only returned for transport failures, while
+    /// the circuit is open and once the daily quota is used up.
+    pub async fn send<F>(&self, build: F) -> Result<Response>
+    where
+        F: Fn(&Client) -> RequestBuilder,
+    {
+        self.check_breaker()?;
+
+        let mut attempt = 0;
+        loop {
+            self.limiter.acquire().await?;
+
+            // Errors carry the URL, which may contain an API key
+            let result = build(&self.client)
+                .send()
+                .await
+                .map_err(|e| e.without_url());
+            let retry_delay = match result {
+                Ok(response) if is_retryable(response.status()) => {
+                    if attempt >= self.config.max_retries {
+                        warn!(
+                            "{} request failed with {} after {} attempt(s)",
+                            self.provider,
+                            response.status(),
+                            attempt + 1
+                        );
+                        self.record_failure();
+                        return Ok(response);
+                    }
+                    debug!(
+                        "{} responded {}, retrying",
+                        self.provider,
+                        response.status()
+                    );
+                    retry_after(&response).map(|delay| delay.min(self.config.max_backoff))
+                }
+                Ok(response) => {
+                    self.record_success();
+                    return Ok(response);
+                }
+                Err(e) if attempt >= self.config.max_retries || e.is_builder() => {
+                    warn!(
+                        "{} request failed after {} attempt(s): {}",
+                        self.provider,
+                        attempt + 1,
+                        e
+                    );
+                    self.record_failure();
+                    return Err(anyhow!("{} request failed: {}", self.provider, e));
+                }
+                Err(e) => {
+                    debug!("{} request failed, retrying: {}", self.provider, e);
+                    None
+                }
+            };
+
+            let delay = retry_delay.unwrap_or_else(|| self.backoff(attempt));
+            warn!(
+                "{} attempt {} failed, retrying in {} ms",
+                self.provider,
+                attempt + 1,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Exponential backoff with jitter: half the delay is fixed, the other
+    /// half random, so concurrent callers spread out
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .config
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.config.max_backoff);
+        let half = exponential / 2;
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+
+    fn check_breaker(&self) -> Result<()> {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        match breaker.open_until {
+            Some(until) if Instant::now() < until => Err(anyhow!(
+                "{} circuit breaker is open after {} consecutive failures, retrying in {}s",
+                self.provider,
+                breaker.consecutive_failures,
+                (until - Instant::now()).as_secs()
+            )),
+            Some(_) => {
+                // Half-open: let this call through; a failure re-opens the
+                // circuit straight away
+                debug!(
+                    "{} circuit breaker half-open, trying one call",
+                    self.provider
+                );
+                breaker.open_until = None;
+                breaker.consecutive_failures = self.config.breaker_threshold - 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        *breaker = BreakerState::default();
+    }
+
+    fn record_failure(&self) {
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= self.config.breaker_threshold {
+            warn!(
+                "{} circuit breaker opened after {} consecutive failures, cooling down for {}s",
+                self.provider,
+                breaker.consecutive_failures,
+                self.config.breaker_cooldown.as_secs()
+            );
+            breaker.open_until = Some(Instant::now() + self.config.breaker_cooldown);
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Delay requested by a `Retry-After` header, in seconds or as an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::RateLimits;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A response the mock server never sends, holding the connection open
+    const HANG: &str = "";
+
+    fn status(line: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            line
+        )
+    }
+
+    fn ok(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    /// Local server answering the n-th request with the n-th scripted
+    /// response, 500 once the script runs out; returns its URL and the
+    /// arrival time of every request
+    async fn serve(script: Vec<String>) -> (String, Arc<Mutex<Vec<Instant>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+
+        let log = Arc::clone(&arrivals);
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let index = {
+                    let mut log = log.lock().unwrap();
+                    log.push(Instant::now());
+                    log.len() - 1
+                };
+                let response = script
+                    .get(index)
+                    .cloned()
+                    .unwrap_or_else(|| status("500 Internal Server Error"));
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buffer[..n]),
+                        }
+                    }
+                    if response == HANG {
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                        return;
+                    }
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+
+        (url, arrivals)
+    }
+
+    fn config() -> HttpConfig {
+        HttpConfig {
+            connect_timeout: Duration::from_secs(1),
+            request_timeout: Duration::from_millis(300),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(40),
+            max_backoff: Duration::from_millis(400),
+            breaker_threshold: 100,
+            breaker_cooldown: Duration::from_millis(300),
+        }
+    }
+
+    fn gaps(arrivals: &Mutex<Vec<Instant>>) -> Vec<Duration> {
+        let arrivals = arrivals.lock().unwrap();
+        arrivals.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    async fn get(client: &HttpClient, url: &str) -> Result<Response> {
+        client.send(|client| client.get(url)).await
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limits_and_server_errors_with_backoff() {
+        let (url, arrivals) = serve(vec![
+            status("429 Too Many Requests"),
+            status("503 Service Unavailable"),
+            ok("done"),
+        ])
+        .await;
+        let client = HttpClient::new("Test retries", config()).unwrap();
+
+        let response = get(&client, &url).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "done");
+        // Half of each doubled backoff is fixed, the rest is jitter
+        let gaps = gaps(&arrivals);
+        assert_eq!(gaps.len(), 2);
+        assert!(gaps[0] >= Duration::from_millis(20), "{:?}", gaps);
+        assert!(gaps[1] >= Duration::from_millis(40), "{:?}", gaps);
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after() {
+        let (url, arrivals) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\nContent-Length: 0\r\n\
+            Connection: close\r\n\r\n"
+                .to_string(),
+            ok("done"),
+        ])
+        .await;
+        let client = HttpClient::new(
+            "Test retry after",
+            HttpConfig {
+                max_backoff: Duration::from_secs(5),
+                ..config()
+            },
+        )
+        .unwrap();
+
+        let response = get(&client, &url).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(gaps(&arrivals)[0] >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn caps_retry_after_at_the_maximum_backoff() {
+        let (url, arrivals) = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 120\r\nContent-Length: 0\r\n\
+            Connection: close\r\n\r\n"
+                .to_string(),
+            ok("done"),
+        ])
+        .await;
+        let client = HttpClient::new("Test retry after cap", config()).unwrap();
+
+        let response = get(&client, &url).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let gap = gaps(&arrivals)[0];
+        assert!(gap >= Duration::from_millis(400), "{:?}", gap);
+        assert!(gap < Duration::from_secs(5), "{:?}", gap);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_retry() {
+        let (url, arrivals) = serve(Vec::new()).await;
+        let client = HttpClient::new(
+            "Test give up",
+            HttpConfig {
+                max_retries: 2,
+                ..config()
+            },
+        )
+        .unwrap();
+
+        let response = get(&client, &url).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(arrivals.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_opens_half_opens_and_closes() {
+        let (url, arrivals) = serve(vec![
+            status("500 Internal Server Error"),
+            status("500 Internal Server Error"),
+            status("500 Internal Server Error"),
+            ok("recovered"),
+            status("500 Internal Server Error"),
+            ok("still closed"),
+        ])
+        .await;
+        let client = HttpClient::new(
+            "Test breaker",
+            HttpConfig {
+                max_retries: 0,
+                breaker_threshold: 2,
+                ..config()
+            },
+        )
+        .unwrap();
+        let requests = || arrivals.lock().unwrap().len();
+
+        // Two failed calls open the circuit, which then rejects calls
+        // without reaching the server
+        for _ in 0..2 {
+            assert_eq!(get(&client, &url).await.unwrap().status(), 500);
+        }
+        let error = get(&client, &url).await.unwrap_err().to_string();
+        assert!(error.contains("circuit breaker is open"), "{}", error);
+        assert_eq!(requests(), 2);
+
+        // Half-open after the cooldown: a failed trial call re-opens it
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert_eq!(get(&client, &url).await.unwrap().status(), 500);
+        assert!(get(&client, &url).await.is_err());
+        assert_eq!(requests(), 3);
+
+        // A successful trial call closes it again
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        assert_eq!(get(&client, &url).await.unwrap().status(), 200);
+        assert_eq!(get(&client, &url).await.unwrap().status(), 500);
+        assert_eq!(get(&client, &url).await.unwrap().status(), 200);
+        assert_eq!(requests(), 6);
+    }
+
+    #[tokio::test]
+    async fn times_out_each_attempt() {
+        let (url, arrivals) = serve(vec![HANG.to_string(), ok("done")]).await;
+        let client = HttpClient::new("Test timeout retry", config()).unwrap();
+
+        let started = Instant::now();
+        let response = get(&client, &url).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(arrivals.lock().unwrap().len(), 2);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn fails_when_every_attempt_times_out() {
+        let (url, arrivals) = serve(vec![HANG.to_string(); 2]).await;
+        let client = HttpClient::new(
+            "Test timeout",
+            HttpConfig {
+                max_retries: 1,
+                ..config()
+            },
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let error = get(&client, &url).await.unwrap_err().to_string();
+
+        assert!(
+            error.starts_with("Test timeout request failed"),
+            "{}",
+            error
+        );
+        assert_eq!(arrivals.lock().unwrap().len(), 2);
+        assert!(started.elapsed() >= Duration::from_millis(600));
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn counts_every_attempt_against_the_daily_quota() {
+        let (url, arrivals) = serve(vec![
+            status("503 Service Unavailable"),
+            status("503 Service Unavailable"),
+            ok("done"),
+        ])
+        .await;
+        let dir = tempfile::tempdir().unwrap();
+        let limits = RateLimits {
+            per_minute: None,
+            daily_quota: Some(3),
+        };
+        let limiter =
+            RateLimiter::new("Test quota", limits).with_quota_file(dir.path().join("quotas.json"));
+        let client = HttpClient {
+            limiter: Arc::new(limiter),
+            ..HttpClient::new("Test quota", config()).unwrap()
+        };
+
+        // Both retries took a call from the quota
+        assert_eq!(get(&client, &url).await.unwrap().status(), 200);
+        assert_eq!(client.limiter.remaining_today(), Some(0));
+
+        let error = get(&client, &url).await.unwrap_err().to_string();
+        assert!(
+            error.contains("daily quota of 3 calls used up"),
+            "{}",
+            error
+        );
+        assert_eq!(arrivals.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,1117 @@
+use crate::models::StockData;
+use crate::streaming::{RsiMethod, Seed};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Indicator value at one bar
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point<T> {
+    pub date: NaiveDate,
+    /// `None` during warm-up
+    pub value: Option<T>,
+}
+
+/// Indicator values aligned with the bars they were computed from
+///
+/// Every indicator returns one point per input bar, dated with the bar's
+/// trading date. Points where the indicator is not defined yet have no value,
+/// so warm-up is never mistaken for a real reading.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Series<T> {
+    pub points: Vec<Point<T>>,
+}
+
+impl<T: Copy> Series<T> {
+    /// Pair values with the dates of the bars they belong to
+    fn new(bars: &[StockData], values: Vec<Option<T>>) -> Self {
+        debug_assert_eq!(bars.len(), values.len());
+        Self {
+            points: bars
+                .iter()
+                .zip(values)
+                .map(|(bar, value)| Point {
+                    date: bar.trade_date(),
+                    value,
+                })
+                .collect(),
+        }
+    }
+
+    /// Values in bar order, `None` during warm-up
+    pub fn values(&self) -> impl Iterator<Item = Option<T>> + '_ {
+        self.points.iter().map(|point| point.value)
+    }
+}
+
+/// Bollinger Bands
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bollinger {
+    pub middle: f64,
+    pub upper: f64,
+    pub lower: f64,
+    /// Position of the close within the bands: 0 at the lower band, 1 at the
+    /// upper band
+    pub percent_b: f64,
+    /// Band width relative to the middle band
+    pub bandwidth: f64,
+}
+
+/// Moving average convergence/divergence
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Macd {
+    /// Fast EMA minus slow EMA
+    pub macd: f64,
+    /// EMA of the MACD line
+    pub signal: f64,
+    /// MACD line minus signal line; Wind and Tonghuashun plot twice this as
+    /// their MACD bars
+    pub histogram: f64,
+}
+
+/// KDJ stochastic oscillator
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Kdj {
+    pub k: f64,
+    pub d: f64,
+    pub j: f64,
+}
+
+/// Directional movement index
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Dmi {
+    pub plus_di: f64,
+    pub minus_di: f64,
+    /// `None` until enough DX values have been averaged
+    pub adx: Option<f64>,
+}
+
+/// Parabolic SAR
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Psar {
+    pub sar: f64,
+    /// Whether the SAR is below price, i.e. the trend is up
+    pub rising: bool,
+}
+
+/// Ichimoku cloud, with every line placed at the bar it is plotted at
+///
+/// The leading spans are computed 26 bars earlier and shifted forward; the
+/// lagging span is the close 26 bars later, so it is `None` for the most
+/// recent bars.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Ichimoku {
+    pub tenkan: Option<f64>,
+    pub kijun: Option<f64>,
+    pub senkou_a: Option<f64>,
+    pub senkou_b: Option<f64>,
+    pub chikou: Option<f64>,
+}
+
+fn closes(bars: &[StockData]) -> Vec<f64> {
+    bars.iter().map(|bar| bar.close).collect()
+}
+
+/// Simple moving average of arbitrary values
+fn sma_values(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| {
+            (period > 0 && i + 1 >= period)
+                .then(|| values[i + 1 - period..=i].iter().sum::<f64>() / period as f64)
+        })
+        .collect()
+}
+
+/// Recursive average `value = alpha * x + (1 - alpha) * value` of arbitrary
+/// values, including the warm-up values of a first-value seed
+fn recursive_values(values: &[f64], period: usize, alpha: f64, seed: Seed) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    let start = match seed {
+        Seed::Sma => period,
+        Seed::FirstValue => 1,
+    };
+    if period == 0 || values.len() < start {
+        return result;
+    }
+
+    let mut value = values[..start].iter().sum::<f64>() / start as f64;
+    result[start - 1] = Some(value);
+    for i in start..values.len() {
+        value = alpha * values[i] + (1.0 - alpha) * value;
+        result[i] = Some(value);
+    }
+    result
+}
+
+/// Drop the values before the `period`-th, which a first-value seed still
+/// dominates
+fn warmed_up(values: Vec<Option<f64>>, period: usize) -> Vec<Option<f64>> {
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| value.filter(|_| i + 1 >= period))
+        .collect()
+}
+
+/// Exponential moving average of arbitrary values, defined from the
+/// `period`-th value
+fn ema_values(values: &[f64], period: usize, seed: Seed) -> Vec<Option<f64>> {
+    let alpha = 2.0 / (period as f64 + 1.0);
+    warmed_up(recursive_values(values, period, alpha, seed), period)
+}
+
+/// Sum of each `period` values, from the `period`-th value
+fn window_sums(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| (period > 0 && i + 1 >= period).then(|| values[i + 1 - period..=i].iter().sum()))
+        .collect()
+}
+
+/// Highest high and lowest low of the `period` bars ending at `i`
+fn high_low(bars: &[StockData], i: usize, period: usize) -> (f64, f64) {
+    bars[i + 1 - period..=i]
+        .iter()
+        .fold((f64::NEG_INFINITY, f64::INFINITY), |(high, low), bar| {
+            (high.max(bar.high), low.min(bar.low))
+        })
+}
+
+/// Midpoint of the highest high and lowest low of the `period` bars ending
+/// at each bar
+fn midpoints(bars: &[StockData], period: usize) -> Vec<Option<f64>> {
+    (0..bars.len())
+        .map(|i| {
+            (period > 0 && i + 1 >= period).then(|| {
+                let (high, low) = high_low(bars, i, period);
+                (high + low) / 2.0
+            })
+        })
+        .collect()
+}
+
+fn true_range(bars: &[StockData], i: usize) -> f64 {
+    let bar = &bars[i];
+    match i.checked_sub(1).map(|prev| bars[prev].close) {
+        Some(prev_close) => (bar.high - bar.low)
+            .max((bar.high - prev_close).abs())
+            .max((bar.low - prev_close).abs()),
+        None => bar.high - bar.low,
+    }
+}
+
+/// Simple moving average of the close
+pub fn sma(bars: &[StockData], period: usize) -> Series<f64> {
+    Series::new(bars, sma_values(&closes(bars), period))
+}
+
+/// Exponential moving average of the close, defined from bar `period - 1`
+pub fn ema(bars: &[StockData], period: usize, seed: Seed) -> Series<f64> {
+    Series::new(bars, ema_values(&closes(bars), period, seed))
+}
+
+/// Relative strength index over `period` bars (commonly 14)
+///
+/// Defined from bar `period`, the first with `period` price changes behind it.
+pub fn rsi(bars: &[StockData], period: usize, method: RsiMethod) -> Series<f64> {
+    let changes: Vec<f64> = bars.windows(2).map(|w| w[1].close - w[0].close).collect();
+    let gains: Vec<f64> = changes.iter().map(|change| change.max(0.0)).collect();
+    let losses: Vec<f64> = changes.iter().map(|change| (-change).max(0.0)).collect();
+
+    let (gains, losses) = match method {
+        RsiMethod::Wilder(seed) => {
+            let alpha = 1.0 / period as f64;
+            let average =
+                |values: &[f64]| warmed_up(recursive_values(values, period, alpha, seed), period);
+            (average(&gains), average(&losses))
+        }
+        RsiMethod::Cutler => (window_sums(&gains, period), window_sums(&losses, period)),
+    };
+
+    // The first bar has no change
+    let values = std::iter::once(None)
+        .chain(gains.into_iter().zip(losses).map(|(gain, loss)| {
+            let (gain, loss) = (gain?, loss?);
+            Some(if loss <= 0.0 {
+                100.0
+            } else {
+                100.0 - 100.0 / (1.0 + gain / loss)
+            })
+        }))
+        .take(bars.len())
+        .collect();
+    Series::new(bars, values)
+}
+
+/// MACD of the close (commonly 12, 26, 9)
+///
+/// With an SMA seed, defined from bar `slow + signal - 2`; with a first-value
+/// seed, from bar `max(slow, signal) - 1`.
+pub fn macd(
+    bars: &[StockData],
+    fast: usize,
+    slow: usize,
+    signal: usize,
+    seed: Seed,
+) -> Series<Macd> {
+    let closes = closes(bars);
+    let ema = |period: usize| recursive_values(&closes, period, 2.0 / (period as f64 + 1.0), seed);
+    let (fast_ema, slow_ema) = (ema(fast), ema(slow));
+    let macd_line: Vec<Option<f64>> = fast_ema
+        .iter()
+        .zip(&slow_ema)
+        .map(|(fast, slow)| Some((*fast)? - (*slow)?))
+        .collect();
+
+    // The signal line is an EMA over the MACD line from its first value, so
+    // with a first-value seed it runs through the slow EMA's warm-up
+    let start = macd_line
+        .iter()
+        .position(Option::is_some)
+        .unwrap_or(macd_line.len());
+    let defined: Vec<f64> = macd_line[start..].iter().flatten().copied().collect();
+    let mut signal_line = vec![None; start];
+    signal_line.extend(ema_values(&defined, signal, seed));
+
+    let values = macd_line
+        .iter()
+        .zip(&signal_line)
+        .enumerate()
+        .map(|(i, (macd, signal))| {
+            if i + 1 < slow {
+                return None;
+            }
+            let (macd, signal) = ((*macd)?, (*signal)?);
+            Some(Macd {
+                macd,
+                signal,
+                histogram: macd - signal,
+            })
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+/// Linearly weighted moving average of the close, the latest close weighted
+/// `period`
+pub fn wma(bars: &[StockData], period: usize) -> Series<f64> {
+    let weight_sum = (period * (period + 1) / 2) as f64;
+    let values = (0..bars.len())
+        .map(|i| {
+            (period > 0 && i + 1 >= period).then(|| {
+                bars[i + 1 - period..=i]
+                    .iter()
+                    .enumerate()
+                    .map(|(j, bar)| (j + 1) as f64 * bar.close)
+                    .sum::<f64>()
+                    / weight_sum
+            })
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+/// Bollinger Bands around the `period` SMA, `multiplier` population standard
+/// deviations wide (commonly 20 and 2.0)
+pub fn bollinger(bars: &[StockData], period: usize, multiplier: f64) -> Series<Bollinger> {
+    let closes = closes(bars);
+    let values = sma_values(&closes, period)
+        .into_iter()
+        .enumerate()
+        .map(|(i, middle)| {
+            let middle = middle?;
+            let window = &closes[i + 1 - period..=i];
+            let variance = window.iter().map(|c| (c - middle).powi(2)).sum::<f64>() / period as f64;
+            let deviation = variance.sqrt() * multiplier;
+            let (upper, lower) = (middle + deviation, middle - deviation);
+            Some(Bollinger {
+                middle,
+                upper,
+                lower,
+                percent_b: if upper > lower {
+                    (closes[i] - lower) / (upper - lower)
+                } else {
+                    0.5
+                },
+                bandwidth: if middle != 0.0 {
+                    (upper - lower) / middle
+                } else {
+                    0.0
+                },
+            })
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+/// KDJ as computed by Chinese charting software (commonly 9, 3, 3)
+///
+/// RSV over `period` bars is smoothed into K with weight `1/k_smoothing`, K
+/// into D with weight `1/d_smoothing`, both starting from 50; J = 3K - 2D.
+pub fn kdj(
+    bars: &[StockData],
+    period: usize,
+    k_smoothing: usize,
+    d_smoothing: usize,
+) -> Series<Kdj> {
+    let (k_weight, d_weight) = (1.0 / k_smoothing as f64, 1.0 / d_smoothing as f64);
+    let (mut k, mut d) = (50.0, 50.0);
+
+    let values = (0..bars.len())
+        .map(|i| {
+            if period == 0 || i + 1 < period {
+                return None;
+            }
+            let (high, low) = high_low(bars, i, period);
+            let rsv = if high > low {
+                (bars[i].close - low) / (high - low) * 100.0
+            } else {
+                50.0
+            };
+            k = (1.0 - k_weight) * k + k_weight * rsv;
+            d = (1.0 - d_weight) * d + d_weight * k;
+            Some(Kdj {
+                k,
+                d,
+                j: 3.0 * k - 2.0 * d,
+            })
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+/// Average true range with Wilder's smoothing, seeded with the mean of the
+/// first `period` true ranges
+pub fn atr(bars: &[StockData], period: usize) -> Series<f64> {
+    let mut values = vec![None; bars.len()];
+    if period > 0 && bars.len() >= period {
+        let mut atr = (0..period).map(|i| true_range(bars, i)).sum::<f64>() / period as f64;
+        values[period - 1] = Some(atr);
+        for (i, value) in values.iter_mut().enumerate().skip(period) {
+            atr = (atr * (period - 1) as f64 + true_range(bars, i)) / period as f64;
+            *value = Some(atr);
+        }
+    }
+    Series::new(bars, values)
+}
+
+/// On-balance volume, starting from 0 at the first bar
+pub fn obv(bars: &[StockData]) -> Series<f64> {
+    let mut obv = 0.0;
+    let values = (0..bars.len())
+        .map(|i| {
+            if i > 0 {
+                let volume = bars[i].volume as f64;
+                if bars[i].close > bars[i - 1].close {
+                    obv += volume;
+                } else if bars[i].close < bars[i - 1].close {
+                    obv -= volume;
+                }
+            }
+            Some(obv)
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+/// Volume-weighted average of the typical price over `period` bars
+///
+/// `None` where the window has no volume.
+pub fn vwap(bars: &[StockData], period: usize) -> Series<f64> {
+    let values = (0..bars.len())
+        .map(|i| {
+            if period == 0 || i + 1 < period {
+                return None;
+            }
+            let window = &bars[i + 1 - period..=i];
+            let volume: f64 = window.iter().map(|bar| bar.volume as f64).sum();
+            let value: f64 = window
+                .iter()
+                .map(|bar| typical_price(bar) * bar.volume as f64)
+                .sum();
+            (volume > 0.0).then(|| value / volume)
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+fn typical_price(bar: &StockData) -> f64 {
+    (bar.high + bar.low + bar.close) / 3.0
+}
+
+/// Commodity channel index over `period` bars (commonly 14 or 20)
+pub fn cci(bars: &[StockData], period: usize) -> Series<f64> {
+    let typical: Vec<f64> = bars.iter().map(typical_price).collect();
+    let values = sma_values(&typical, period)
+        .into_iter()
+        .enumerate()
+        .map(|(i, mean)| {
+            let mean = mean?;
+            let deviation = typical[i + 1 - period..=i]
+                .iter()
+                .map(|tp| (tp - mean).abs())
+                .sum::<f64>()
+                / period as f64;
+            Some(if deviation > 0.0 {
+                (typical[i] - mean) / (0.015 * deviation)
+            } else {
+                0.0
+            })
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+/// Williams %R over `period` bars, from -100 (at the low) to 0 (at the high)
+pub fn williams_r(bars: &[StockData], period: usize) -> Series<f64> {
+    let values = (0..bars.len())
+        .map(|i| {
+            (period > 0 && i + 1 >= period).then(|| {
+                let (high, low) = high_low(bars, i, period);
+                if high > low {
+                    (high - bars[i].close) / (high - low) * -100.0
+                } else {
+                    -50.0
+                }
+            })
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+/// +DI, -DI and ADX with Wilder's smoothing (commonly 14)
+///
+/// The DIs are defined from bar `period`, the ADX from bar `2 * period - 1`.
+pub fn dmi(bars: &[StockData], period: usize) -> Series<Dmi> {
+    let mut values = vec![None; bars.len()];
+    if period == 0 || bars.len() <= period {
+        return Series::new(bars, values);
+    }
+
+    let directional_movement = |i: usize| {
+        let up = bars[i].high - bars[i - 1].high;
+        let down = bars[i - 1].low - bars[i].low;
+        (
+            if up > down && up > 0.0 { up } else { 0.0 },
+            if down > up && down > 0.0 { down } else { 0.0 },
+        )
+    };
+
+    let (mut plus_dm, mut minus_dm, mut tr) = (0.0, 0.0, 0.0);
+    let mut dx_sum = 0.0;
+    let mut adx: Option<f64> = None;
+    let n = period as f64;
+
+    for (i, value) in values.iter_mut().enumerate().skip(1) {
+        let (plus, minus) = directional_movement(i);
+        let range = true_range(bars, i);
+        if i <= period {
+            plus_dm += plus;
+            minus_dm += minus;
+            tr += range;
+            if i < period {
+                continue;
+            }
+        } else {
+            plus_dm = plus_dm - plus_dm / n + plus;
+            minus_dm = minus_dm - minus_dm / n + minus;
+            tr = tr - tr / n + range;
+        }
+
+        let (plus_di, minus_di) = if tr > 0.0 {
+            (100.0 * plus_dm / tr, 100.0 * minus_dm / tr)
+        } else {
+            (0.0, 0.0)
+        };
+        let di_sum = plus_di + minus_di;
+        let dx = if di_sum > 0.0 {
+            100.0 * (plus_di - minus_di).abs() / di_sum
+        } else {
+            0.0
+        };
+
+        // DX values start at bar `period`; the first ADX averages `period`
+        // of them
+        let dx_count = i + 1 - period;
+        adx = match adx {
+            Some(adx) => Some((adx * (n - 1.0) + dx) / n),
+            None => {
+                dx_sum += dx;
+                (dx_count == period).then(|| dx_sum / n)
+            }
+        };
+
+        *value = Some(Dmi {
+            plus_di,
+            minus_di,
+            adx,
+        });
+    }
+    Series::new(bars, values)
+}
+
+/// Parabolic SAR with acceleration `step` up to `max_step` (commonly 0.02
+/// and 0.2)
+///
+/// The initial trend follows the second bar's close; the SAR is defined from
+/// the second bar.
+pub fn psar(bars: &[StockData], step: f64, max_step: f64) -> Series<Psar> {
+    let mut values = vec![None; bars.len()];
+    if bars.len() < 2 {
+        return Series::new(bars, values);
+    }
+
+    let mut rising = bars[1].close >= bars[0].close;
+    let mut sar = if rising { bars[0].low } else { bars[0].high };
+    let mut extreme = if rising { bars[0].high } else { bars[0].low };
+    let mut acceleration = step;
+
+    for i in 1..bars.len() {
+        let bar = &bars[i];
+        sar += acceleration * (extreme - sar);
+
+        // The SAR may not move into the range of the previous two bars
+        let earlier = &bars[i.saturating_sub(2)..i];
+        if rising {
+            sar = earlier.iter().map(|b| b.low).fold(sar, f64::min);
+        } else {
+            sar = earlier.iter().map(|b| b.high).fold(sar, f64::max);
+        }
+
+        if rising && bar.low < sar {
+            rising = false;
+            sar = extreme;
+            extreme = bar.low;
+            acceleration = step;
+        } else if !rising && bar.high > sar {
+            rising = true;
+            sar = extreme;
+            extreme = bar.high;
+            acceleration = step;
+        } else if rising && bar.high > extreme {
+            extreme = bar.high;
+            acceleration = (acceleration + step).min(max_step);
+        } else if !rising && bar.low < extreme {
+            extreme = bar.low;
+            acceleration = (acceleration + step).min(max_step);
+        }
+
+        values[i] = Some(Psar { sar, rising });
+    }
+    Series::new(bars, values)
+}
+
+/// Ichimoku cloud (commonly 9, 26, 52), displaced by `kijun_period`
+pub fn ichimoku(
+    bars: &[StockData],
+    tenkan_period: usize,
+    kijun_period: usize,
+    senkou_b_period: usize,
+) -> Series<Ichimoku> {
+    let tenkan = midpoints(bars, tenkan_period);
+    let kijun = midpoints(bars, kijun_period);
+    let senkou_b = midpoints(bars, senkou_b_period);
+    let shift = kijun_period;
+
+    let values = (0..bars.len())
+        .map(|i| {
+            let earlier = i.checked_sub(shift);
+            Some(Ichimoku {
+                tenkan: tenkan[i],
+                kijun: kijun[i],
+                senkou_a: earlier.and_then(|j| Some((tenkan[j]? + kijun[j]?) / 2.0)),
+                senkou_b: earlier.and_then(|j| senkou_b[j]),
+                chikou: bars.get(i + shift).map(|bar| bar.close),
+            })
+        })
+        .collect();
+    Series::new(bars, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::market_close_utc;
+    use chrono::Days;
+
+    /// Closes of StockCharts' RSI example
+    const RSI_CLOSES: [f64; 33] = [
+        44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03, 45.61,
+        46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64, 46.21, 46.25, 45.71, 46.45, 45.78, 45.35,
+        44.03, 44.18, 44.22, 44.57, 43.42, 42.66, 43.13,
+    ];
+
+    /// Closes of StockCharts' moving average example
+    const EMA_CLOSES: [f64; 30] = [
+        22.27, 22.19, 22.08, 22.17, 22.18, 22.13, 22.23, 22.43, 22.24, 22.29, 22.15, 22.39, 22.38,
+        22.61, 23.36, 24.05, 23.75, 23.83, 23.95, 23.63, 23.82, 23.87, 23.65, 23.19, 23.10, 23.33,
+        22.68, 23.10, 22.40, 22.17,
+    ];
+
+    /// Bars of StockCharts' ATR example, with made-up volumes; expected values
+    /// of indicators StockCharts does not publish for them were worked out
+    /// separately from the textbook definitions
+    const HIGHS: [f64; 30] = [
+        48.70, 48.72, 48.90, 48.87, 48.82, 49.05, 49.20, 49.35, 49.92, 50.19, 50.12, 49.66, 49.88,
+        50.19, 50.36, 50.57, 50.65, 50.43, 49.63, 50.33, 50.29, 50.17, 49.32, 48.50, 48.32, 46.80,
+        47.80, 48.39, 48.66, 48.79,
+    ];
+    const LOWS: [f64; 30] = [
+        47.79, 48.14, 48.39, 48.37, 48.24, 48.64, 48.94, 48.86, 49.50, 49.87, 49.20, 48.90, 49.43,
+        49.73, 49.26, 50.09, 50.30, 49.21, 48.98, 49.61, 49.20, 49.43, 48.08, 47.64, 41.55, 44.28,
+        47.31, 47.20, 47.90, 47.73,
+    ];
+    const CLOSES: [f64; 30] = [
+        48.16, 48.61, 48.75, 48.63, 48.74, 49.03, 49.07, 49.32, 49.91, 50.13, 49.53, 49.50, 49.75,
+        50.03, 50.31, 50.52, 50.41, 49.34, 49.37, 50.23, 49.24, 49.93, 48.43, 48.18, 46.57, 45.41,
+        47.77, 47.72, 48.62, 47.85,
+    ];
+    const VOLUMES: [u64; 30] = [
+        1200, 1350, 980, 1100, 1420, 1610, 1530, 1270, 1890, 2040, 1760, 1320, 1150, 1480, 1670,
+        1920, 1580, 2210, 1990, 1730, 2080, 1640, 2470, 2630, 3910, 3420, 2760, 2150, 1880, 2300,
+    ];
+
+    fn date(i: usize) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, 1).unwrap() + Days::new(i as u64)
+    }
+
+    fn closes(closes: &[f64]) -> Vec<StockData> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| StockData {
+                date: market_close_utc(date(i)),
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: 1_000,
+                amount: None,
+            })
+            .collect()
+    }
+
+    fn ohlcv() -> Vec<StockData> {
+        (0..CLOSES.len())
+            .map(|i| StockData {
+                date: market_close_utc(date(i)),
+                open: CLOSES[i],
+                high: HIGHS[i],
+                low: LOWS[i],
+                close: CLOSES[i],
+                volume: VOLUMES[i],
+                amount: None,
+            })
+            .collect()
+    }
+
+    /// One field of a compound indicator
+    fn field<T: Copy>(series: &Series<T>, get: impl Fn(T) -> Option<f64>) -> Series<f64> {
+        Series {
+            points: series
+                .points
+                .iter()
+                .map(|point| Point {
+                    date: point.date,
+                    value: point.value.and_then(&get),
+                })
+                .collect(),
+        }
+    }
+
+    /// Check a series is dated like `bars`, undefined for the first `warmup`
+    /// bars and matches `expected` after that
+    fn assert_series(
+        series: &Series<f64>,
+        bars: &[StockData],
+        warmup: usize,
+        expected: &[f64],
+        tolerance: f64,
+    ) {
+        assert_eq!(series.points.len(), bars.len());
+        assert_eq!(warmup + expected.len(), bars.len());
+        for (i, (point, bar)) in series.points.iter().zip(bars).enumerate() {
+            assert_eq!(point.date, bar.trade_date(), "date at bar {}", i);
+            match (point.value, i.checked_sub(warmup)) {
+                (None, None) => {}
+                (Some(value), Some(j)) => assert!(
+                    (value - expected[j]).abs() <= tolerance,
+                    "bar {}: got {}, expected {}",
+                    i,
+                    value,
+                    expected[j]
+                ),
+                (value, _) => panic!("bar {}: unexpected {:?} with warm-up {}", i, value, warmup),
+            }
+        }
+    }
+
+    #[test]
+    fn sma_matches_stockcharts() {
+        let bars = closes(&EMA_CLOSES);
+        let expected = [
+            22.22, 22.21, 22.23, 22.26, 22.30, 22.42, 22.61, 22.77, 22.91, 23.08, 23.21, 23.38,
+            23.52, 23.65, 23.71, 23.68, 23.61, 23.51, 23.43, 23.28, 23.13,
+        ];
+        // Published rounded to cents
+        assert_series(&sma(&bars, 10), &bars, 9, &expected, 0.0051);
+    }
+
+    #[test]
+    fn ema_matches_stockcharts() {
+        let bars = closes(&EMA_CLOSES);
+        // StockCharts rounds each step to cents, hence the wider tolerance
+        let expected = [
+            22.22, 22.21, 22.24, 22.27, 22.33, 22.52, 22.80, 22.97, 23.13, 23.28, 23.34, 23.43,
+            23.51, 23.54, 23.47, 23.40, 23.39, 23.26, 23.23, 23.08, 22.92,
+        ];
+        assert_series(&ema(&bars, 10, Seed::Sma), &bars, 9, &expected, 0.01);
+    }
+
+    #[test]
+    fn ema_seeds() {
+        let bars = ohlcv();
+        let sma_seeded = ema(&bars, 10, Seed::Sma);
+        let first_value = ema(&bars, 10, Seed::FirstValue);
+
+        // Both report from bar `period - 1`; the SMA seed starts at the SMA
+        let seed = sma(&bars, 10).points[9].value.unwrap();
+        assert!((sma_seeded.points[9].value.unwrap() - seed).abs() < 1e-9);
+
+        // The first-value seed runs the recursion from the first close
+        let alpha = 2.0 / 11.0;
+        let recursion = CLOSES[1..10]
+            .iter()
+            .fold(CLOSES[0], |ema, close| alpha * close + (1.0 - alpha) * ema);
+        assert!((first_value.points[9].value.unwrap() - recursion).abs() < 1e-9);
+
+        let expected = [
+            49.188265, 49.250399, 49.295781, 49.378366, 49.496845, 49.644691, 49.803838, 49.914050,
+            49.809677, 49.729736, 49.820693, 49.715112, 49.754183, 49.513422, 49.270982, 48.779894,
+            48.167186, 48.094971, 48.026794, 48.134650, 48.082895,
+        ];
+        assert_series(&first_value, &bars, 9, &expected, 1e-6);
+        let expected = [
+            49.035000, 49.125000, 49.193182, 49.294421, 49.428163, 49.588497, 49.757861, 49.876432,
+            49.778899, 49.704554, 49.800089, 49.698255, 49.740390, 49.502138, 49.261749, 48.772340,
+            48.161005, 48.089914, 48.022657, 48.131264, 48.080125,
+        ];
+        assert_series(&sma_seeded, &bars, 9, &expected, 1e-6);
+    }
+
+    #[test]
+    fn rsi_matches_stockcharts() {
+        let bars = closes(&RSI_CLOSES);
+        let expected = [
+            70.46, 66.25, 66.48, 69.35, 66.29, 57.92, 62.88, 63.21, 56.01, 62.34, 54.67, 50.39,
+            40.02, 41.49, 41.90, 45.50, 37.32, 33.09, 37.79,
+        ];
+        let series = rsi(&bars, 14, RsiMethod::Wilder(Seed::Sma));
+        assert_series(&series, &bars, 14, &expected, 0.005);
+    }
+
+    #[test]
+    fn rsi_methods() {
+        let bars = closes(&RSI_CLOSES);
+
+        // Cutler's RSI sums the last `period` gains and losses, so it agrees
+        // with Wilder's on the first value only
+        let expected = [
+            70.464135, 70.020964, 69.831224, 80.567686, 73.333333, 59.806295, 62.528217, 60.000000,
+            48.477752, 53.878407, 48.952381, 43.862816, 37.732919, 32.263514, 32.718121, 38.142620,
+            31.748252, 25.099602, 30.217670,
+        ];
+        assert_series(
+            &rsi(&bars, 14, RsiMethod::Cutler),
+            &bars,
+            14,
+            &expected,
+            1e-6,
+        );
+
+        // Seeded with the first change, Wilder's averages still carry it
+        let expected = [
+            50.657415, 47.272575, 47.675983, 52.620718, 50.072727, 43.196520, 50.404615, 50.875713,
+            44.702384, 53.100794, 46.251521, 42.465700, 33.421828, 35.112954, 35.582848, 39.697820,
+            32.378969, 28.623230, 33.741716,
+        ];
+        let series = rsi(&bars, 14, RsiMethod::Wilder(Seed::FirstValue));
+        assert_series(&series, &bars, 14, &expected, 1e-6);
+    }
+
+    #[test]
+    fn wma_reference() {
+        let bars = ohlcv();
+        let expected = [
+            49.319455, 49.409455, 49.469091, 49.558000, 49.679636, 49.826727, 49.983455, 50.093091,
+            49.983818, 49.879636, 49.941636, 49.821818, 49.832727, 49.563091, 49.272000, 48.721818,
+            48.028727, 47.857636, 47.725455, 47.786364, 47.720909,
+        ];
+        assert_series(&wma(&bars, 10), &bars, 9, &expected, 1e-6);
+    }
+
+    #[test]
+    fn bollinger_reference() {
+        let bars = ohlcv();
+        let series = bollinger(&bars, 20, 2.0);
+        type Case = (fn(Bollinger) -> f64, [f64; 11]);
+        let cases: [Case; 5] = [
+            (
+                |b| b.middle,
+                [
+                    49.467000, 49.521000, 49.587000, 49.571000, 49.548500, 49.440000, 49.259000,
+                    49.194000, 49.114000, 49.049500, 48.935500,
+                ],
+            ),
+            (
+                |b| b.upper,
+                [
+                    50.801947, 50.720615, 50.722396, 50.760839, 50.822694, 51.234447, 51.769688,
+                    51.786863, 51.783962, 51.701695, 51.588125,
+                ],
+            ),
+            (
+                |b| b.lower,
+                [
+                    48.132053, 48.321385, 48.451604, 48.381161, 48.274306, 47.645553, 46.748312,
+                    46.601137, 46.444038, 46.397305, 46.282875,
+                ],
+            ),
+            (
+                |b| b.percent_b,
+                [
+                    0.785779, 0.382879, 0.651049, 0.020523, -0.037006, -0.299689, -0.266523,
+                    0.225400, 0.238948, 0.419029, 0.295391,
+                ],
+            ),
+            (
+                |b| b.bandwidth,
+                [
+                    0.053973, 0.048449, 0.045794, 0.048005, 0.051432, 0.072591, 0.101938, 0.105414,
+                    0.108725, 0.108144, 0.108413,
+                ],
+            ),
+        ];
+        for (get, expected) in cases {
+            assert_series(
+                &field(&series, |b| Some(get(b))),
+                &bars,
+                19,
+                &expected,
+                1e-6,
+            );
+        }
+    }
+
+    #[test]
+    fn kdj_reference() {
+        let bars = ohlcv();
+        let series = kdj(&bars, 9, 3, 3);
+        type Case = (fn(Kdj) -> f64, [f64; 22]);
+        let cases: [Case; 3] = [
+            (
+                |kdj| kdj.k,
+                [
+                    66.510172, 76.697838, 73.183174, 70.327244, 72.696795, 78.357003, 84.460224,
+                    88.665491, 87.872232, 66.962440, 53.594008, 61.062672, 45.898069, 49.560788,
+                    37.580085, 31.033456, 39.077249, 40.540989, 50.641601, 57.185517, 65.087827,
+                    67.753834,
+                ],
+            ),
+            (
+                |kdj| kdj.d,
+                [
+                    55.503391, 62.568207, 66.106529, 67.513434, 69.241221, 72.279815, 76.339952,
+                    80.448465, 82.923054, 77.602849, 69.599902, 66.754159, 59.802129, 56.388349,
+                    50.118927, 43.757104, 42.197152, 41.645098, 44.643932, 48.824460, 54.245583,
+                    58.748333,
+                ],
+            ),
+            (
+                |kdj| kdj.j,
+                [
+                    88.523735, 104.957102, 87.336465, 75.954865, 79.607944, 90.511380, 100.700770,
+                    105.099543, 97.770588, 45.681622, 21.582219, 49.679698, 18.089949, 35.905668,
+                    12.502399, 5.586161, 32.837443, 38.332771, 62.636938, 73.907630, 86.772317,
+                    85.764835,
+                ],
+            ),
+        ];
+        for (get, expected) in cases {
+            assert_series(
+                &field(&series, |kdj| Some(get(kdj))),
+                &bars,
+                8,
+                &expected,
+                1e-6,
+            );
+        }
+    }
+
+    #[test]
+    fn atr_matches_stockcharts() {
+        let bars = ohlcv();
+        let expected = [
+            0.554286, 0.593265, 0.585175, 0.568377, 0.614921, 0.617427, 0.641896, 0.673904,
+            0.692196, 0.774897, 0.780975, 1.208763, 1.302423, 1.380107, 1.366528, 1.336061,
+            1.316343,
+        ];
+        assert_series(&atr(&bars, 14), &bars, 13, &expected, 1e-6);
+    }
+
+    #[test]
+    fn obv_reference() {
+        let bars = ohlcv();
+        let expected = [
+            0.0, 1350.0, 2330.0, 1230.0, 2650.0, 4260.0, 5790.0, 7060.0, 8950.0, 10990.0, 9230.0,
+            7910.0, 9060.0, 10540.0, 12210.0, 14130.0, 12550.0, 10340.0, 12330.0, 14060.0, 11980.0,
+            13620.0, 11150.0, 8520.0, 4610.0, 1190.0, 3950.0, 1800.0, 3680.0, 1380.0,
+        ];
+        assert_series(&obv(&bars), &bars, 0, &expected, 0.0);
+    }
+
+    #[test]
+    fn vwap_reference() {
+        let bars = ohlcv();
+        let expected = [
+            48.516623, 48.669551, 48.798328, 48.884396, 49.140039, 49.457830, 49.595277, 49.653768,
+            49.732659, 49.769798, 49.735470, 49.930721, 50.134295, 50.074090, 49.929687, 49.944662,
+            49.775019, 49.675568, 49.413454, 49.104057, 47.861474, 47.033099, 46.837540, 46.677944,
+            46.640224, 47.275526,
+        ];
+        assert_series(&vwap(&bars, 5), &bars, 4, &expected, 1e-6);
+
+        // A window without volume has no average
+        let flat = closes(&[10.0; 3])
+            .into_iter()
+            .map(|bar| StockData { volume: 0, ..bar })
+            .collect::<Vec<_>>();
+        assert!(vwap(&flat, 2).values().all(|value| value.is_none()));
+    }
+
+    #[test]
+    fn cci_reference() {
+        let bars = ohlcv();
+        let expected = [
+            77.358677,
+            13.660388,
+            43.862196,
+            -133.438819,
+            -189.452159,
+            -379.054605,
+            -268.110675,
+            -96.722245,
+            -75.987781,
+            -34.140267,
+            -44.028833,
+        ];
+        assert_series(&cci(&bars, 20), &bars, 19, &expected, 1e-6);
+    }
+
+    #[test]
+    fn williams_r_reference() {
+        let bars = ohlcv();
+        let expected = [
+            -6.666667, -2.252252, -2.145923, -9.958506, -54.356846, -63.681592, -23.463687,
+            -78.770950, -41.142857, -86.381323, -82.059801, -44.835165, -57.582418, -31.648352,
+            -32.197802, -22.307692, -30.769231,
+        ];
+        assert_series(&williams_r(&bars, 14), &bars, 13, &expected, 1e-6);
+    }
+
+    #[test]
+    fn dmi_reference() {
+        let bars = ohlcv();
+        let series = dmi(&bars, 14);
+        let expected = [
+            26.415094, 27.473426, 27.262239, 23.266616, 21.461822, 27.112981, 23.902992, 21.557833,
+            17.813725, 16.388770, 9.766016, 8.404560, 12.572058, 14.890444, 15.590326, 14.687860,
+        ];
+        assert_series(
+            &field(&series, |dmi| Some(dmi.plus_di)),
+            &bars,
+            14,
+            &expected,
+            1e-6,
+        );
+        let expected = [
+            20.000000, 18.778959, 17.919854, 28.387995, 28.930724, 25.753334, 27.157635, 24.493157,
+            32.913004, 34.372840, 56.834045, 48.910949, 42.814717, 40.132550, 38.102033, 36.824813,
+        ];
+        assert_series(
+            &field(&series, |dmi| Some(dmi.minus_di)),
+            &bars,
+            14,
+            &expected,
+            1e-6,
+        );
+        let expected = [28.597875, 29.549970, 30.508815];
+        assert_series(&field(&series, |dmi| dmi.adx), &bars, 27, &expected, 1e-6);
+    }
+
+    #[test]
+    fn psar_reference() {
+        let bars = ohlcv();
+        let series = psar(&bars, 0.02, 0.2);
+        let expected = [
+            47.790000, 47.790000, 47.856600, 47.919204, 47.978052, 48.063808, 48.177427, 48.318136,
+            48.542397, 48.806013, 50.190000, 50.164200, 48.900000, 48.925800, 48.983168, 49.078378,
+            49.204108, 50.650000, 50.616600, 50.583868, 50.551791, 50.520355, 50.422741, 50.255776,
+            49.559314, 48.918569, 41.550000, 41.686800, 41.965728,
+        ];
+        assert_series(
+            &field(&series, |psar| Some(psar.sar)),
+            &bars,
+            1,
+            &expected,
+            1e-6,
+        );
+
+        // Reversals at bars 11, 13, 18 and 27
+        let rising: Vec<bool> = series.values().flatten().map(|psar| psar.rising).collect();
+        let mut expected = vec![true; 10];
+        expected.extend([false, false]);
+        expected.extend([true; 5]);
+        expected.extend([false; 9]);
+        expected.extend([true; 3]);
+        assert_eq!(rising, expected);
+    }
+
+    #[test]
+    fn ichimoku_reference() {
+        let bars = ohlcv();
+        let series = ichimoku(&bars, 3, 5, 10);
+        let expected = [
+            48.345000, 48.520000, 48.570000, 48.645000, 48.720000, 48.995000, 49.390000, 49.525000,
+            49.695000, 49.545000, 49.510000, 49.545000, 49.810000, 49.915000, 49.955000, 49.930000,
+            49.815000, 49.705000, 49.655000, 49.765000, 49.185000, 48.905000, 45.435000, 45.025000,
+            44.935000, 46.335000, 47.930000, 47.995000,
+        ];
+        assert_series(&field(&series, |i| i.tenkan), &bars, 2, &expected, 1e-9);
+        let expected = [
+            48.345000, 48.595000, 48.720000, 48.795000, 49.080000, 49.415000, 49.525000, 49.525000,
+            49.545000, 49.545000, 49.630000, 49.735000, 49.955000, 49.930000, 49.815000, 49.815000,
+            49.815000, 49.705000, 49.205000, 48.985000, 45.920000, 45.860000, 45.435000, 45.025000,
+            45.105000, 46.535000,
+        ];
+        assert_series(&field(&series, |i| i.kijun), &bars, 4, &expected, 1e-9);
+
+        // The leading spans are displaced forward by the kijun period
+        let expected = [
+            48.457500, 48.620000, 48.720000, 48.895000, 49.235000, 49.470000, 49.610000, 49.535000,
+            49.527500, 49.545000, 49.720000, 49.825000, 49.955000, 49.930000, 49.815000, 49.760000,
+            49.735000, 49.735000, 49.195000, 48.945000, 45.677500,
+        ];
+        assert_series(&field(&series, |i| i.senkou_a), &bars, 9, &expected, 1e-9);
+        let expected = [
+            48.990000, 49.165000, 49.215000, 49.215000, 49.215000, 49.500000, 49.715000, 49.755000,
+            49.775000, 49.775000, 49.775000, 49.775000, 49.815000, 49.365000, 49.145000, 46.100000,
+        ];
+        assert_series(&field(&series, |i| i.senkou_b), &bars, 14, &expected, 1e-9);
+
+        // The lagging span is the close 5 bars later, missing at the end
+        let chikou: Vec<Option<f64>> = series.values().map(|i| i.unwrap().chikou).collect();
+        let expected: Vec<Option<f64>> = (0..bars.len())
+            .map(|i| CLOSES.get(i + 5).copied())
+            .collect();
+        assert_eq!(chikou, expected);
+    }
+}
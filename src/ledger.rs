@@ -0,0 +1,236 @@
+use crate::store::data_dir;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// What started a job run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTrigger {
+    /// Run from the command line
+    Manual,
+    /// Run by the daemon at its scheduled time
+    Scheduled,
+    /// Run by the daemon at startup for a scheduled time it missed
+    CatchUp,
+}
+
+/// How a job run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// Every instrument got a report
+    Success,
+    /// At least one instrument only got a data unavailable notice
+    DataUnavailable,
+    Failed,
+}
+
+impl fmt::Display for RunTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RunTrigger::Manual => "manual",
+            RunTrigger::Scheduled => "scheduled",
+            RunTrigger::CatchUp => "catch-up",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RunStatus::Success => "success",
+            RunStatus::DataUnavailable => "data unavailable",
+            RunStatus::Failed => "failed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One job run in the ledger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub job: String,
+    pub mode: String,
+    pub trigger: RunTrigger,
+    /// Scheduled time of the run; the start time for manual runs
+    pub scheduled_at: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: RunStatus,
+    /// Data sources that served the reports, comma-separated
+    pub data_source: Option<String>,
+    /// SHA-256 of the generated reports
+    pub report_hash: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Append-only log of job runs in `$DATA_DIR/runs.jsonl`
+pub struct RunLedger {
+    path: PathBuf,
+}
+
+impl RunLedger {
+    pub fn open() -> Self {
+        Self::at(data_dir().join("runs.jsonl"))
+    }
+
+    fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Append a run to the ledger
+    pub fn append(&self, record: &RunRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to append to {}", self.path.display()))
+    }
+
+    /// All recorded runs, oldest first
+    ///
+    /// Unreadable lines, e.g. from a write cut short by a crash, are skipped.
+    pub fn load(&self) -> Result<Vec<RunRecord>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()));
+            }
+        };
+
+        Ok(contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| match serde_json::from_str(line) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    warn!(
+                        "Skipping unreadable line {} of {}: {}",
+                        index + 1,
+                        self.path.display(),
+                        e
+                    );
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Whether a run of the job at the scheduled time was recorded
+pub fn was_recorded(records: &[RunRecord], job: &str, scheduled_at: DateTime<Utc>) -> bool {
+    records
+        .iter()
+        .any(|record| record.job == job && record.scheduled_at == scheduled_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 10, 14, hour, 0, 0).unwrap()
+    }
+
+    fn record(job: &str, scheduled_at: DateTime<Utc>, status: RunStatus) -> RunRecord {
+        RunRecord {
+            job: job.to_string(),
+            mode: "daily".to_string(),
+            trigger: RunTrigger::Scheduled,
+            scheduled_at,
+            started_at: scheduled_at,
+            finished_at: scheduled_at,
+            status,
+            data_source: Some("TuShare".to_string()),
+            report_hash: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn loads_appended_runs_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = RunLedger::at(dir.path().join("nested").join("runs.jsonl"));
+        assert!(ledger.load().unwrap().is_empty());
+
+        ledger
+            .append(&record("daily", utc(7), RunStatus::Success))
+            .unwrap();
+        let failed = RunRecord {
+            trigger: RunTrigger::CatchUp,
+            error: Some("TuShare is down".to_string()),
+            ..record("weekly", utc(12), RunStatus::Failed)
+        };
+        ledger.append(&failed).unwrap();
+
+        let records = ledger.load().unwrap();
+        let runs: Vec<_> = records
+            .iter()
+            .map(|record| (record.job.as_str(), record.scheduled_at, record.status))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("daily", utc(7), RunStatus::Success),
+                ("weekly", utc(12), RunStatus::Failed)
+            ]
+        );
+        assert_eq!(records[1].trigger, RunTrigger::CatchUp);
+        assert_eq!(records[1].error.as_deref(), Some("TuShare is down"));
+    }
+
+    #[test]
+    fn skips_unreadable_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = RunLedger::at(dir.path().join("runs.jsonl"));
+        ledger
+            .append(&record("daily", utc(7), RunStatus::Success))
+            .unwrap();
+        // A write cut short by a crash, then a blank line
+        let mut contents = std::fs::read_to_string(&ledger.path).unwrap();
+        contents.push_str("{\"job\":\"daily\",\"mo\n\n");
+        std::fs::write(&ledger.path, contents).unwrap();
+        ledger
+            .append(&record("daily", utc(8), RunStatus::DataUnavailable))
+            .unwrap();
+
+        let records = ledger.load().unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].status, RunStatus::DataUnavailable);
+    }
+
+    #[test]
+    fn matches_recorded_runs_by_job_and_scheduled_time() {
+        let records = [
+            record("daily", utc(7), RunStatus::Success),
+            record("weekly", utc(12), RunStatus::Failed),
+        ];
+
+        assert!(was_recorded(&records, "daily", utc(7)));
+        // Failed runs count as recorded, so they are not caught up again
+        assert!(was_recorded(&records, "weekly", utc(12)));
+        assert!(!was_recorded(&records, "daily", utc(12)));
+        assert!(!was_recorded(&records, "monthly", utc(7)));
+        assert!(!was_recorded(&[], "daily", utc(7)));
+    }
+}
@@ -1,35 +1,114 @@
+mod alerts;
 mod analyzer;
+mod calendar;
+mod daemon;
 mod data_fetcher;
+mod data_source;
 mod email_sender;
 mod gemini_client;
+mod http;
+mod indicators;
+mod ledger;
+mod mock_data;
 mod models;
+mod rate_limit;
+mod report;
+mod risk;
+mod rules;
+mod schedule;
 mod scheduler;
-
-use anyhow::Result;
-use clap::Parser;
+mod store;
+mod streaming;
+mod validator;
+mod valuation;
+
+use anyhow::{Result, anyhow};
+use clap::{Parser, Subcommand};
+use ledger::RunTrigger;
+use models::{Instrument, market_timezone};
+use report::{OnDataUnavailable, RunOptions};
+use schedule::Schedule;
+use std::path::{Path, PathBuf};
 use tracing::info;
 
 #[derive(Parser)]
 #[command(name = "investment-notice")]
-#[command(about = "A-Share Investment Notification System - CSI 300 ETF Analysis")]
+#[command(about = "A-Share Investment Notification System - Index, ETF and Stock Analysis")]
 struct Args {
-    /// Run mode: daily, weekly, monthly
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Run mode: daily, weekly, monthly, quarterly
     #[arg(short, long, default_value = "daily")]
     mode: String,
 
+    /// Instrument to analyze, e.g. 000300.SH, 159919 or "600519.SH:Kweichow Moutai".
+    /// Overrides the WATCHLIST environment variable
+    #[arg(global = true, long)]
+    symbol: Option<Instrument>,
+
     /// Whether to send email notifications
-    #[arg(short, long, default_value = "false")]
+    #[arg(global = true, short, long, default_value = "false")]
     send_email: bool,
 
+    /// Never fall back to mock data (default when --send-email is set)
+    #[arg(global = true, long, conflicts_with = "allow_mock")]
+    strict: bool,
+
+    /// Allow falling back to mock data even when sending email
+    #[arg(global = true, long)]
+    allow_mock: bool,
+
+    /// Action when strict mode leaves no data source for an instrument
+    #[arg(global = true, long, value_enum, default_value = "fail")]
+    on_data_unavailable: OnDataUnavailable,
+
+    /// Print the next N run times of the schedule and exit
+    #[arg(long, value_name = "N")]
+    next_runs: Option<usize>,
+
+    /// Schedule to preview with --next-runs, as a cron expression or e.g.
+    /// "last trading day of month 16:00". Defaults to the mode's schedule
+    #[arg(long, requires = "next_runs")]
+    schedule: Option<Schedule>,
+
+    /// Send emails even if the same report was already delivered
+    #[arg(global = true, long)]
+    force: bool,
+
     /// Debug mode
-    #[arg(short, long, default_value = "false")]
+    #[arg(global = true, short, long, default_value = "false")]
     debug: bool,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Run the daily, weekly, monthly, quarterly and custom jobs on their
+    /// schedules until SIGTERM or Ctrl-C
+    #[command(alias = "daemon")]
+    Serve,
+    /// List past job runs from the run ledger
+    History {
+        /// Only show runs of this job
+        #[arg(long)]
+        job: Option<String>,
+
+        /// Number of most recent runs to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    /// Check an alert rules file and list its rules
+    ValidateRules {
+        /// Rules file to check. Defaults to ALERT_RULES_FILE
+        path: Option<PathBuf>,
+    },
+}
+
 /// Main entry point for the A-Share Investment Notification System
 ///
-/// This application analyzes CSI 300 ETF data and provides investment notifications
-/// through various analysis modes (daily, weekly, monthly) with optional email delivery.
+/// This application analyzes A-share instruments (CSI 300 by default) and provides
+/// investment notifications through various analysis modes (daily, weekly, monthly, quarterly)
+/// with optional email delivery.
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
@@ -47,168 +126,193 @@ async fn main() -> Result<()> {
         args.mode
     );
 
-    match args.mode.as_str() {
-        "daily" => run_daily_analysis(args.send_email).await?,
-        "weekly" => run_weekly_analysis(args.send_email).await?,
-        "monthly" => run_monthly_analysis(args.send_email).await?,
-        _ => {
-            eprintln!(
-                "Invalid mode: {}. Supported modes: daily, weekly, monthly",
-                args.mode
-            );
-            std::process::exit(1);
-        }
+    if !report::MODES.contains(&args.mode.as_str()) {
+        eprintln!(
+            "Invalid mode: {}. Supported modes: {}",
+            args.mode,
+            report::MODES.join(", ")
+        );
+        std::process::exit(1);
     }
 
-    info!("Analysis completed");
-    Ok(())
-}
-
-/// Execute daily investment analysis
-///
-/// Fetches current CSI 300 ETF data, performs technical analysis,
-/// generates AI-powered insights, and optionally sends email notifications.
-async fn run_daily_analysis(send_email: bool) -> Result<()> {
-    info!("Starting daily analysis");
-
-    // Fetch CSI 300 ETF data
-    let data = data_fetcher::fetch_hs300_data().await?;
-    info!("Retrieved {} data points", data.len());
+    if let Some(count) = args.next_runs {
+        let schedule = match args.schedule {
+            Some(schedule) => schedule,
+            None => Schedule::for_mode(&args.mode)?,
+        };
+        print_next_runs(&schedule, count);
+        return Ok(());
+    }
 
-    // Analyze data
-    let analysis = analyzer::analyze_daily_data(&data).await?;
-    info!(
-        "Analysis completed, price change: {:.2}%",
-        analysis.price_change_pct
-    );
+    if let Some(Command::History { job, limit }) = &args.command {
+        return print_history(job.as_deref(), *limit);
+    }
 
-    // Generate intelligent analysis using Gemini
-    let gemini_analysis = gemini_client::generate_daily_analysis(&analysis).await?;
-    info!("Gemini analysis completed");
+    if let Some(Command::ValidateRules { path }) = &args.command {
+        let Some(path) = path.clone().or_else(rules::configured_path) else {
+            eprintln!("No rules file given and ALERT_RULES_FILE is not set");
+            std::process::exit(1);
+        };
+        if !validate_rules(&path) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    // Generate summary report
-    let report = format_daily_report(&analysis, &gemini_analysis);
-    println!("{}", report);
+    let instruments = match args.symbol {
+        Some(instrument) => vec![instrument],
+        None => load_watchlist()?,
+    };
 
-    // Send email notification
-    if send_email {
-        email_sender::send_email("Daily Investment Analysis Report", &report).await?;
-        info!("Email sent successfully");
+    let strict = args.strict || (args.send_email && !args.allow_mock);
+    if strict {
+        info!("Strict mode enabled, mock data will not be used");
     }
 
-    Ok(())
+    let options = RunOptions {
+        send_email: args.send_email,
+        allow_mock: !strict,
+        on_data_unavailable: args.on_data_unavailable,
+        force: args.force,
+    };
+
+    match args.command {
+        Some(Command::Serve) => {
+            let jobs = daemon::configured_jobs()?;
+            daemon::serve(jobs, instruments, options).await
+        }
+        Some(Command::History { .. } | Command::ValidateRules { .. }) => {
+            unreachable!("handled before loading the watchlist")
+        }
+        None => {
+            daemon::run_job(
+                &args.mode,
+                &args.mode,
+                chrono::Utc::now(),
+                RunTrigger::Manual,
+                &instruments,
+                &options,
+            )
+            .await
+        }
+    }
 }
 
-/// Execute weekly investment analysis
-///
-/// Performs both daily analysis and additional weekly-specific analysis
-/// including trend analysis and volume studies.
-async fn run_weekly_analysis(send_email: bool) -> Result<()> {
-    info!("Starting weekly analysis");
-
-    // Also execute daily analysis
-    run_daily_analysis(false).await?;
-
-    // Fetch weekly data for weekly analysis
-    let weekly_data = data_fetcher::fetch_weekly_hs300_data().await?;
-    let weekly_analysis = analyzer::analyze_weekly_data(&weekly_data).await?;
-    let gemini_analysis = gemini_client::generate_weekly_analysis(&weekly_analysis).await?;
-    let report = format_weekly_report(&weekly_analysis, &gemini_analysis);
-
-    println!("{}", report);
+/// Print the upcoming run times of a schedule in the market timezone
+fn print_next_runs(schedule: &Schedule, count: usize) {
+    println!(
+        "Next {} run(s) of \"{}\" ({}):",
+        count,
+        schedule.expression(),
+        market_timezone()
+    );
 
-    if send_email {
-        email_sender::send_email("Weekly Investment Analysis Report", &report).await?;
+    let runs = schedule.upcoming(chrono::Utc::now(), count);
+    for run in &runs {
+        println!("  {}", schedule::format_run(*run));
+    }
+    if runs.len() < count {
+        println!("  (no further runs within the next five years)");
     }
-
-    Ok(())
 }
 
-/// Execute monthly investment analysis
-///
-/// Performs both daily analysis and comprehensive monthly analysis
-/// including long-term trend assessment and market outlook.
-async fn run_monthly_analysis(send_email: bool) -> Result<()> {
-    info!("Starting monthly analysis");
-
-    // Also execute daily analysis
-    run_daily_analysis(false).await?;
-
-    // Fetch monthly data for monthly analysis
-    let monthly_data = data_fetcher::fetch_monthly_hs300_data().await?;
-    let monthly_analysis = analyzer::analyze_monthly_data(&monthly_data).await?;
-    let gemini_analysis = gemini_client::generate_monthly_analysis(&monthly_analysis).await?;
-    let report = format_monthly_report(&monthly_analysis, &gemini_analysis);
-
-    println!("{}", report);
+/// Print the most recent runs from the run ledger, newest last
+fn print_history(job: Option<&str>, limit: usize) -> Result<()> {
+    let records = ledger::RunLedger::open().load()?;
+    let records: Vec<_> = records
+        .iter()
+        .filter(|record| job.is_none_or(|job| record.job == job))
+        .collect();
+
+    if records.is_empty() {
+        println!("No runs recorded");
+        return Ok(());
+    }
 
-    if send_email {
-        email_sender::send_email("Monthly Investment Analysis Report", &report).await?;
+    println!(
+        "{:<26} {:<20} {:<12} {:<10} {:<17} {:<32} REPORT HASH",
+        "SCHEDULED", "STARTED", "JOB", "TRIGGER", "STATUS", "SOURCE"
+    );
+    for record in records.iter().skip(records.len().saturating_sub(limit)) {
+        let started = record.started_at.with_timezone(&market_timezone());
+        println!(
+            "{:<26} {:<20} {:<12} {:<10} {:<17} {:<32} {}",
+            schedule::format_run(record.scheduled_at),
+            started.format("%Y-%m-%d %H:%M:%S"),
+            record.job,
+            record.trigger.to_string(),
+            record.status.to_string(),
+            record.data_source.as_deref().unwrap_or("-"),
+            record
+                .report_hash
+                .as_deref()
+                .map_or("-", |hash| &hash[..12])
+        );
+        if let Some(error) = &record.error {
+            println!("    error: {}", error);
+        }
     }
 
     Ok(())
 }
 
-fn format_daily_report(analysis: &models::DailyAnalysis, gemini_analysis: &str) -> String {
-    format!(
-        "📊 CSI 300 ETF Daily Analysis Report\n\n\
-        📅 Date: {}\n\n\
-        💰 Current Price: {:.2} CNY\n\
-        📈 Price Change: {:.2}%\n\
-        📊 Relative to High: {:.2}%\n\
-        📉 Relative to Low: {:.2}%\n\n\
-        🤖 AI Analysis:\n{}\n",
-        analysis.date.format("%Y-%m-%d"),
-        analysis.current_price,
-        analysis.price_change_pct,
-        analysis.relative_to_high,
-        analysis.relative_to_low,
-        gemini_analysis
-    )
-}
+/// Check a rules file, printing its rules or every error found
+fn validate_rules(path: &Path) -> bool {
+    let rules = match rules::load(path) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprint!("{}", e);
+            return false;
+        }
+    };
+
+    println!("{}: {} rule(s) OK", path.display(), rules.len());
+    for rule in &rules {
+        let scope = if rule.instruments.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", rule.instruments.join(", "))
+        };
+        println!(
+            "  {:<6} {}{}: {}",
+            rule.kind.to_string(),
+            rule.name,
+            scope,
+            rule.condition
+        );
+    }
 
-fn format_weekly_report(analysis: &models::WeeklyAnalysis, gemini_analysis: &str) -> String {
-    format!(
-        "📈 CSI 300 ETF Weekly Analysis Report\n\n\
-        📅 Period: {} to {}\n\n\
-        💰 Start Price: {:.2} CNY\n\
-        💰 End Price: {:.2} CNY\n\
-        📈 Weekly Change: {:.2}%\n\
-        📊 Highest: {:.2} CNY ({})\n\
-        📉 Lowest: {:.2} CNY ({})\n\n\
-        🤖 AI Analysis:\n{}\n",
-        analysis.start_date.format("%Y-%m-%d"),
-        analysis.end_date.format("%Y-%m-%d"),
-        analysis.start_price,
-        analysis.end_price,
-        analysis.weekly_change_pct,
-        analysis.highest_price,
-        analysis.highest_date.format("%Y-%m-%d"),
-        analysis.lowest_price,
-        analysis.lowest_date.format("%Y-%m-%d"),
-        gemini_analysis
-    )
+    let is_json = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    if is_json {
+        match rules::to_toml(&rules) {
+            Ok(equivalent) => println!(
+                "\nJSON rules files are deprecated, the equivalent TOML is:\n\n{}",
+                equivalent
+            ),
+            Err(e) => eprintln!("Failed to convert the rules to TOML: {:#}", e),
+        }
+    }
+    true
 }
 
-fn format_monthly_report(analysis: &models::MonthlyAnalysis, gemini_analysis: &str) -> String {
-    format!(
-        "📊 CSI 300 ETF Monthly Analysis Report\n\n\
-        📅 Month: {}-{}\n\n\
-        💰 Start Price: {:.2} CNY\n\
-        💰 End Price: {:.2} CNY\n\
-        📈 Monthly Change: {:.2}%\n\
-        📊 Highest: {:.2} CNY ({})\n\
-        📉 Lowest: {:.2} CNY ({})\n\n\
-        🤖 AI Analysis:\n{}\n",
-        analysis.year,
-        analysis.month,
-        analysis.start_price,
-        analysis.end_price,
-        analysis.monthly_change_pct,
-        analysis.highest_price,
-        analysis.highest_date.format("%Y-%m-%d"),
-        analysis.lowest_price,
-        analysis.lowest_date.format("%Y-%m-%d"),
-        gemini_analysis
-    )
+/// Load the instruments to analyze from the WATCHLIST environment variable
+///
+/// WATCHLIST is a comma-separated list of instruments in the same format as
+/// `--symbol`. Falls back to the CSI 300 index when unset.
+fn load_watchlist() -> Result<Vec<Instrument>> {
+    let watchlist = match std::env::var("WATCHLIST") {
+        Ok(watchlist) if !watchlist.trim().is_empty() => watchlist,
+        _ => return Ok(vec![Instrument::csi300()]),
+    };
+
+    watchlist
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| {
+            s.parse()
+                .map_err(|e| anyhow!("Invalid WATCHLIST entry '{}': {}", s.trim(), e))
+        })
+        .collect()
 }
@@ -0,0 +1,322 @@
+use crate::calendar::TradingCalendar;
+use crate::models::{StockData, market_close_utc};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+use tracing::info;
+
+/// Price process used to generate mock closes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockModel {
+    /// Arithmetic random walk, price moves are proportional to the start price
+    RandomWalk,
+    /// Geometric Brownian motion, log returns are normally distributed
+    Gbm,
+    /// Geometric Brownian motion with Poisson-distributed price jumps
+    Jump,
+}
+
+impl FromStr for MockModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "random_walk" | "randomwalk" => Ok(MockModel::RandomWalk),
+            "gbm" => Ok(MockModel::Gbm),
+            "jump" => Ok(MockModel::Jump),
+            other => Err(anyhow!(
+                "Unknown mock model '{}'. Supported: random_walk, gbm, jump",
+                other
+            )),
+        }
+    }
+}
+
+/// Parameters of the mock data generator
+///
+/// Volatility and drift are per trading day. With a fixed seed the generated
+/// series only depends on the configuration and the requested dates.
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// Random seed, a fresh one is drawn (and logged) when unset
+    pub seed: Option<u64>,
+    pub start_price: f64,
+    pub volatility: f64,
+    pub drift: f64,
+    /// Number of bars to generate, ending at the end of the range. When
+    /// unset, one bar is generated for every trading day in the range
+    pub bars: Option<usize>,
+    pub model: MockModel,
+    /// Probability of a jump on any given bar (jump model only)
+    pub jump_intensity: f64,
+    /// Mean log return of a jump (jump model only)
+    pub jump_mean: f64,
+    /// Standard deviation of the log return of a jump (jump model only)
+    pub jump_volatility: f64,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            start_price: 3500.0,
+            volatility: 0.012,
+            drift: 0.0002,
+            bars: None,
+            model: MockModel::Gbm,
+            jump_intensity: 0.02,
+            jump_mean: -0.01,
+            jump_volatility: 0.04,
+        }
+    }
+}
+
+impl MockConfig {
+    /// Load the configuration from `MOCK_*` environment variables, falling
+    /// back to the defaults for unset ones
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+
+        Ok(Self {
+            seed: env_parse("MOCK_SEED")?,
+            start_price: env_parse("MOCK_START_PRICE")?.unwrap_or(defaults.start_price),
+            volatility: env_parse("MOCK_VOLATILITY")?.unwrap_or(defaults.volatility),
+            drift: env_parse("MOCK_DRIFT")?.unwrap_or(defaults.drift),
+            bars: env_parse("MOCK_BARS")?,
+            model: env_parse("MOCK_MODEL")?.unwrap_or(defaults.model),
+            jump_intensity: env_parse("MOCK_JUMP_INTENSITY")?.unwrap_or(defaults.jump_intensity),
+            jump_mean: env_parse("MOCK_JUMP_MEAN")?.unwrap_or(defaults.jump_mean),
+            jump_volatility: env_parse("MOCK_JUMP_VOLATILITY")?.unwrap_or(defaults.jump_volatility),
+        })
+    }
+}
+
+/// Parse an optional environment variable
+fn env_parse<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid {} '{}': {}", name, value, e)),
+        _ => Ok(None),
+    }
+}
+
+/// Generate mock bars between `start_date` and `end_date`
+///
+/// Bars are only generated for trading days, so there are no weekend bars.
+pub fn generate(config: &MockConfig, start_date: NaiveDate, end_date: NaiveDate) -> Vec<StockData> {
+    let seed = config.seed.unwrap_or_else(|| rand::rng().random());
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let dates = trading_dates(config, start_date, end_date);
+    let mut data = Vec::with_capacity(dates.len());
+    let mut previous_close = config.start_price;
+
+    for date in dates {
+        let z = standard_normal(&mut rng);
+        let close = match config.model {
+            MockModel::RandomWalk => {
+                previous_close + config.start_price * (config.drift + config.volatility * z)
+            }
+            MockModel::Gbm => previous_close * gbm_return(config, z).exp(),
+            MockModel::Jump => {
+                let mut log_return = gbm_return(config, z);
+                if rng.random::<f64>() < config.jump_intensity {
+                    log_return +=
+                        config.jump_mean + config.jump_volatility * standard_normal(&mut rng);
+                }
+                previous_close * log_return.exp()
+            }
+        }
+        .max(0.01);
+
+        // Overnight gap, then intraday extremes beyond the open/close range
+        let open = previous_close * (0.2 * config.volatility * standard_normal(&mut rng)).exp();
+        let high =
+            open.max(close) * (1.0 + 0.5 * config.volatility * standard_normal(&mut rng).abs());
+        let low = open.min(close)
+            * (1.0 - 0.5 * config.volatility * standard_normal(&mut rng).abs()).max(0.0);
+
+        // Larger moves come with larger volume
+        let move_size = ((close - previous_close) / previous_close).abs() / config.volatility;
+        let volume = 1_000_000.0 * (1.0 + 0.3 * move_size + 0.2 * standard_normal(&mut rng));
+
+        data.push(StockData {
+            date,
+            open,
+            high,
+            low,
+            close,
+            volume: volume.max(100_000.0) as u64,
+            amount: None,
+        });
+
+        previous_close = close;
+    }
+
+    info!(
+        "Generated {} mock data points ({:?} model, seed {})",
+        data.len(),
+        config.model,
+        seed
+    );
+    data
+}
+
+/// Trading days to generate bars for, stamped at the market close
+fn trading_dates(
+    config: &MockConfig,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Vec<DateTime<Utc>> {
+    let calendar = TradingCalendar::global();
+
+    let dates: Vec<NaiveDate> = match config.bars {
+        Some(bars) => {
+            let mut dates = Vec::with_capacity(bars);
+            let mut date = end_date;
+            while dates.len() < bars {
+                if calendar.is_trading_day(date) {
+                    dates.push(date);
+                }
+                date -= Duration::days(1);
+            }
+            dates.reverse();
+            dates
+        }
+        None => start_date
+            .iter_days()
+            .take_while(|date| *date <= end_date)
+            .filter(|date| calendar.is_trading_day(*date))
+            .collect(),
+    };
+
+    dates.into_iter().map(market_close_utc).collect()
+}
+
+/// Daily GBM log return for a standard normal draw
+fn gbm_return(config: &MockConfig, z: f64) -> f64 {
+    config.drift - 0.5 * config.volatility.powi(2) + config.volatility * z
+}
+
+/// Draw from the standard normal distribution (Box-Muller transform)
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>();
+    let u2: f64 = rng.random::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Weekday};
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn seeded(seed: u64) -> MockConfig {
+        MockConfig {
+            seed: Some(seed),
+            ..MockConfig::default()
+        }
+    }
+
+    fn log_returns(bars: &[StockData]) -> Vec<f64> {
+        bars.windows(2)
+            .map(|pair| (pair[1].close / pair[0].close).ln())
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_generates_identical_bars() {
+        let first = generate(&seeded(42), date("2025-01-01"), date("2025-12-31"));
+        let second = generate(&seeded(42), date("2025-01-01"), date("2025-12-31"));
+        assert!(!first.is_empty());
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn different_seeds_generate_different_bars() {
+        let first = generate(&seeded(1), date("2025-01-01"), date("2025-03-31"));
+        let second = generate(&seeded(2), date("2025-01-01"), date("2025-03-31"));
+        assert_eq!(first.len(), second.len());
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn skips_weekends_and_holidays() {
+        let bars = generate(&seeded(7), date("2025-01-01"), date("2025-12-31"));
+        let calendar = TradingCalendar::bundled();
+        for bar in &bars {
+            let day = bar.trade_date();
+            assert!(!matches!(day.weekday(), Weekday::Sat | Weekday::Sun));
+            assert!(!calendar.is_holiday(day), "bar on holiday {}", day);
+        }
+        // National Day week
+        assert!(
+            bars.iter()
+                .all(|bar| !(date("2025-10-01")..=date("2025-10-08")).contains(&bar.trade_date()))
+        );
+        let trading_days = date("2025-01-01")
+            .iter_days()
+            .take_while(|day| *day <= date("2025-12-31"))
+            .filter(|day| calendar.is_trading_day(*day))
+            .count();
+        assert_eq!(bars.len(), trading_days);
+    }
+
+    #[test]
+    fn honors_bar_count() {
+        let config = MockConfig {
+            bars: Some(250),
+            ..seeded(3)
+        };
+        // Bars reach back past the range start and end at its last trading day
+        let bars = generate(&config, date("2025-09-01"), date("2025-10-06"));
+        assert_eq!(bars.len(), 250);
+        assert_eq!(bars.last().unwrap().trade_date(), date("2025-09-30"));
+        assert!(bars.windows(2).all(|pair| pair[0].date < pair[1].date));
+    }
+
+    #[test]
+    fn jump_model_has_fatter_tails_than_gbm() {
+        let config = |model| MockConfig {
+            model,
+            jump_intensity: 0.1,
+            jump_volatility: 0.08,
+            ..seeded(11)
+        };
+        let gbm = generate(
+            &config(MockModel::Gbm),
+            date("2025-01-01"),
+            date("2025-12-31"),
+        );
+        let jump = generate(
+            &config(MockModel::Jump),
+            date("2025-01-01"),
+            date("2025-12-31"),
+        );
+        assert_eq!(gbm.len(), jump.len());
+        assert_ne!(gbm, jump);
+
+        // Beyond five daily standard deviations
+        let threshold = 5.0 * config(MockModel::Gbm).volatility;
+        let tail = |bars: &[StockData]| {
+            log_returns(bars)
+                .iter()
+                .filter(|r| r.abs() > threshold)
+                .count()
+        };
+        assert_eq!(tail(&gbm), 0);
+        assert!(tail(&jump) >= 5, "only {} jumps", tail(&jump));
+    }
+}
@@ -1,7 +1,287 @@
-use chrono::{DateTime, Utc};
+use crate::indicators::{Kdj, Macd};
+use crate::risk::RiskMetrics;
+use crate::validator::ValidationReport;
+use crate::valuation::Valuation;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tracing::warn;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Market timezone used when `MARKET_TIMEZONE` is not set
+const DEFAULT_MARKET_TIMEZONE: Tz = chrono_tz::Asia::Shanghai;
+
+/// Timezone of the market, from `MARKET_TIMEZONE` (default Asia/Shanghai)
+///
+/// Trading dates, report dates and schedules are all expressed in this
+/// timezone.
+pub fn market_timezone() -> Tz {
+    static TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+    *TIMEZONE.get_or_init(|| match std::env::var("MARKET_TIMEZONE") {
+        Ok(name) if !name.trim().is_empty() => name.trim().parse().unwrap_or_else(|e| {
+            warn!(
+                "Invalid MARKET_TIMEZONE '{}': {}, using {}",
+                name, e, DEFAULT_MARKET_TIMEZONE
+            );
+            DEFAULT_MARKET_TIMEZONE
+        }),
+        _ => DEFAULT_MARKET_TIMEZONE,
+    })
+}
+
+/// Calendar date of an instant in the market timezone
+pub fn market_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&market_timezone()).date_naive()
+}
+
+/// Today's date in the market timezone
+pub fn market_today() -> NaiveDate {
+    market_date(Utc::now())
+}
+
+/// Instant of a local market date and time
+///
+/// Times skipped by a DST transition resolve to the first valid instant
+/// after them; ambiguous times resolve to the earlier instant.
+pub fn market_datetime(date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    local_datetime(market_timezone(), date, time)
+}
+
+/// Instant of a local date and time in `tz`, resolved like
+/// [`market_datetime`]
+pub fn local_datetime(tz: Tz, date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    let mut local = date.and_time(time);
+
+    loop {
+        if let Some(resolved) = tz.from_local_datetime(&local).earliest() {
+            return resolved.with_timezone(&Utc);
+        }
+        local += Duration::minutes(15);
+    }
+}
+
+/// Instant of the 15:00 market close of a trading date
+///
+/// Bars are stamped at the close, so their market date is the trading date.
+pub fn market_close_utc(date: NaiveDate) -> DateTime<Utc> {
+    market_datetime(date, NaiveTime::from_hms_opt(15, 0, 0).unwrap())
+}
+
+/// Stock exchange an instrument is listed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Exchange {
+    /// Shanghai Stock Exchange (SSE)
+    Shanghai,
+    /// Shenzhen Stock Exchange (SZSE)
+    Shenzhen,
+}
+
+impl Exchange {
+    /// Exchange suffix used by TuShare codes, e.g. `000300.SH`
+    pub fn tushare_suffix(&self) -> &'static str {
+        match self {
+            Exchange::Shanghai => "SH",
+            Exchange::Shenzhen => "SZ",
+        }
+    }
+
+    /// Exchange suffix used by Alpha Vantage symbols, e.g. `000300.SS`
+    pub fn alpha_vantage_suffix(&self) -> &'static str {
+        match self {
+            Exchange::Shanghai => "SS",
+            Exchange::Shenzhen => "SZ",
+        }
+    }
+}
+
+/// Kind of instrument, which decides the API used to fetch its bars
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssetType {
+    Index,
+    Etf,
+    Stock,
+}
+
+/// A tradable or trackable A-share instrument
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instrument {
+    /// Six-digit exchange code, e.g. `000300`
+    pub code: String,
+    pub exchange: Exchange,
+    pub asset_type: AssetType,
+    /// Human readable name used in reports and prompts
+    pub name: String,
+}
+
+/// Well-known instruments that can be referenced by code alone
+const KNOWN_INSTRUMENTS: &[(&str, Exchange, AssetType, &str)] = &[
+    (
+        "000300",
+        Exchange::Shanghai,
+        AssetType::Index,
+        "CSI 300 Index",
+    ),
+    (
+        "000905",
+        Exchange::Shanghai,
+        AssetType::Index,
+        "CSI 500 Index",
+    ),
+    (
+        "000016",
+        Exchange::Shanghai,
+        AssetType::Index,
+        "SSE 50 Index",
+    ),
+    (
+        "399001",
+        Exchange::Shenzhen,
+        AssetType::Index,
+        "SZSE Component Index",
+    ),
+    (
+        "399006",
+        Exchange::Shenzhen,
+        AssetType::Index,
+        "ChiNext Index",
+    ),
+    (
+        "510300",
+        Exchange::Shanghai,
+        AssetType::Etf,
+        "Huatai-PineBridge CSI 300 ETF",
+    ),
+    (
+        "510500",
+        Exchange::Shanghai,
+        AssetType::Etf,
+        "China Southern CSI 500 ETF",
+    ),
+    (
+        "159919",
+        Exchange::Shenzhen,
+        AssetType::Etf,
+        "Harvest CSI 300 ETF",
+    ),
+    (
+        "159915",
+        Exchange::Shenzhen,
+        AssetType::Etf,
+        "E Fund ChiNext ETF",
+    ),
+];
+
+impl Instrument {
+    /// The CSI 300 index, the default instrument
+    pub fn csi300() -> Self {
+        Self::from_str("000300.SH").expect("CSI 300 is a known instrument")
+    }
+
+    /// Code in TuShare format, e.g. `000300.SH`
+    pub fn tushare_code(&self) -> String {
+        format!("{}.{}", self.code, self.exchange.tushare_suffix())
+    }
+
+    /// Symbol in Alpha Vantage format, e.g. `000300.SS`
+    pub fn alpha_vantage_symbol(&self) -> String {
+        format!("{}.{}", self.code, self.exchange.alpha_vantage_suffix())
+    }
+
+    /// Unit that prices of this instrument are quoted in
+    pub fn price_unit(&self) -> &'static str {
+        match self.asset_type {
+            AssetType::Index => "pts",
+            AssetType::Etf | AssetType::Stock => "CNY",
+        }
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.tushare_code())
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = anyhow::Error;
+
+    /// Parse `CODE[.EXCHANGE][:Name]`, e.g. `000300.SH`, `159919` or
+    /// `600519.SH:Kweichow Moutai`
+    ///
+    /// Exchange and asset type are taken from the known instrument table
+    /// when possible and otherwise inferred from the code prefix.
+    fn from_str(s: &str) -> Result<Self> {
+        let (symbol, name) = match s.split_once(':') {
+            Some((symbol, name)) => (symbol.trim(), Some(name.trim().to_string())),
+            None => (s.trim(), None),
+        };
+
+        let (code, exchange) = match symbol.split_once('.') {
+            Some((code, suffix)) => {
+                let exchange = match suffix.to_ascii_uppercase().as_str() {
+                    "SH" | "SS" | "SSE" => Exchange::Shanghai,
+                    "SZ" | "SZSE" => Exchange::Shenzhen,
+                    other => return Err(anyhow!("Unknown exchange suffix: {}", other)),
+                };
+                (code, Some(exchange))
+            }
+            None => (symbol, None),
+        };
+
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow!("Invalid instrument code: {}", symbol));
+        }
+
+        let known = KNOWN_INSTRUMENTS
+            .iter()
+            .find(|(c, e, _, _)| *c == code && exchange.is_none_or(|exchange| exchange == *e));
+
+        let (exchange, asset_type, default_name) = match known {
+            Some((_, exchange, asset_type, known_name)) => {
+                (*exchange, *asset_type, known_name.to_string())
+            }
+            None => {
+                let exchange = exchange.unwrap_or_else(|| infer_exchange(code));
+                let asset_type = infer_asset_type(code, exchange);
+                (exchange, asset_type, code.to_string())
+            }
+        };
+
+        Ok(Self {
+            code: code.to_string(),
+            exchange,
+            asset_type,
+            name: name.filter(|n| !n.is_empty()).unwrap_or(default_name),
+        })
+    }
+}
+
+/// Infer the exchange of an unsuffixed code from its prefix
+fn infer_exchange(code: &str) -> Exchange {
+    match &code[..1] {
+        "5" | "6" | "9" => Exchange::Shanghai,
+        _ => Exchange::Shenzhen,
+    }
+}
+
+/// Infer the asset type of a code from its prefix and exchange
+fn infer_asset_type(code: &str, exchange: Exchange) -> AssetType {
+    match exchange {
+        Exchange::Shanghai if code.starts_with('6') || code.starts_with('9') => AssetType::Stock,
+        Exchange::Shanghai if code.starts_with('5') => AssetType::Etf,
+        Exchange::Shanghai => AssetType::Index,
+        Exchange::Shenzhen if code.starts_with("399") => AssetType::Index,
+        Exchange::Shenzhen if code.starts_with("15") || code.starts_with("16") => AssetType::Etf,
+        Exchange::Shenzhen => AssetType::Stock,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StockData {
     pub date: DateTime<Utc>,
     pub open: f64,
@@ -9,10 +289,37 @@ pub struct StockData {
     pub low: f64,
     pub close: f64,
     pub volume: u64,
+    /// Turnover as reported by the provider (TuShare: thousand CNY)
+    #[serde(default)]
+    pub amount: Option<f64>,
+}
+
+impl StockData {
+    /// Trading date of the bar in the market timezone
+    pub fn trade_date(&self) -> NaiveDate {
+        market_date(self.date)
+    }
+}
+
+/// Bars fetched for an instrument, together with the source that served them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketData {
+    pub instrument: Instrument,
+    /// Name of the data source that served the bars
+    pub source: String,
+    /// Whether the bars are fabricated mock data rather than real prices
+    pub is_mock: bool,
+    pub bars: Vec<StockData>,
+    /// What validation repaired, dropped or flagged in the fetched bars
+    pub validation: ValidationReport,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DailyAnalysis {
+    pub instrument: Instrument,
+    pub data_source: String,
+    pub is_mock: bool,
+    pub data_quality: ValidationReport,
     pub date: DateTime<Utc>,
     pub current_price: f64,
     pub previous_price: f64,
@@ -22,81 +329,338 @@ pub struct DailyAnalysis {
     pub historical_high: f64,
     pub historical_low: f64,
     pub volume: u64,
+    pub indicators: IndicatorSnapshot,
+    /// Names of the signal rules that hold at the latest bar
+    pub signals: Vec<String>,
+    /// PE/PB and their historical percentiles, for indexes TuShare covers
+    pub valuation: Option<Valuation>,
 }
 
+/// Periods of the moving averages in the daily indicator snapshot
+pub const SNAPSHOT_MA_PERIODS: [usize; 6] = [5, 10, 20, 60, 120, 250];
+
+/// A moving average at the latest bar and where the close sits against it
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WeeklyAnalysis {
+pub struct MovingAverage {
+    pub period: usize,
+    pub value: f64,
+    /// Distance of the close from the average, in percent of the average
+    pub distance_pct: f64,
+}
+
+/// Where the MACD line stands against its signal line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MacdCross {
+    /// Crossed above the signal line on the latest bar
+    Bullish,
+    /// Crossed below the signal line on the latest bar
+    Bearish,
+    Above,
+    Below,
+}
+
+impl fmt::Display for MacdCross {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            MacdCross::Bullish => "bullish crossover today",
+            MacdCross::Bearish => "bearish crossover today",
+            MacdCross::Above => "above signal",
+            MacdCross::Below => "below signal",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Technical indicators at the latest bar
+///
+/// Indicators without enough history are left out: moving averages are only
+/// listed once the series covers their period.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndicatorSnapshot {
+    pub moving_averages: Vec<MovingAverage>,
+    /// RSI(14)
+    pub rsi: Option<f64>,
+    /// MACD(12, 26, 9)
+    pub macd: Option<Macd>,
+    pub macd_cross: Option<MacdCross>,
+    /// Bollinger %B over 20 bars and 2 standard deviations
+    pub bollinger_percent_b: Option<f64>,
+    /// KDJ(9, 3, 3)
+    pub kdj: Option<Kdj>,
+}
+
+impl fmt::Display for IndicatorSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for ma in &self.moving_averages {
+            writeln!(
+                f,
+                "MA{}: {:.2} (price {:+.2}%, {})",
+                ma.period,
+                ma.value,
+                ma.distance_pct,
+                if ma.distance_pct >= 0.0 {
+                    "above"
+                } else {
+                    "below"
+                }
+            )?;
+        }
+        match self.rsi {
+            Some(rsi) => writeln!(f, "RSI(14): {:.1}", rsi)?,
+            None => writeln!(f, "RSI(14): n/a")?,
+        }
+        match (self.macd, self.macd_cross) {
+            (Some(macd), Some(cross)) => writeln!(
+                f,
+                "MACD(12,26,9): {:.2}, Signal {:.2}, Histogram {:.2} ({})",
+                macd.macd, macd.signal, macd.histogram, cross
+            )?,
+            (Some(macd), None) => writeln!(
+                f,
+                "MACD(12,26,9): {:.2}, Signal {:.2}, Histogram {:.2}",
+                macd.macd, macd.signal, macd.histogram
+            )?,
+            _ => writeln!(f, "MACD(12,26,9): n/a")?,
+        }
+        match self.bollinger_percent_b {
+            Some(percent_b) => writeln!(f, "Bollinger %B(20,2): {:.2}", percent_b)?,
+            None => writeln!(f, "Bollinger %B(20,2): n/a")?,
+        }
+        match self.kdj {
+            Some(kdj) => write!(
+                f,
+                "KDJ(9,3,3): K {:.1}, D {:.1}, J {:.1}",
+                kdj.k, kdj.d, kdj.j
+            ),
+            None => write!(f, "KDJ(9,3,3): n/a"),
+        }
+    }
+}
+
+/// Bars of a reporting period summarized: the move from its first to its
+/// last bar, the extremes and the traded volume, with risk and valuation as
+/// of the last bar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodSummary {
     pub start_date: DateTime<Utc>,
     pub end_date: DateTime<Utc>,
     pub start_price: f64,
     pub end_price: f64,
-    pub weekly_change_pct: f64,
+    /// Change from the first to the last close, in percent
+    pub change_pct: f64,
     pub highest_price: f64,
     pub highest_date: DateTime<Utc>,
     pub lowest_price: f64,
     pub lowest_date: DateTime<Utc>,
     pub average_volume: f64,
     pub total_volume: u64,
+    /// Risk over the trailing lookback ending with the last bar, `None`
+    /// without enough history
+    pub risk: Option<RiskMetrics>,
+    /// Valuation at the last bar, for indexes TuShare covers
+    pub valuation: Option<Valuation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeeklyAnalysis {
+    pub instrument: Instrument,
+    pub data_source: String,
+    pub is_mock: bool,
+    pub data_quality: ValidationReport,
+    #[serde(flatten)]
+    pub summary: PeriodSummary,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonthlyAnalysis {
+    pub instrument: Instrument,
+    pub data_source: String,
+    pub is_mock: bool,
+    pub data_quality: ValidationReport,
     pub year: i32,
     pub month: u32,
-    pub start_date: DateTime<Utc>,
-    pub end_date: DateTime<Utc>,
-    pub start_price: f64,
-    pub end_price: f64,
-    pub monthly_change_pct: f64,
-    pub highest_price: f64,
-    pub highest_date: DateTime<Utc>,
-    pub lowest_price: f64,
-    pub lowest_date: DateTime<Utc>,
-    pub average_volume: f64,
-    pub total_volume: u64,
+    /// Whether the last bar is the month's last trading day
+    pub complete: bool,
+    #[serde(flatten)]
+    pub summary: PeriodSummary,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ApiResponse {
-    pub code: String,
-    pub data: Vec<StockData>,
-    pub message: Option<String>,
+pub struct QuarterlyAnalysis {
+    pub instrument: Instrument,
+    pub data_source: String,
+    pub is_mock: bool,
+    pub data_quality: ValidationReport,
+    pub year: i32,
+    /// Quarter of the year, 1-4
+    pub quarter: u32,
+    /// Whether the last bar is the quarter's last trading day
+    pub complete: bool,
+    #[serde(flatten)]
+    pub summary: PeriodSummary,
 }
 
+/// Envelope of every TuShare Pro API response
+///
+/// `code` is `0` on success, in which case `data` holds a column-oriented
+/// table.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct EmailConfig {
-    pub smtp_server: String,
-    pub smtp_port: u16,
-    pub username: String,
-    pub password: String,
-    pub from_email: String,
-    pub to_emails: Vec<String>,
+pub struct TushareResponse {
+    pub code: i64,
+    pub msg: Option<String>,
+    pub data: Option<TushareTable>,
 }
 
+/// Column-oriented TuShare table: `items` rows are ordered like `fields`
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GeminiConfig {
-    pub api_key: String,
-    pub model: String,
-}
-
-impl Default for EmailConfig {
-    fn default() -> Self {
-        Self {
-            smtp_server: "smtp.gmail.com".to_string(),
-            smtp_port: 587,
-            username: "".to_string(),
-            password: "".to_string(),
-            from_email: "".to_string(),
-            to_emails: vec![],
-        }
-    }
+pub struct TushareTable {
+    pub fields: Vec<String>,
+    pub items: Vec<Vec<Value>>,
 }
 
-impl Default for GeminiConfig {
-    fn default() -> Self {
-        Self {
-            api_key: "".to_string(),
-            model: "gemini-pro".to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn instrument(s: &str) -> Instrument {
+        s.parse()
+            .unwrap_or_else(|e| panic!("'{}' did not parse: {:#}", s, e))
+    }
+
+    fn parse_error(s: &str) -> String {
+        match s.parse::<Instrument>() {
+            Ok(instrument) => panic!("'{}' parsed as {}", s, instrument),
+            Err(e) => e.to_string(),
         }
     }
+
+    #[test]
+    fn parses_known_instruments() {
+        let csi300 = instrument("000300.SH");
+        assert_eq!(csi300, Instrument::csi300());
+        assert_eq!(csi300.exchange, Exchange::Shanghai);
+        assert_eq!(csi300.asset_type, AssetType::Index);
+        assert_eq!(csi300.to_string(), "CSI 300 Index (000300.SH)");
+
+        // Without a suffix, or with Alpha Vantage's or a lowercase one
+        assert_eq!(instrument("000300"), csi300);
+        assert_eq!(instrument(" 000300.ss "), csi300);
+        assert_eq!(instrument("000300.SSE"), csi300);
+
+        let etf = instrument("159919");
+        assert_eq!(etf.exchange, Exchange::Shenzhen);
+        assert_eq!(etf.asset_type, AssetType::Etf);
+        assert_eq!(etf.name, "Harvest CSI 300 ETF");
+    }
+
+    #[test]
+    fn infers_unknown_instruments_from_their_code() {
+        let kinds: Vec<(Exchange, AssetType)> = [
+            "600519",
+            "688981",
+            "512880",
+            "000001.SH",
+            "000001",
+            "300750",
+            "399300",
+            "161725",
+        ]
+        .into_iter()
+        .map(|s| {
+            let instrument = instrument(s);
+            (instrument.exchange, instrument.asset_type)
+        })
+        .collect();
+
+        assert_eq!(
+            kinds,
+            [
+                (Exchange::Shanghai, AssetType::Stock),
+                (Exchange::Shanghai, AssetType::Stock),
+                (Exchange::Shanghai, AssetType::Etf),
+                (Exchange::Shanghai, AssetType::Index),
+                (Exchange::Shenzhen, AssetType::Stock),
+                (Exchange::Shenzhen, AssetType::Stock),
+                (Exchange::Shenzhen, AssetType::Index),
+                (Exchange::Shenzhen, AssetType::Etf),
+            ]
+        );
+        // Unknown instruments are named after their code
+        assert_eq!(instrument("600519").name, "600519");
+    }
+
+    #[test]
+    fn takes_a_name_after_a_colon() {
+        let moutai = instrument("600519.SH:Kweichow Moutai");
+        assert_eq!(moutai.name, "Kweichow Moutai");
+        assert_eq!(moutai.to_string(), "Kweichow Moutai (600519.SH)");
+
+        assert_eq!(instrument("000300:My Index").name, "My Index");
+        assert_eq!(instrument("000300.SH: ").name, "CSI 300 Index");
+    }
+
+    #[test]
+    fn formats_provider_codes_and_price_units() {
+        let csi300 = Instrument::csi300();
+        assert_eq!(csi300.tushare_code(), "000300.SH");
+        assert_eq!(csi300.alpha_vantage_symbol(), "000300.SS");
+        assert_eq!(csi300.price_unit(), "pts");
+
+        let stock = instrument("000001.SZ");
+        assert_eq!(stock.tushare_code(), "000001.SZ");
+        assert_eq!(stock.alpha_vantage_symbol(), "000001.SZ");
+        assert_eq!(stock.price_unit(), "CNY");
+    }
+
+    #[test]
+    fn rejects_invalid_instruments() {
+        assert_eq!(parse_error("00300"), "Invalid instrument code: 00300");
+        assert_eq!(parse_error("HS300"), "Invalid instrument code: HS300");
+        assert_eq!(
+            parse_error("0003001.SH"),
+            "Invalid instrument code: 0003001.SH"
+        );
+        assert_eq!(parse_error("000300.HK"), "Unknown exchange suffix: HK");
+        assert_eq!(parse_error(""), "Invalid instrument code: ");
+    }
+
+    #[test]
+    fn dates_instants_in_the_market_timezone() {
+        // Shanghai is UTC+8 all year
+        assert_eq!(market_date(utc("2025-01-01T15:59:59Z")), date("2025-01-01"));
+        assert_eq!(market_date(utc("2025-01-01T16:00:00Z")), date("2025-01-02"));
+        assert_eq!(
+            market_datetime(
+                date("2025-01-02"),
+                NaiveTime::from_hms_opt(0, 30, 0).unwrap()
+            ),
+            utc("2025-01-01T16:30:00Z")
+        );
+    }
+
+    #[test]
+    fn stamps_bars_at_the_market_close() {
+        let close = market_close_utc(date("2025-01-02"));
+        assert_eq!(close, utc("2025-01-02T07:00:00Z"));
+
+        let bar = StockData {
+            date: close,
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 0,
+            amount: None,
+        };
+        assert_eq!(bar.trade_date(), date("2025-01-02"));
+    }
 }
@@ -0,0 +1,399 @@
+use crate::http::env_parse;
+use crate::models::market_today;
+use crate::store::{data_dir, write_atomic};
+use anyhow::{Context, Result, anyhow};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Call limits of one provider
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimits {
+    /// Calls allowed per minute, `None` for no limit
+    pub per_minute: Option<u32>,
+    /// Calls allowed per market day, `None` for no limit
+    pub daily_quota: Option<u32>,
+}
+
+impl RateLimits {
+    /// Defaults matching the providers' free tiers
+    fn defaults(provider: &str) -> Self {
+        match provider {
+            "Alpha Vantage" => Self {
+                per_minute: Some(5),
+                daily_quota: Some(25),
+            },
+            "TuShare" => Self {
+                per_minute: Some(50),
+                daily_quota: None,
+            },
+            _ => Self::default(),
+        }
+    }
+
+    /// Defaults overridden by `<PROVIDER>_RATE_PER_MINUTE` and
+    /// `<PROVIDER>_DAILY_QUOTA`, where 0 removes the limit
+    ///
+    /// The prefix is the provider name in upper case with spaces replaced by
+    /// underscores, e.g. `ALPHA_VANTAGE_DAILY_QUOTA`.
+    pub fn from_env(provider: &str) -> Self {
+        let prefix = provider.to_ascii_uppercase().replace(' ', "_");
+        let defaults = Self::defaults(provider);
+        let limit = |name: String, default: Option<u32>| match env_parse::<u32>(&name) {
+            Some(0) => None,
+            Some(limit) => Some(limit),
+            None => default,
+        };
+
+        Self {
+            per_minute: limit(format!("{}_RATE_PER_MINUTE", prefix), defaults.per_minute),
+            daily_quota: limit(format!("{}_DAILY_QUOTA", prefix), defaults.daily_quota),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket holding a minute's worth of calls
+    fn per_minute(limit: u32, now: Instant) -> Self {
+        let capacity = f64::from(limit);
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: capacity / 60.0,
+            refilled_at: now,
+        }
+    }
+
+    /// Take a token at `now`, or return how long to wait until one is
+    /// available
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+}
+
+/// Calls made per provider on one market day, in `$DATA_DIR/quotas.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuotaUsage {
+    date: Option<NaiveDate>,
+    calls: BTreeMap<String, u32>,
+}
+
+impl QuotaUsage {
+    /// Usage of `today` recorded in `path`; counters of an earlier day are
+    /// dropped
+    fn load(path: &Path, today: NaiveDate) -> Result<Self> {
+        let usage: Self = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+
+        Ok(if usage.date == Some(today) {
+            usage
+        } else {
+            Self {
+                date: Some(today),
+                calls: BTreeMap::new(),
+            }
+        })
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    fn used(&self, provider: &str) -> u32 {
+        self.calls.get(provider).copied().unwrap_or(0)
+    }
+}
+
+/// Serializes reads and writes of the quota file within the process
+static QUOTA_FILE: Mutex<()> = Mutex::new(());
+
+fn load_usage(path: &Path, today: NaiveDate) -> QuotaUsage {
+    QuotaUsage::load(path, today).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable quota counters: {:#}", e);
+        QuotaUsage {
+            date: Some(today),
+            calls: BTreeMap::new(),
+        }
+    })
+}
+
+/// Per-minute token bucket and persistent daily quota of one provider
+///
+/// Every HTTP call to the provider, retries included, first goes through
+/// [`RateLimiter::acquire`]. Daily counters are kept on disk so that they
+/// survive restarts and are shared with manual runs next to the daemon.
+pub struct RateLimiter {
+    provider: &'static str,
+    limits: RateLimits,
+    bucket: Option<Mutex<TokenBucket>>,
+    /// Daily counters, `$DATA_DIR/quotas.json`
+    quota_file: PathBuf,
+}
+
+impl RateLimiter {
+    pub fn new(provider: &'static str, limits: RateLimits) -> Self {
+        Self {
+            provider,
+            limits,
+            bucket: limits
+                .per_minute
+                .map(|limit| Mutex::new(TokenBucket::per_minute(limit, Instant::now()))),
+            quota_file: data_dir().join("quotas.json"),
+        }
+    }
+
+    /// Same limiter counting calls in `path` instead
+    #[cfg(test)]
+    pub fn with_quota_file(self, path: PathBuf) -> Self {
+        Self {
+            quota_file: path,
+            ..self
+        }
+    }
+
+    /// Shared limiter of a provider, configured from the environment
+    pub fn for_provider(provider: &'static str) -> Arc<RateLimiter> {
+        static LIMITERS: OnceLock<Mutex<HashMap<&'static str, Arc<RateLimiter>>>> = OnceLock::new();
+
+        let mut limiters = LIMITERS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        Arc::clone(limiters.entry(provider).or_insert_with(|| {
+            Arc::new(RateLimiter::new(provider, RateLimits::from_env(provider)))
+        }))
+    }
+
+    /// Calls left today, `None` without a daily quota
+    pub fn remaining_today(&self) -> Option<u32> {
+        self.remaining_on(market_today())
+    }
+
+    /// Calls left on a market day, `None` without a daily quota
+    fn remaining_on(&self, today: NaiveDate) -> Option<u32> {
+        let quota = self.limits.daily_quota?;
+        let _file = QUOTA_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        Some(quota.saturating_sub(load_usage(&self.quota_file, today).used(self.provider)))
+    }
+
+    /// Whether the daily quota is used up
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining_today() == Some(0)
+    }
+
+    /// Wait for a call slot and count the call against the daily quota
+    ///
+    /// Fails without waiting once the daily quota is used up.
+    pub async fn acquire(&self) -> Result<()> {
+        if self.is_exhausted() {
+            return Err(self.exhausted_error());
+        }
+
+        if let Some(bucket) = &self.bucket {
+            loop {
+                let wait = bucket
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .try_take(Instant::now());
+                match wait {
+                    Ok(()) => break,
+                    Err(wait) => {
+                        debug!(
+                            "{} rate limit reached, waiting {} ms",
+                            self.provider,
+                            wait.as_millis()
+                        );
+                        tokio::time::sleep(wait).await;
+                    }
+                }
+            }
+        }
+
+        self.record_call(market_today())
+    }
+
+    /// Count a call on a market day, failing if another caller used up the
+    /// quota meanwhile
+    fn record_call(&self, today: NaiveDate) -> Result<()> {
+        let Some(quota) = self.limits.daily_quota else {
+            return Ok(());
+        };
+
+        let _file = QUOTA_FILE.lock().unwrap_or_else(|e| e.into_inner());
+        let mut usage = load_usage(&self.quota_file, today);
+        let used = usage.used(self.provider);
+        if used >= quota {
+            return Err(self.exhausted_error());
+        }
+
+        usage.calls.insert(self.provider.to_string(), used + 1);
+        if let Err(e) = usage.save(&self.quota_file) {
+            warn!("Failed to record {} call: {:#}", self.provider, e);
+        }
+
+        let remaining = quota - used - 1;
+        if remaining == 0 {
+            info!("{} daily quota of {} calls used up", self.provider, quota);
+        } else {
+            debug!("{} calls left today: {}", self.provider, remaining);
+        }
+        Ok(())
+    }
+
+    fn exhausted_error(&self) -> anyhow::Error {
+        anyhow!(
+            "{} daily quota of {} calls used up, resets next market day",
+            self.provider,
+            self.limits.daily_quota.unwrap_or(0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn limiter(daily_quota: Option<u32>, quota_file: &Path) -> RateLimiter {
+        let limits = RateLimits {
+            per_minute: None,
+            daily_quota,
+        };
+        RateLimiter::new("Test", limits).with_quota_file(quota_file.to_path_buf())
+    }
+
+    #[test]
+    fn bucket_starts_full_and_refills_over_the_minute() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut bucket = TokenBucket::per_minute(5, start);
+
+        for _ in 0..5 {
+            assert_eq!(bucket.try_take(start), Ok(()));
+        }
+        // One token refills every 12 seconds
+        assert_eq!(bucket.try_take(start), Err(Duration::from_secs(12)));
+        assert_eq!(bucket.try_take(at(6)), Err(Duration::from_secs(6)));
+        assert_eq!(bucket.try_take(at(12)), Ok(()));
+        assert_eq!(bucket.try_take(at(12)), Err(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn bucket_holds_at_most_a_minute_of_calls() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::per_minute(5, start);
+        assert_eq!(bucket.try_take(start), Ok(()));
+
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..5 {
+            assert_eq!(bucket.try_take(later), Ok(()));
+        }
+        assert!(bucket.try_take(later).is_err());
+    }
+
+    #[test]
+    fn counts_calls_against_the_daily_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let limiter = limiter(Some(2), &dir.path().join("quotas.json"));
+        let today = date("2025-09-30");
+
+        assert_eq!(limiter.remaining_on(today), Some(2));
+        limiter.record_call(today).unwrap();
+        assert_eq!(limiter.remaining_on(today), Some(1));
+        limiter.record_call(today).unwrap();
+        assert_eq!(limiter.remaining_on(today), Some(0));
+
+        let error = limiter.record_call(today).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Test daily quota of 2 calls used up, resets next market day"
+        );
+        assert_eq!(limiter.remaining_on(today), Some(0));
+    }
+
+    #[test]
+    fn quota_resets_on_the_next_market_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotas.json");
+        let limiter = limiter(Some(2), &path);
+
+        limiter.record_call(date("2025-09-30")).unwrap();
+        limiter.record_call(date("2025-09-30")).unwrap();
+        assert_eq!(limiter.remaining_on(date("2025-10-01")), Some(2));
+
+        limiter.record_call(date("2025-10-01")).unwrap();
+        let usage = QuotaUsage::load(&path, date("2025-10-01")).unwrap();
+        assert_eq!(usage.date, Some(date("2025-10-01")));
+        assert_eq!(usage.used("Test"), 1);
+    }
+
+    #[test]
+    fn persists_counters_across_limiters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotas.json");
+        let today = date("2025-09-30");
+
+        limiter(Some(3), &path).record_call(today).unwrap();
+        // E.g. a manual run next to the daemon, or the daemon after a restart
+        let other = limiter(Some(3), &path);
+        assert_eq!(other.remaining_on(today), Some(2));
+
+        let usage: QuotaUsage =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(usage.date, Some(today));
+        assert_eq!(usage.calls.get("Test"), Some(&1));
+    }
+
+    #[test]
+    fn ignores_unreadable_counters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotas.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        let limiter = limiter(Some(2), &path);
+        assert_eq!(limiter.remaining_on(date("2025-09-30")), Some(2));
+        limiter.record_call(date("2025-09-30")).unwrap();
+        assert_eq!(limiter.remaining_on(date("2025-09-30")), Some(1));
+    }
+
+    #[test]
+    fn calls_without_a_quota_are_not_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quotas.json");
+        let limiter = limiter(None, &path);
+
+        assert_eq!(limiter.remaining_on(date("2025-09-30")), None);
+        limiter.record_call(date("2025-09-30")).unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,847 @@
+use crate::calendar::{Period, TradingCalendar};
+use crate::data_fetcher::{self, DataUnavailable};
+use crate::models::{self, Instrument, MarketData, market_date, market_today};
+use crate::risk::RiskMetrics;
+use crate::valuation::{self, Valuation, ValuationPoint};
+use crate::{alerts, analyzer, email_sender, gemini_client};
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use tracing::{error, info, warn};
+
+/// Report modes accepted by `--mode` and by scheduled jobs
+pub const MODES: &[&str] = &["daily", "weekly", "monthly", "quarterly"];
+
+/// Banner added to every report built from mock data
+const MOCK_DATA_WATERMARK: &str =
+    "⚠️ MOCK DATA - the prices in this report are simulated, NOT real market data ⚠️";
+
+/// Subject prefix for emails carrying a report built from mock data
+const MOCK_DATA_SUBJECT_PREFIX: &str = "[MOCK DATA] ";
+
+/// What to do when no real data source can serve an instrument in strict mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnDataUnavailable {
+    /// Fail the run with a non-zero exit code
+    Fail,
+    /// Send an explicit "data unavailable" notice instead of a report
+    Notify,
+}
+
+/// Options shared by all analysis runs
+#[derive(Debug, Clone, Copy)]
+pub struct RunOptions {
+    pub send_email: bool,
+    pub allow_mock: bool,
+    pub on_data_unavailable: OnDataUnavailable,
+    /// Send emails even if the same report was already delivered
+    pub force: bool,
+}
+
+impl RunOptions {
+    /// Same options with email delivery disabled, for nested runs
+    fn without_email(&self) -> Self {
+        Self {
+            send_email: false,
+            ..*self
+        }
+    }
+}
+
+/// Result of a report run for one instrument
+#[derive(Debug, Clone)]
+pub enum ReportOutcome {
+    /// A report was generated, and emailed when requested
+    Generated { data_source: String, report: String },
+    /// No data source could serve the instrument; a notice was sent instead
+    DataUnavailable,
+}
+
+/// Run a report mode for every instrument
+///
+/// `job` names the run for email delivery deduplication: each report is
+/// emailed at most once per job, instrument, trading date and recipient list
+/// unless [`RunOptions::force`] is set.
+///
+/// An instrument that fails does not stop the others; the run then fails
+/// with every instrument's error once all of them were tried.
+pub async fn run_mode(
+    job: &str,
+    mode: &str,
+    instruments: &[Instrument],
+    options: &RunOptions,
+) -> Result<Vec<ReportOutcome>> {
+    if !MODES.contains(&mode) {
+        bail!(
+            "Invalid mode: {}. Supported modes: {}",
+            mode,
+            MODES.join(", ")
+        );
+    }
+
+    let outcomes = run_each(mode, instruments, |instrument| async move {
+        match mode {
+            "daily" => run_daily_analysis(job, instrument, options).await,
+            "weekly" => run_period_analysis(Period::Week, job, instrument, options).await,
+            "monthly" => run_period_analysis(Period::Month, job, instrument, options).await,
+            _ => run_period_analysis(Period::Quarter, job, instrument, options).await,
+        }
+    })
+    .await?;
+
+    info!(
+        "{} analysis completed for {} instrument(s)",
+        mode,
+        instruments.len()
+    );
+    Ok(outcomes)
+}
+
+/// Run a report for every instrument, logging failures and going on with
+/// the next instrument
+///
+/// Fails with the errors of all failed instruments after the last one ran.
+async fn run_each<'a, F, Fut>(
+    mode: &str,
+    instruments: &'a [Instrument],
+    mut run: F,
+) -> Result<Vec<ReportOutcome>>
+where
+    F: FnMut(&'a Instrument) -> Fut,
+    Fut: Future<Output = Result<ReportOutcome>>,
+{
+    let mut outcomes = Vec::with_capacity(instruments.len());
+    let mut failures = Vec::new();
+
+    for instrument in instruments {
+        match run(instrument).await {
+            Ok(outcome) => outcomes.push(outcome),
+            Err(e) => {
+                error!("{} analysis failed for {}: {:#}", mode, instrument, e);
+                failures.push(format!("{}: {:#}", instrument, e));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        bail!(
+            "{} analysis failed for {} of {} instrument(s):\n- {}",
+            mode,
+            failures.len(),
+            instruments.len(),
+            failures.join("\n- ")
+        );
+    }
+    Ok(outcomes)
+}
+
+/// Handle a failed fetch according to `--on-data-unavailable`
+///
+/// Returns [`ReportOutcome::DataUnavailable`] when the failure was turned into
+/// a notice, so the run can end without a report. Other errors are passed
+/// through unchanged.
+async fn handle_fetch_error(
+    job: &str,
+    report_name: &str,
+    instrument: &Instrument,
+    err: anyhow::Error,
+    options: &RunOptions,
+) -> Result<ReportOutcome> {
+    let Some(unavailable) = err.downcast_ref::<DataUnavailable>() else {
+        return Err(err);
+    };
+
+    if options.on_data_unavailable == OnDataUnavailable::Fail {
+        error!("{}", unavailable);
+        return Err(err);
+    }
+
+    warn!("{}, sending data unavailable notice", unavailable);
+
+    let notice = format!(
+        "⚠️ {} Unavailable - {}\n\n\
+        No real market data could be fetched for {}, so no report was generated.\n\
+        Mock data was not used to avoid sending fabricated prices.\n\n\
+        Data source errors:\n- {}\n",
+        report_name,
+        instrument,
+        instrument,
+        unavailable.errors.join("\n- ")
+    );
+    println!("{}", notice);
+
+    if options.send_email {
+        let subject = format!(
+            "⚠️ Data Unavailable - {} - {}",
+            report_name, instrument.name
+        );
+        let trade_date = TradingCalendar::global().latest_trading_day(market_today());
+        let delivery_job = format!("{}:unavailable:{}", job, instrument.tushare_code());
+        if email_sender::send_email_once(
+            &delivery_job,
+            trade_date,
+            &subject,
+            &notice,
+            options.force,
+        )
+        .await?
+        {
+            info!("Data unavailable notice sent");
+        }
+    }
+
+    Ok(ReportOutcome::DataUnavailable)
+}
+
+/// Build an email subject, flagging reports built from mock data
+fn email_subject(report_name: &str, instrument: &Instrument, is_mock: bool) -> String {
+    let prefix = if is_mock {
+        MOCK_DATA_SUBJECT_PREFIX
+    } else {
+        ""
+    };
+    format!("{}{} - {}", prefix, report_name, instrument.name)
+}
+
+/// Wrap a report with the mock data watermark when needed
+fn watermark(report: String, is_mock: bool) -> String {
+    if is_mock {
+        format!(
+            "{}\n\n{}\n{}\n",
+            MOCK_DATA_WATERMARK, report, MOCK_DATA_WATERMARK
+        )
+    } else {
+        report
+    }
+}
+
+/// Daily bars of an instrument and its valuation history, fetched once per
+/// run and shared by the daily report, alerts and period reports
+struct DailyData {
+    data: MarketData,
+    valuation_history: Option<Vec<ValuationPoint>>,
+}
+
+/// Fetch an instrument's daily data and check the alert rules against it
+///
+/// Every run checks alerts exactly once, with its own `send_email`, so that
+/// period runs notify like daily runs do. Fetch errors are passed through for
+/// [`handle_fetch_error`].
+async fn fetch_daily_data(instrument: &Instrument, options: &RunOptions) -> Result<DailyData> {
+    let data = data_fetcher::fetch_data(instrument, options.allow_mock).await?;
+    info!(
+        "Retrieved {} data points from {}",
+        data.bars.len(),
+        data.source
+    );
+
+    // Check alert rules against the fresh bars; alerting never fails the report
+    match alerts::check(&data, options.send_email).await {
+        Ok(fired) if !fired.is_empty() => info!("{} alert(s) fired", fired.len()),
+        Ok(_) => {}
+        Err(e) => warn!("Failed to check alert rules: {:#}", e),
+    }
+
+    let valuation_history = valuation_history(instrument, &data).await;
+    Ok(DailyData {
+        data,
+        valuation_history,
+    })
+}
+
+/// Execute daily investment analysis
+///
+/// Fetches current instrument data, performs technical analysis,
+/// generates AI-powered insights, and optionally sends email notifications.
+async fn run_daily_analysis(
+    job: &str,
+    instrument: &Instrument,
+    options: &RunOptions,
+) -> Result<ReportOutcome> {
+    info!("Starting daily analysis for {}", instrument);
+
+    let daily = match fetch_daily_data(instrument, options).await {
+        Ok(daily) => daily,
+        Err(e) => {
+            return handle_fetch_error(
+                job,
+                "Daily Investment Analysis Report",
+                instrument,
+                e,
+                options,
+            )
+            .await;
+        }
+    };
+    daily_report(job, &daily, options).await
+}
+
+/// Analyze daily data and print the daily report, emailing it when requested
+async fn daily_report(job: &str, daily: &DailyData, options: &RunOptions) -> Result<ReportOutcome> {
+    let instrument = &daily.data.instrument;
+
+    // Analyze data
+    let analysis =
+        analyzer::analyze_daily_data(&daily.data, daily.valuation_history.as_deref()).await?;
+    info!(
+        "Analysis completed, price change: {:.2}%",
+        analysis.price_change_pct
+    );
+
+    // Generate intelligent analysis using Gemini
+    let gemini_analysis = gemini_client::generate_daily_analysis(&analysis).await?;
+    info!("Gemini analysis completed");
+
+    // Generate summary report
+    let report = format_daily_report(&analysis, &gemini_analysis);
+    println!("{}", report);
+
+    // Send email notification
+    if options.send_email {
+        let subject = email_subject(
+            "Daily Investment Analysis Report",
+            instrument,
+            analysis.is_mock,
+        );
+        let delivery_job = format!("{}:{}", job, instrument.tushare_code());
+        let trade_date = market_date(analysis.date);
+        if email_sender::send_email_once(
+            &delivery_job,
+            trade_date,
+            &subject,
+            &report,
+            options.force,
+        )
+        .await?
+        {
+            info!("Email sent successfully");
+        }
+    }
+
+    Ok(ReportOutcome::Generated {
+        data_source: analysis.data_source,
+        report,
+    })
+}
+
+/// A period report ready to print and email
+struct PeriodReport {
+    report: String,
+    data_source: String,
+    is_mock: bool,
+    end_date: DateTime<Utc>,
+}
+
+/// Name of a period's report, used in titles and email subjects
+fn period_report_name(period: Period) -> &'static str {
+    match period {
+        Period::Week => "Weekly Investment Analysis Report",
+        Period::Month => "Monthly Investment Analysis Report",
+        Period::Quarter => "Quarterly Investment Analysis Report",
+        Period::Year => "Yearly Investment Analysis Report",
+    }
+}
+
+/// Execute weekly, monthly or quarterly investment analysis
+///
+/// Performs both daily analysis and the period's own analysis: the weekly
+/// trend and volume, the month's long-term trend and outlook, or the
+/// quarter-to-date review and the outlook for the next quarter.
+async fn run_period_analysis(
+    period: Period,
+    job: &str,
+    instrument: &Instrument,
+    options: &RunOptions,
+) -> Result<ReportOutcome> {
+    let report_name = period_report_name(period);
+    info!("Starting {} for {}", report_name, instrument);
+
+    // Also execute daily analysis; only the period report is emailed, but
+    // alerts are checked with the run's own options. The daily data also
+    // feeds the period's risk metrics and valuation.
+    let daily = match fetch_daily_data(instrument, options).await {
+        Ok(daily) => daily,
+        Err(e) => return handle_fetch_error(job, report_name, instrument, e, options).await,
+    };
+    daily_report(job, &daily, &options.without_email()).await?;
+
+    // Fetch the period's data for the period analysis
+    let fetched = match period {
+        Period::Week => data_fetcher::fetch_weekly_data(instrument, options.allow_mock).await,
+        Period::Month => data_fetcher::fetch_monthly_data(instrument, options.allow_mock).await,
+        Period::Quarter => data_fetcher::fetch_quarterly_data(instrument, options.allow_mock).await,
+        Period::Year => bail!("Yearly reports are not supported"),
+    };
+    let period_data = match fetched {
+        Ok(data) => data,
+        Err(e) => return handle_fetch_error(job, report_name, instrument, e, options).await,
+    };
+    let (history, valuation_history) = period_context(&daily, &period_data);
+    let report = period_report(period, &period_data, history, valuation_history).await?;
+
+    println!("{}", report.report);
+
+    if options.send_email {
+        let subject = email_subject(report_name, instrument, report.is_mock);
+        let delivery_job = format!("{}:{}", job, instrument.tushare_code());
+        let trade_date = market_date(report.end_date);
+        email_sender::send_email_once(
+            &delivery_job,
+            trade_date,
+            &subject,
+            &report.report,
+            options.force,
+        )
+        .await?;
+    }
+
+    Ok(ReportOutcome::Generated {
+        data_source: report.data_source,
+        report: report.report,
+    })
+}
+
+/// Analyze a period's data and format its report with the AI analysis
+async fn period_report(
+    period: Period,
+    data: &MarketData,
+    history: Option<&MarketData>,
+    valuation_history: Option<&[ValuationPoint]>,
+) -> Result<PeriodReport> {
+    let report = match period {
+        Period::Week => {
+            let analysis = analyzer::analyze_weekly_data(data, history, valuation_history).await?;
+            let gemini_analysis = gemini_client::generate_weekly_analysis(&analysis).await?;
+            PeriodReport {
+                report: format_weekly_report(&analysis, &gemini_analysis),
+                data_source: analysis.data_source,
+                is_mock: analysis.is_mock,
+                end_date: analysis.summary.end_date,
+            }
+        }
+        Period::Month => {
+            let analysis = analyzer::analyze_monthly_data(data, history, valuation_history).await?;
+            let gemini_analysis = gemini_client::generate_monthly_analysis(&analysis).await?;
+            PeriodReport {
+                report: format_monthly_report(&analysis, &gemini_analysis),
+                data_source: analysis.data_source,
+                is_mock: analysis.is_mock,
+                end_date: analysis.summary.end_date,
+            }
+        }
+        Period::Quarter => {
+            let analysis =
+                analyzer::analyze_quarterly_data(data, history, valuation_history).await?;
+            let gemini_analysis = gemini_client::generate_quarterly_analysis(&analysis).await?;
+            PeriodReport {
+                report: format_quarterly_report(&analysis, &gemini_analysis),
+                data_source: analysis.data_source,
+                is_mock: analysis.is_mock,
+                end_date: analysis.summary.end_date,
+            }
+        }
+        Period::Year => bail!("Yearly reports are not supported"),
+    };
+    Ok(report)
+}
+
+/// Daily history and valuation history a period report is measured against
+///
+/// Mock history only goes into reports built from mock data, and reports
+/// built from mock data get no valuation. Without history the report leaves
+/// the risk metrics out.
+fn period_context<'a>(
+    daily: &'a DailyData,
+    period_data: &MarketData,
+) -> (Option<&'a MarketData>, Option<&'a [ValuationPoint]>) {
+    let history = (!daily.data.is_mock || period_data.is_mock).then_some(&daily.data);
+    let valuation_history = daily
+        .valuation_history
+        .as_deref()
+        .filter(|_| !period_data.is_mock);
+    (history, valuation_history)
+}
+
+/// TuShare valuation history of an index for its report
+///
+/// Mock data and instruments TuShare has no valuation for get none. Without
+/// history the report leaves the valuation out.
+async fn valuation_history(
+    instrument: &Instrument,
+    data: &MarketData,
+) -> Option<Vec<ValuationPoint>> {
+    if data.is_mock || !valuation::is_supported(instrument) {
+        return None;
+    }
+    match data_fetcher::fetch_valuation_history(instrument).await {
+        Ok(history) => Some(history),
+        Err(e) => {
+            warn!(
+                "No valuation history for {}, leaving it out: {:#}",
+                instrument, e
+            );
+            None
+        }
+    }
+}
+
+fn format_daily_report(analysis: &models::DailyAnalysis, gemini_analysis: &str) -> String {
+    let unit = analysis.instrument.price_unit();
+    let report = format!(
+        "📊 {} Daily Analysis Report\n\n\
+        📅 Date: {}\n\n\
+        💰 Current Price: {:.2} {}\n\
+        📈 Price Change: {:.2}%\n\
+        📊 Relative to High: {:.2}%\n\
+        📉 Relative to Low: {:.2}%\n\n\
+        📐 Technical Indicators:\n{}\n\n\
+        {}{}\
+        🤖 AI Analysis:\n{}\n\n\
+        🔌 Data Source: {}\n\
+        🧹 Data Quality: {}\n",
+        analysis.instrument,
+        market_date(analysis.date),
+        analysis.current_price,
+        unit,
+        analysis.price_change_pct,
+        analysis.relative_to_high,
+        analysis.relative_to_low,
+        analysis.indicators,
+        valuation_section(analysis.valuation.as_ref()),
+        signals_section(&analysis.signals),
+        gemini_analysis,
+        analysis.data_source,
+        analysis.data_quality
+    );
+
+    watermark(report, analysis.is_mock)
+}
+
+/// Active signals block of a daily report, empty without signals
+fn signals_section(signals: &[String]) -> String {
+    if signals.is_empty() {
+        return String::new();
+    }
+    format!("🚦 Signals: {}\n\n", signals.join(", "))
+}
+
+/// Valuation block of a report, empty without valuation
+fn valuation_section(valuation: Option<&Valuation>) -> String {
+    valuation
+        .map(|valuation| {
+            format!(
+                "🏷️ Valuation ({}):\n{}\n\n",
+                valuation.trade_date, valuation
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Risk metrics block of a period report, empty without metrics
+fn risk_section(risk: Option<&RiskMetrics>) -> String {
+    risk.map(|risk| format!("⚖️ Risk Metrics:\n{}\n\n", risk))
+        .unwrap_or_default()
+}
+
+fn format_weekly_report(analysis: &models::WeeklyAnalysis, gemini_analysis: &str) -> String {
+    let unit = analysis.instrument.price_unit();
+    let report = format!(
+        "📈 {} Weekly Analysis Report\n\n\
+        📅 Period: {} to {}\n\n\
+        💰 Start Price: {:.2} {}\n\
+        💰 End Price: {:.2} {}\n\
+        📈 Weekly Change: {:.2}%\n\
+        📊 Highest: {:.2} {} ({})\n\
+        📉 Lowest: {:.2} {} ({})\n\n\
+        {}{}\
+        🤖 AI Analysis:\n{}\n\n\
+        🔌 Data Source: {}\n\
+        🧹 Data Quality: {}\n",
+        analysis.instrument,
+        market_date(analysis.summary.start_date),
+        market_date(analysis.summary.end_date),
+        analysis.summary.start_price,
+        unit,
+        analysis.summary.end_price,
+        unit,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        unit,
+        market_date(analysis.summary.highest_date),
+        analysis.summary.lowest_price,
+        unit,
+        market_date(analysis.summary.lowest_date),
+        risk_section(analysis.summary.risk.as_ref()),
+        valuation_section(analysis.summary.valuation.as_ref()),
+        gemini_analysis,
+        analysis.data_source,
+        analysis.data_quality
+    );
+
+    watermark(report, analysis.is_mock)
+}
+
+fn format_monthly_report(analysis: &models::MonthlyAnalysis, gemini_analysis: &str) -> String {
+    let unit = analysis.instrument.price_unit();
+    let report = format!(
+        "📊 {} Monthly Analysis Report\n\n\
+        📅 Month: {}-{}{}\n\n\
+        💰 Start Price: {:.2} {}\n\
+        💰 End Price: {:.2} {}\n\
+        📈 Monthly Change: {:.2}%\n\
+        📊 Highest: {:.2} {} ({})\n\
+        📉 Lowest: {:.2} {} ({})\n\n\
+        {}{}\
+        🤖 AI Analysis:\n{}\n\n\
+        🔌 Data Source: {}\n\
+        🧹 Data Quality: {}\n",
+        analysis.instrument,
+        analysis.year,
+        analysis.month,
+        if analysis.complete {
+            ""
+        } else {
+            " (month to date)"
+        },
+        analysis.summary.start_price,
+        unit,
+        analysis.summary.end_price,
+        unit,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        unit,
+        market_date(analysis.summary.highest_date),
+        analysis.summary.lowest_price,
+        unit,
+        market_date(analysis.summary.lowest_date),
+        risk_section(analysis.summary.risk.as_ref()),
+        valuation_section(analysis.summary.valuation.as_ref()),
+        gemini_analysis,
+        analysis.data_source,
+        analysis.data_quality
+    );
+
+    watermark(report, analysis.is_mock)
+}
+
+fn format_quarterly_report(analysis: &models::QuarterlyAnalysis, gemini_analysis: &str) -> String {
+    let unit = analysis.instrument.price_unit();
+    let report = format!(
+        "📊 {} Quarterly Analysis Report\n\n\
+        📅 Quarter: {} Q{}{}\n\
+        📅 Period: {} to {}\n\n\
+        💰 Start Price: {:.2} {}\n\
+        💰 End Price: {:.2} {}\n\
+        📈 Quarterly Change: {:.2}%\n\
+        📊 Highest: {:.2} {} ({})\n\
+        📉 Lowest: {:.2} {} ({})\n\n\
+        {}{}\
+        🤖 AI Analysis:\n{}\n\n\
+        🔌 Data Source: {}\n\
+        🧹 Data Quality: {}\n",
+        analysis.instrument,
+        analysis.year,
+        analysis.quarter,
+        if analysis.complete {
+            ""
+        } else {
+            " (quarter to date)"
+        },
+        market_date(analysis.summary.start_date),
+        market_date(analysis.summary.end_date),
+        analysis.summary.start_price,
+        unit,
+        analysis.summary.end_price,
+        unit,
+        analysis.summary.change_pct,
+        analysis.summary.highest_price,
+        unit,
+        market_date(analysis.summary.highest_date),
+        analysis.summary.lowest_price,
+        unit,
+        market_date(analysis.summary.lowest_date),
+        risk_section(analysis.summary.risk.as_ref()),
+        valuation_section(analysis.summary.valuation.as_ref()),
+        gemini_analysis,
+        analysis.data_source,
+        analysis.data_quality
+    );
+
+    watermark(report, analysis.is_mock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn generated(instrument: &Instrument) -> ReportOutcome {
+        ReportOutcome::Generated {
+            data_source: "Test".to_string(),
+            report: instrument.code.clone(),
+        }
+    }
+
+    fn instrument(code: &str) -> Instrument {
+        Instrument {
+            code: code.to_string(),
+            ..Instrument::csi300()
+        }
+    }
+
+    fn instruments() -> Vec<Instrument> {
+        ["000300", "BAD", "000905", "ALSO_BAD"]
+            .into_iter()
+            .map(instrument)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn runs_every_instrument() {
+        let instruments = [instrument("000300"), instrument("000905")];
+        let outcomes = run_each("daily", &instruments, |instrument| async move {
+            Ok(generated(instrument))
+        })
+        .await
+        .unwrap();
+
+        let reports: Vec<&str> = outcomes
+            .iter()
+            .map(|outcome| match outcome {
+                ReportOutcome::Generated { report, .. } => report.as_str(),
+                ReportOutcome::DataUnavailable => "unavailable",
+            })
+            .collect();
+        assert_eq!(reports, ["000300", "000905"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_going_after_a_failed_instrument() {
+        let instruments = instruments();
+        let mut ran = Vec::new();
+        let error = run_each("weekly", &instruments, |instrument| {
+            ran.push(instrument.code.clone());
+            async move {
+                if instrument.code.contains("BAD") {
+                    Err(anyhow!("no data"))
+                } else {
+                    Ok(generated(instrument))
+                }
+            }
+        })
+        .await
+        .unwrap_err();
+
+        assert_eq!(ran, ["000300", "BAD", "000905", "ALSO_BAD"]);
+        assert_eq!(
+            error.to_string(),
+            "weekly analysis failed for 2 of 4 instrument(s):\n\
+            - CSI 300 Index (BAD.SH): no data\n\
+            - CSI 300 Index (ALSO_BAD.SH): no data"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_an_unknown_mode() {
+        let error = run_mode(
+            "yearly",
+            "yearly",
+            &instruments(),
+            &RunOptions {
+                send_email: false,
+                allow_mock: true,
+                on_data_unavailable: OnDataUnavailable::Fail,
+                force: false,
+            },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid mode: yearly. Supported modes: daily, weekly, monthly, quarterly"
+        );
+    }
+
+    fn options(on_data_unavailable: OnDataUnavailable) -> RunOptions {
+        RunOptions {
+            send_email: false,
+            allow_mock: false,
+            on_data_unavailable,
+            force: false,
+        }
+    }
+
+    fn unavailable() -> anyhow::Error {
+        DataUnavailable {
+            instrument: Instrument::csi300(),
+            errors: vec!["TuShare: HTTP 500".to_string()],
+        }
+        .into()
+    }
+
+    #[test]
+    fn flags_mock_reports() {
+        let csi300 = Instrument::csi300();
+        assert_eq!(
+            email_subject("Daily Report", &csi300, false),
+            "Daily Report - CSI 300 Index"
+        );
+        assert_eq!(
+            email_subject("Daily Report", &csi300, true),
+            "[MOCK DATA] Daily Report - CSI 300 Index"
+        );
+
+        assert_eq!(watermark("report".to_string(), false), "report");
+        let marked = watermark("report".to_string(), true);
+        assert!(marked.starts_with(MOCK_DATA_WATERMARK));
+        assert!(marked.ends_with(&format!("\nreport\n{}\n", MOCK_DATA_WATERMARK)));
+    }
+
+    #[tokio::test]
+    async fn turns_unavailable_data_into_a_notice_when_asked() {
+        let outcome = handle_fetch_error(
+            "daily",
+            "Daily Report",
+            &Instrument::csi300(),
+            unavailable(),
+            &options(OnDataUnavailable::Notify),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(outcome, ReportOutcome::DataUnavailable));
+    }
+
+    #[tokio::test]
+    async fn fails_on_unavailable_data_by_default() {
+        let error = handle_fetch_error(
+            "daily",
+            "Daily Report",
+            &Instrument::csi300(),
+            unavailable(),
+            &options(OnDataUnavailable::Fail),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(error.downcast_ref::<DataUnavailable>().is_some());
+    }
+
+    #[tokio::test]
+    async fn passes_other_fetch_errors_through() {
+        let error = handle_fetch_error(
+            "daily",
+            "Daily Report",
+            &Instrument::csi300(),
+            anyhow!("TUSHARE_TOKEN environment variable not set"),
+            &options(OnDataUnavailable::Notify),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "TUSHARE_TOKEN environment variable not set"
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use crate::models::StockData;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Trading days per year used to annualize daily figures
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+/// Trailing bars risk is measured over when `RISK_LOOKBACK_DAYS` is not set
+const DEFAULT_LOOKBACK_DAYS: usize = 250;
+
+/// Fewest daily returns risk is computed from
+const MIN_RETURNS: usize = 2;
+
+/// Trailing bars risk is measured over, from `RISK_LOOKBACK_DAYS`
+pub fn lookback_days() -> usize {
+    std::env::var("RISK_LOOKBACK_DAYS")
+        .ok()
+        .and_then(|days| days.trim().parse::<usize>().ok())
+        .filter(|days| *days > MIN_RETURNS)
+        .unwrap_or(DEFAULT_LOOKBACK_DAYS)
+}
+
+/// Annual risk-free rate from `RISK_FREE_RATE` (e.g. `0.02`), default 0
+pub fn risk_free_rate() -> f64 {
+    std::env::var("RISK_FREE_RATE")
+        .ok()
+        .and_then(|rate| rate.trim().parse::<f64>().ok())
+        .filter(|rate| rate.is_finite())
+        .unwrap_or(0.0)
+}
+
+/// Historical value at risk and expected shortfall of daily returns at one
+/// confidence level, as positive loss fractions
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TailRisk {
+    pub confidence: f64,
+    /// Loss not exceeded on `confidence` of days
+    pub var: f64,
+    /// Average loss on the days beyond the VaR
+    pub cvar: f64,
+}
+
+/// A fall from a peak close
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Drawdown {
+    /// Fall from the peak as a positive fraction
+    pub depth: f64,
+    pub peak_date: NaiveDate,
+    pub peak_price: f64,
+    pub trough_date: NaiveDate,
+    pub trough_price: f64,
+}
+
+/// Risk statistics of the daily close-to-close returns of a bar series
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskMetrics {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Number of daily returns
+    pub observations: usize,
+    /// Sample standard deviation of daily returns (divides by n - 1)
+    pub daily_volatility: f64,
+    pub annualized_volatility: f64,
+    /// Annualized mean daily return
+    pub annualized_return: f64,
+    pub risk_free_rate: f64,
+    /// `None` when returns do not vary
+    pub sharpe_ratio: Option<f64>,
+    /// `None` when no return falls below the risk-free rate
+    pub sortino_ratio: Option<f64>,
+    /// Root mean square of daily shortfalls below the risk-free rate, over
+    /// all n returns rather than only the shortfalls
+    pub downside_deviation: f64,
+    pub max_drawdown: Drawdown,
+    /// Fall of the latest close from the highest close before it; the
+    /// trough is the latest bar
+    pub current_drawdown: Drawdown,
+    pub var_95: TailRisk,
+    pub var_99: TailRisk,
+    /// Population skewness m3 / m2^1.5, without small-sample correction
+    pub skewness: f64,
+    /// Population kurtosis m4 / m2² in excess of a normal distribution's 3,
+    /// without small-sample correction
+    pub excess_kurtosis: f64,
+}
+
+/// Risk statistics of a bar series, `None` if it has fewer than three bars
+pub fn analyze(bars: &[StockData], risk_free_rate: f64) -> Option<RiskMetrics> {
+    let returns: Vec<f64> = bars
+        .windows(2)
+        .map(|pair| pair[1].close / pair[0].close - 1.0)
+        .collect();
+    if returns.len() < MIN_RETURNS {
+        return None;
+    }
+
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let deviations = |power: i32| returns.iter().map(|r| (r - mean).powi(power)).sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let daily_volatility = variance.sqrt();
+
+    let daily_risk_free = risk_free_rate / TRADING_DAYS_PER_YEAR;
+    let excess = mean - daily_risk_free;
+    let downside_deviation = (returns
+        .iter()
+        .map(|r| (r - daily_risk_free).min(0.0).powi(2))
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    let annualize = TRADING_DAYS_PER_YEAR.sqrt();
+
+    // Population moments for the shape of the distribution
+    let (m2, m3, m4) = (deviations(2), deviations(3), deviations(4));
+    let (skewness, excess_kurtosis) = if m2 > 0.0 {
+        (m3 / m2.powf(1.5), m4 / (m2 * m2) - 3.0)
+    } else {
+        (0.0, 0.0)
+    };
+
+    let mut sorted = returns.clone();
+    sorted.sort_by(f64::total_cmp);
+
+    let (max_drawdown, current_drawdown) = drawdowns(bars);
+
+    Some(RiskMetrics {
+        start_date: bars[0].trade_date(),
+        end_date: bars[bars.len() - 1].trade_date(),
+        observations: returns.len(),
+        daily_volatility,
+        annualized_volatility: daily_volatility * annualize,
+        annualized_return: mean * TRADING_DAYS_PER_YEAR,
+        risk_free_rate,
+        sharpe_ratio: (daily_volatility > 0.0).then(|| excess / daily_volatility * annualize),
+        sortino_ratio: (downside_deviation > 0.0).then(|| excess / downside_deviation * annualize),
+        downside_deviation,
+        max_drawdown,
+        current_drawdown,
+        var_95: tail_risk(&sorted, 0.95),
+        var_99: tail_risk(&sorted, 0.99),
+        skewness,
+        excess_kurtosis,
+    })
+}
+
+/// Historical VaR and CVaR from returns sorted ascending
+///
+/// The VaR is the return at the `1 - confidence` quantile, taking the worse
+/// return when the quantile falls between two; the CVaR averages that
+/// return and all worse ones. The tail is the worst `ceil((1 - confidence) *
+/// n)` returns, at least one.
+fn tail_risk(sorted: &[f64], confidence: f64) -> TailRisk {
+    // 1 - 0.95 is slightly above 0.05 in binary, which would round a tail
+    // of exactly one return in twenty up to two
+    let share = (1.0 - confidence) * sorted.len() as f64;
+    let tail = ((share - 1e-9).ceil() as usize).clamp(1, sorted.len());
+    let var = -sorted[tail - 1];
+    let cvar = -sorted[..tail].iter().sum::<f64>() / tail as f64;
+    TailRisk {
+        confidence,
+        var,
+        cvar,
+    }
+}
+
+/// Maximum and current drawdown of the closes
+fn drawdowns(bars: &[StockData]) -> (Drawdown, Drawdown) {
+    let drawdown = |peak: &StockData, trough: &StockData| Drawdown {
+        depth: 1.0 - trough.close / peak.close,
+        peak_date: peak.trade_date(),
+        peak_price: peak.close,
+        trough_date: trough.trade_date(),
+        trough_price: trough.close,
+    };
+
+    let mut peak = &bars[0];
+    let mut max = drawdown(peak, peak);
+    for bar in bars {
+        if bar.close > peak.close {
+            peak = bar;
+        }
+        let current = drawdown(peak, bar);
+        if current.depth > max.depth {
+            max = current;
+        }
+    }
+
+    (max, drawdown(peak, &bars[bars.len() - 1]))
+}
+
+fn percent(fraction: f64) -> String {
+    format!("{:.2}%", fraction * 100.0)
+}
+
+fn ratio(value: Option<f64>) -> String {
+    value.map_or_else(|| "n/a".to_string(), |value| format!("{:.2}", value))
+}
+
+impl fmt::Display for Drawdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "-{} ({:.2} on {} to {:.2} on {})",
+            percent(self.depth),
+            self.peak_price,
+            self.peak_date,
+            self.trough_price,
+            self.trough_date
+        )
+    }
+}
+
+impl fmt::Display for TailRisk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "VaR({:.0}%) {}, CVaR({:.0}%) {}",
+            self.confidence * 100.0,
+            percent(self.var),
+            self.confidence * 100.0,
+            percent(self.cvar)
+        )
+    }
+}
+
+impl fmt::Display for RiskMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Window: {} to {} ({} daily returns)",
+            self.start_date, self.end_date, self.observations
+        )?;
+        writeln!(
+            f,
+            "Volatility: {} daily, {} annualized",
+            percent(self.daily_volatility),
+            percent(self.annualized_volatility)
+        )?;
+        writeln!(
+            f,
+            "Sharpe Ratio: {}, Sortino Ratio: {} (risk-free rate {})",
+            ratio(self.sharpe_ratio),
+            ratio(self.sortino_ratio),
+            percent(self.risk_free_rate)
+        )?;
+        writeln!(
+            f,
+            "Downside Deviation: {} daily",
+            percent(self.downside_deviation)
+        )?;
+        writeln!(f, "Max Drawdown: {}", self.max_drawdown)?;
+        writeln!(f, "Current Drawdown: {}", self.current_drawdown)?;
+        writeln!(f, "{}", self.var_95)?;
+        writeln!(f, "{}", self.var_99)?;
+        write!(
+            f,
+            "Skewness: {:.2}, Excess Kurtosis: {:.2}",
+            self.skewness, self.excess_kurtosis
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::market_close_utc;
+    use chrono::Days;
+
+    /// Daily returns of a made-up 20-day series; expected statistics were
+    /// worked out separately from the textbook definitions
+    const RETURNS: [f64; 20] = [
+        0.01, -0.02, 0.015, 0.03, -0.01, 0.0, 0.02, -0.04, 0.005, 0.01, -0.015, 0.025, -0.03, 0.01,
+        0.0, 0.02, -0.005, 0.015, -0.01, 0.01,
+    ];
+
+    fn date(i: usize) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, 1).unwrap() + Days::new(i as u64)
+    }
+
+    fn closes(closes: &[f64]) -> Vec<StockData> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| StockData {
+                date: market_close_utc(date(i)),
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: 1_000,
+                amount: None,
+            })
+            .collect()
+    }
+
+    /// Bars starting at 100 that move by `returns`
+    fn compounded(returns: &[f64]) -> Vec<StockData> {
+        let mut prices = vec![100.0];
+        for r in returns {
+            prices.push(prices[prices.len() - 1] * (1.0 + r));
+        }
+        closes(&prices)
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn measures_a_symmetric_series_by_hand() {
+        // Returns +10%, -10%, 0, +10%, -10%: mean 0, squared deviations sum
+        // to 0.04
+        let risk = analyze(&closes(&[100.0, 110.0, 99.0, 99.0, 108.9, 98.01]), 0.0).unwrap();
+
+        assert_eq!(risk.observations, 5);
+        // Sample variance 0.04 / 4, not population 0.04 / 5
+        assert_near(risk.daily_volatility, 0.1);
+        assert_near(risk.annualized_volatility, 0.1 * 252f64.sqrt());
+        assert_near(risk.annualized_return, 0.0);
+        assert_near(risk.sharpe_ratio.unwrap(), 0.0);
+        // Population shortfalls: sqrt(0.02 / 5)
+        assert_near(risk.downside_deviation, 0.004f64.sqrt());
+        assert_near(risk.sortino_ratio.unwrap(), 0.0);
+        assert_near(risk.skewness, 0.0);
+        // m4 / m2² - 3 = 0.00008 / 0.000064 - 3
+        assert_near(risk.excess_kurtosis, -1.75);
+    }
+
+    #[test]
+    fn measures_volatility_and_ratios_against_the_risk_free_rate() {
+        let risk = analyze(&compounded(&RETURNS), 0.03).unwrap();
+
+        assert_eq!(risk.observations, 20);
+        assert_eq!(risk.start_date, date(0));
+        assert_eq!(risk.end_date, date(20));
+        assert_near(risk.daily_volatility, 0.018453355136383017);
+        assert_near(risk.annualized_volatility, 0.2929379312737552);
+        assert_near(risk.annualized_return, 0.504);
+        assert_near(risk.risk_free_rate, 0.03);
+        assert_near(risk.sharpe_ratio.unwrap(), 1.6180902143295184);
+        assert_near(risk.downside_deviation, 0.013002076626394707);
+        assert_near(risk.sortino_ratio.unwrap(), 2.2964941851760377);
+    }
+
+    #[test]
+    fn measures_the_shape_of_the_returns() {
+        let risk = analyze(&compounded(&RETURNS), 0.0).unwrap();
+
+        assert_near(risk.skewness, -0.635730289258853);
+        assert_near(risk.excess_kurtosis, -0.27669973650828616);
+    }
+
+    #[test]
+    fn takes_the_worst_returns_as_the_tail() {
+        let risk = analyze(&compounded(&RETURNS), 0.0).unwrap();
+
+        // 5% and 1% of 20 returns both leave only the worst day
+        for tail in [risk.var_95, risk.var_99] {
+            assert_near(tail.var, 0.04);
+            assert_near(tail.cvar, 0.04);
+        }
+        assert_eq!(risk.var_95.to_string(), "VaR(95%) 4.00%, CVaR(95%) 4.00%");
+    }
+
+    #[test]
+    fn sizes_the_tail_by_rounding_up() {
+        // -5.0%, -4.9%, ... +4.9%
+        let sorted: Vec<f64> = (0..100).map(|i| (i as f64 - 50.0) / 1000.0).collect();
+
+        // Exactly 5 of 100 returns
+        let tail = tail_risk(&sorted, 0.95);
+        assert_near(tail.var, 0.046);
+        assert_near(tail.cvar, 0.048);
+        let tail = tail_risk(&sorted, 0.99);
+        assert_near(tail.var, 0.05);
+        assert_near(tail.cvar, 0.05);
+
+        // 1.5 of 30 returns takes two
+        let tail = tail_risk(&sorted[..30], 0.95);
+        assert_near(tail.var, 0.049);
+        assert_near(tail.cvar, 0.0495);
+
+        // 12.5 of 250 takes 13
+        let sorted: Vec<f64> = (0..250).map(|i| i as f64 / 1000.0 - 0.1).collect();
+        let tail = tail_risk(&sorted, 0.95);
+        assert_near(tail.var, 0.088);
+        assert_near(tail.cvar, 0.094);
+    }
+
+    #[test]
+    fn finds_the_deepest_drawdown_and_the_current_one() {
+        let bars = compounded(&RETURNS);
+        let risk = analyze(&bars, 0.0).unwrap();
+
+        let max = risk.max_drawdown;
+        assert_eq!((max.peak_date, max.trough_date), (date(7), date(13)));
+        assert_near(max.depth, 1.0 - bars[13].close / bars[7].close);
+        assert_near(max.depth, 0.04568774986000035);
+
+        let current = risk.current_drawdown;
+        assert_eq!(
+            (current.peak_date, current.trough_date),
+            (date(7), date(20))
+        );
+        assert_near(current.depth, 0.007209219047833226);
+    }
+
+    #[test]
+    fn measures_the_current_drawdown_from_the_latest_peak() {
+        let risk = analyze(&closes(&[100.0, 120.0, 90.0, 130.0, 125.0]), 0.0).unwrap();
+
+        let max = risk.max_drawdown;
+        assert_near(max.depth, 0.25);
+        assert_eq!((max.peak_date, max.trough_date), (date(1), date(2)));
+        assert_eq!(
+            max.to_string(),
+            "-25.00% (120.00 on 2025-01-02 to 90.00 on 2025-01-03)"
+        );
+
+        let current = risk.current_drawdown;
+        assert_near(current.depth, 1.0 - 125.0 / 130.0);
+        assert_eq!((current.peak_date, current.trough_date), (date(3), date(4)));
+    }
+
+    #[test]
+    fn has_no_current_drawdown_at_a_new_high() {
+        let risk = analyze(&closes(&[100.0, 120.0, 90.0, 130.0]), 0.0).unwrap();
+
+        assert_near(risk.max_drawdown.depth, 0.25);
+        let current = risk.current_drawdown;
+        assert_near(current.depth, 0.0);
+        assert_eq!((current.peak_date, current.trough_date), (date(3), date(3)));
+    }
+
+    #[test]
+    fn leaves_ratios_undefined_without_variation_or_shortfall() {
+        let risk = analyze(&closes(&[100.0, 100.0, 100.0]), 0.0).unwrap();
+
+        assert_near(risk.daily_volatility, 0.0);
+        assert_eq!(risk.sharpe_ratio, None);
+        assert_eq!(risk.sortino_ratio, None);
+        assert_near(risk.skewness, 0.0);
+        assert_near(risk.excess_kurtosis, 0.0);
+        assert!(
+            risk.to_string()
+                .contains("Sharpe Ratio: n/a, Sortino Ratio: n/a")
+        );
+
+        // Flat prices still fall short of a positive risk-free rate
+        let risk = analyze(&closes(&[100.0, 100.0, 100.0]), 0.0252).unwrap();
+        assert_eq!(risk.sharpe_ratio, None);
+        assert_near(risk.downside_deviation, 0.0001);
+        assert_near(risk.sortino_ratio.unwrap(), -252f64.sqrt());
+    }
+
+    #[test]
+    fn needs_two_returns() {
+        assert!(analyze(&[], 0.0).is_none());
+        assert!(analyze(&closes(&[100.0, 101.0]), 0.0).is_none());
+        assert!(analyze(&closes(&[100.0, 101.0, 102.0]), 0.0).is_some());
+    }
+}
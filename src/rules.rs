@@ -0,0 +1,1757 @@
+use crate::indicators;
+use crate::models::{Instrument, MarketData, StockData};
+use crate::streaming::{RsiMethod, Seed};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::warn;
+
+/// A value of the bar series, evaluated at each bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+    /// Change of the close over `n` bars, in percent
+    PctChange(usize),
+    Sma(usize),
+    Ema(usize),
+    /// Wilder RSI
+    Rsi(usize),
+    /// Average volume of the `n` bars before the current one
+    AverageVolume(usize),
+    /// Highest high of the `n` bars before the current one
+    Highest(usize),
+    /// Lowest low of the `n` bars before the current one
+    Lowest(usize),
+    /// MACD(12, 26, 9) line
+    Macd,
+    MacdSignal,
+    MacdHistogram,
+    /// Bollinger %B(20, 2)
+    PercentB,
+    /// KDJ(9, 3, 3) lines
+    KdjK,
+    KdjD,
+    KdjJ,
+    Wma(usize),
+    /// Average true range
+    Atr(usize),
+    /// Commodity channel index
+    Cci(usize),
+    WilliamsR(usize),
+    /// Volume-weighted average price
+    Vwap(usize),
+    /// On-balance volume
+    Obv,
+    /// DMI(14) lines
+    PlusDi,
+    MinusDi,
+    Adx,
+    /// Parabolic SAR(0.02, 0.2)
+    Psar,
+    /// Ichimoku(9, 26, 52) lines, the leading spans where they are plotted
+    Tenkan,
+    Kijun,
+    SenkouA,
+    SenkouB,
+}
+
+/// Names usable without arguments, e.g. `close`
+const VARIABLES: &[(&str, Metric)] = &[
+    ("open", Metric::Open),
+    ("high", Metric::High),
+    ("low", Metric::Low),
+    ("close", Metric::Close),
+    ("volume", Metric::Volume),
+    ("change_pct", Metric::PctChange(1)),
+    ("macd", Metric::Macd),
+    ("macd_signal", Metric::MacdSignal),
+    ("macd_hist", Metric::MacdHistogram),
+    ("percent_b", Metric::PercentB),
+    ("kdj_k", Metric::KdjK),
+    ("kdj_d", Metric::KdjD),
+    ("kdj_j", Metric::KdjJ),
+    ("obv", Metric::Obv),
+    ("plus_di", Metric::PlusDi),
+    ("minus_di", Metric::MinusDi),
+    ("adx", Metric::Adx),
+    ("psar", Metric::Psar),
+    ("tenkan", Metric::Tenkan),
+    ("kijun", Metric::Kijun),
+    ("senkou_a", Metric::SenkouA),
+    ("senkou_b", Metric::SenkouB),
+];
+
+/// Builds a metric from its period
+type PeriodMetric = fn(usize) -> Metric;
+
+/// Functions taking a period, e.g. `sma(60)`
+const FUNCTIONS: &[(&str, PeriodMetric)] = &[
+    ("sma", Metric::Sma),
+    ("ema", Metric::Ema),
+    ("rsi", Metric::Rsi),
+    ("pct_change", Metric::PctChange),
+    ("avg_volume", Metric::AverageVolume),
+    ("highest", Metric::Highest),
+    ("lowest", Metric::Lowest),
+    ("wma", Metric::Wma),
+    ("atr", Metric::Atr),
+    ("cci", Metric::Cci),
+    ("williams_r", Metric::WilliamsR),
+    ("vwap", Metric::Vwap),
+];
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((name, _)) = VARIABLES.iter().find(|(_, metric)| metric == self) {
+            return write!(f, "{}", name);
+        }
+        let (name, period) = match self {
+            Metric::PctChange(n) => ("pct_change", n),
+            Metric::Sma(n) => ("sma", n),
+            Metric::Ema(n) => ("ema", n),
+            Metric::Rsi(n) => ("rsi", n),
+            Metric::AverageVolume(n) => ("avg_volume", n),
+            Metric::Highest(n) => ("highest", n),
+            Metric::Lowest(n) => ("lowest", n),
+            Metric::Wma(n) => ("wma", n),
+            Metric::Atr(n) => ("atr", n),
+            Metric::Cci(n) => ("cci", n),
+            Metric::WilliamsR(n) => ("williams_r", n),
+            Metric::Vwap(n) => ("vwap", n),
+            _ => unreachable!("variables are listed in VARIABLES"),
+        };
+        write!(f, "{}({})", name, period)
+    }
+}
+
+impl Metric {
+    /// Value at every bar, `None` where it is not defined
+    fn values(self, bars: &[StockData]) -> Vec<Option<f64>> {
+        let field = |get: fn(&StockData) -> f64| bars.iter().map(|bar| Some(get(bar))).collect();
+        // Aggregate of the `n` bars before each bar
+        let before = |n: usize, aggregate: &dyn Fn(&[StockData]) -> f64| {
+            (0..bars.len())
+                .map(|i| Some(aggregate(&bars[i.checked_sub(n)?..i])))
+                .collect()
+        };
+        let macd = || indicators::macd(bars, 12, 26, 9, Seed::Sma);
+        let kdj = || indicators::kdj(bars, 9, 3, 3);
+        let dmi = || indicators::dmi(bars, 14);
+        let ichimoku = || indicators::ichimoku(bars, 9, 26, 52);
+
+        match self {
+            Metric::Open => field(|bar| bar.open),
+            Metric::High => field(|bar| bar.high),
+            Metric::Low => field(|bar| bar.low),
+            Metric::Close => field(|bar| bar.close),
+            Metric::Volume => field(|bar| bar.volume as f64),
+            Metric::PctChange(n) => (0..bars.len())
+                .map(|i| {
+                    let base = bars[i.checked_sub(n)?].close;
+                    Some((bars[i].close - base) / base * 100.0)
+                })
+                .collect(),
+            Metric::Sma(n) => indicators::sma(bars, n).values().collect(),
+            Metric::Ema(n) => indicators::ema(bars, n, Seed::Sma).values().collect(),
+            Metric::Rsi(n) => indicators::rsi(bars, n, RsiMethod::Wilder(Seed::Sma))
+                .values()
+                .collect(),
+            Metric::AverageVolume(n) => before(n, &|window| {
+                window.iter().map(|bar| bar.volume as f64).sum::<f64>() / n as f64
+            }),
+            Metric::Highest(n) => before(n, &|window| {
+                window
+                    .iter()
+                    .map(|bar| bar.high)
+                    .fold(f64::NEG_INFINITY, f64::max)
+            }),
+            Metric::Lowest(n) => before(n, &|window| {
+                window
+                    .iter()
+                    .map(|bar| bar.low)
+                    .fold(f64::INFINITY, f64::min)
+            }),
+            Metric::Macd => macd().values().map(|v| v.map(|m| m.macd)).collect(),
+            Metric::MacdSignal => macd().values().map(|v| v.map(|m| m.signal)).collect(),
+            Metric::MacdHistogram => macd().values().map(|v| v.map(|m| m.histogram)).collect(),
+            Metric::PercentB => indicators::bollinger(bars, 20, 2.0)
+                .values()
+                .map(|v| v.map(|b| b.percent_b))
+                .collect(),
+            Metric::KdjK => kdj().values().map(|v| v.map(|k| k.k)).collect(),
+            Metric::KdjD => kdj().values().map(|v| v.map(|k| k.d)).collect(),
+            Metric::KdjJ => kdj().values().map(|v| v.map(|k| k.j)).collect(),
+            Metric::Wma(n) => indicators::wma(bars, n).values().collect(),
+            Metric::Atr(n) => indicators::atr(bars, n).values().collect(),
+            Metric::Cci(n) => indicators::cci(bars, n).values().collect(),
+            Metric::WilliamsR(n) => indicators::williams_r(bars, n).values().collect(),
+            Metric::Vwap(n) => indicators::vwap(bars, n).values().collect(),
+            Metric::Obv => indicators::obv(bars).values().collect(),
+            Metric::PlusDi => dmi().values().map(|v| v.map(|d| d.plus_di)).collect(),
+            Metric::MinusDi => dmi().values().map(|v| v.map(|d| d.minus_di)).collect(),
+            Metric::Adx => dmi().values().map(|v| v.and_then(|d| d.adx)).collect(),
+            Metric::Psar => indicators::psar(bars, 0.02, 0.2)
+                .values()
+                .map(|v| v.map(|p| p.sar))
+                .collect(),
+            Metric::Tenkan => ichimoku().values().map(|v| v?.tenkan).collect(),
+            Metric::Kijun => ichimoku().values().map(|v| v?.kijun).collect(),
+            Metric::SenkouA => ichimoku().values().map(|v| v?.senkou_a).collect(),
+            Metric::SenkouB => ichimoku().values().map(|v| v?.senkou_b).collect(),
+        }
+    }
+}
+
+/// An error in a condition, with the byte range it refers to
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl ExprError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+type ExprResult<T> = std::result::Result<T, ExprError>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    LeftParen,
+    RightParen,
+    Comma,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {}", value),
+            Token::Name(name) => write!(f, "'{}'", name),
+            Token::LeftParen => write!(f, "'('"),
+            Token::RightParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+            Token::Plus => write!(f, "'+'"),
+            Token::Minus => write!(f, "'-'"),
+            Token::Star => write!(f, "'*'"),
+            Token::Slash => write!(f, "'/'"),
+            Token::Less => write!(f, "'<'"),
+            Token::LessEqual => write!(f, "'<='"),
+            Token::Greater => write!(f, "'>'"),
+            Token::GreaterEqual => write!(f, "'>='"),
+            Token::Equal => write!(f, "'=='"),
+            Token::NotEqual => write!(f, "'!='"),
+            Token::End => write!(f, "end of condition"),
+        }
+    }
+}
+
+/// Split a condition into tokens, ending with `Token::End`
+fn tokenize(source: &str) -> ExprResult<Vec<(Token, Range<usize>)>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let token = if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let text = &source[start..end];
+            let value = text
+                .parse()
+                .map_err(|_| ExprError::new(start..end, format!("invalid number '{}'", text)))?;
+            tokens.push((Token::Number(value), start..end));
+            continue;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let name = source[start..end].to_ascii_lowercase();
+            tokens.push((Token::Name(name), start..end));
+            continue;
+        } else {
+            chars.next();
+            let next = chars.peek().map(|&(_, c)| c);
+            let two = |token: Token, chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+                chars.next();
+                (token, 2)
+            };
+            let (token, len) = match (c, next) {
+                ('(', _) => (Token::LeftParen, 1),
+                (')', _) => (Token::RightParen, 1),
+                (',', _) => (Token::Comma, 1),
+                ('+', _) => (Token::Plus, 1),
+                ('-', _) => (Token::Minus, 1),
+                ('*', _) => (Token::Star, 1),
+                ('/', _) => (Token::Slash, 1),
+                ('<', Some('=')) => two(Token::LessEqual, &mut chars),
+                ('<', _) => (Token::Less, 1),
+                ('>', Some('=')) => two(Token::GreaterEqual, &mut chars),
+                ('>', _) => (Token::Greater, 1),
+                ('=', Some('=')) => two(Token::Equal, &mut chars),
+                ('!', Some('=')) => two(Token::NotEqual, &mut chars),
+                ('=', _) => {
+                    return Err(ExprError::new(
+                        start..start + 1,
+                        "use '==' to compare for equality",
+                    ));
+                }
+                ('&', _) => return Err(ExprError::new(start..start + 1, "use 'and' instead")),
+                ('|', _) => return Err(ExprError::new(start..start + 1, "use 'or' instead")),
+                ('!', _) => return Err(ExprError::new(start..start + 1, "use 'not' instead")),
+                _ => {
+                    return Err(ExprError::new(
+                        start..start + c.len_utf8(),
+                        format!("unexpected character '{}'", c),
+                    ));
+                }
+            };
+            (token, start..start + len)
+        };
+        tokens.push(token);
+    }
+
+    tokens.push((Token::End, source.len()..source.len()));
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    CrossesAbove,
+    CrossesBelow,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Less
+            | BinaryOp::LessEqual
+            | BinaryOp::Greater
+            | BinaryOp::GreaterEqual
+            | BinaryOp::Equal
+            | BinaryOp::NotEqual
+            | BinaryOp::CrossesAbove
+            | BinaryOp::CrossesBelow => 3,
+            BinaryOp::Add | BinaryOp::Subtract => 4,
+            BinaryOp::Multiply | BinaryOp::Divide => 5,
+        }
+    }
+
+    fn is_arithmetic(self) -> bool {
+        self.precedence() >= 4
+    }
+
+    fn is_logical(self) -> bool {
+        self.precedence() <= 2
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::CrossesAbove => "crosses_above",
+            BinaryOp::CrossesBelow => "crosses_below",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// Parsed condition before names and types are checked
+#[derive(Debug, Clone)]
+struct Ast {
+    kind: AstKind,
+    span: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum AstKind {
+    Number(f64),
+    Name(String),
+    Call(String, Vec<Ast>),
+    Negate(Box<Ast>),
+    Not(Box<Ast>),
+    Binary(BinaryOp, Box<Ast>, Box<Ast>),
+}
+
+/// Recursive descent parser over the grammar
+///
+/// ```text
+/// or         = and ("or" and)*
+/// and        = not ("and" not)*
+/// not        = "not" not | comparison
+/// comparison = sum (("<" | "<=" | ">" | ">=" | "==" | "!=" | "crosses_above" | "crosses_below") sum)?
+/// sum        = product (("+" | "-") product)*
+/// product    = unary (("*" | "/") unary)*
+/// unary      = "-" unary | primary
+/// primary    = number | name | name "(" (or ("," or)*)? ")" | "(" or ")"
+/// ```
+struct Parser {
+    tokens: Vec<(Token, Range<usize>)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn span(&self) -> Range<usize> {
+        self.tokens[self.position].1.clone()
+    }
+
+    fn advance(&mut self) -> (Token, Range<usize>) {
+        let token = self.tokens[self.position].clone();
+        if token.0 != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Name(name) if name == keyword)
+    }
+
+    fn expect(&mut self, expected: Token) -> ExprResult<Range<usize>> {
+        if *self.peek() == expected {
+            Ok(self.advance().1)
+        } else {
+            Err(ExprError::new(
+                self.span(),
+                format!("expected {}, found {}", expected, self.peek()),
+            ))
+        }
+    }
+
+    fn binary(op: BinaryOp, left: Ast, right: Ast) -> Ast {
+        Ast {
+            span: left.span.start..right.span.end,
+            kind: AstKind::Binary(op, Box::new(left), Box::new(right)),
+        }
+    }
+
+    fn parse(mut self) -> ExprResult<Ast> {
+        if *self.peek() == Token::End {
+            return Err(ExprError::new(self.span(), "condition is empty"));
+        }
+        let ast = self.or()?;
+        match self.peek() {
+            Token::End => Ok(ast),
+            Token::Name(name) if name == "crosses" => Err(ExprError::new(
+                self.span(),
+                "use 'crosses_above' or 'crosses_below'",
+            )),
+            token => Err(ExprError::new(
+                self.span(),
+                format!("expected an operator, found {}", token),
+            )),
+        }
+    }
+
+    fn or(&mut self) -> ExprResult<Ast> {
+        let mut left = self.and()?;
+        while self.is_keyword("or") {
+            self.advance();
+            let right = self.and()?;
+            left = Self::binary(BinaryOp::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> ExprResult<Ast> {
+        let mut left = self.not()?;
+        while self.is_keyword("and") {
+            self.advance();
+            let right = self.not()?;
+            left = Self::binary(BinaryOp::And, left, right);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> ExprResult<Ast> {
+        if self.is_keyword("not") {
+            let start = self.advance().1.start;
+            let operand = self.not()?;
+            return Ok(Ast {
+                span: start..operand.span.end,
+                kind: AstKind::Not(Box::new(operand)),
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison_op(&self) -> Option<BinaryOp> {
+        Some(match self.peek() {
+            Token::Less => BinaryOp::Less,
+            Token::LessEqual => BinaryOp::LessEqual,
+            Token::Greater => BinaryOp::Greater,
+            Token::GreaterEqual => BinaryOp::GreaterEqual,
+            Token::Equal => BinaryOp::Equal,
+            Token::NotEqual => BinaryOp::NotEqual,
+            Token::Name(name) if name == "crosses_above" => BinaryOp::CrossesAbove,
+            Token::Name(name) if name == "crosses_below" => BinaryOp::CrossesBelow,
+            _ => return None,
+        })
+    }
+
+    fn comparison(&mut self) -> ExprResult<Ast> {
+        let left = self.sum()?;
+        let Some(op) = self.comparison_op() else {
+            return Ok(left);
+        };
+        self.advance();
+        let right = self.sum()?;
+        if self.comparison_op().is_some() {
+            return Err(ExprError::new(
+                self.span(),
+                "comparisons cannot be chained, combine them with 'and'",
+            ));
+        }
+        Ok(Self::binary(op, left, right))
+    }
+
+    fn sum(&mut self) -> ExprResult<Ast> {
+        let mut left = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Subtract,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.product()?;
+            left = Self::binary(op, left, right);
+        }
+    }
+
+    fn product(&mut self) -> ExprResult<Ast> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Star => BinaryOp::Multiply,
+                Token::Slash => BinaryOp::Divide,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.unary()?;
+            left = Self::binary(op, left, right);
+        }
+    }
+
+    fn unary(&mut self) -> ExprResult<Ast> {
+        if *self.peek() == Token::Minus {
+            let start = self.advance().1.start;
+            let operand = self.unary()?;
+            return Ok(Ast {
+                span: start..operand.span.end,
+                kind: AstKind::Negate(Box::new(operand)),
+            });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> ExprResult<Ast> {
+        let (token, span) = self.advance();
+        match token {
+            Token::Number(value) => Ok(Ast {
+                kind: AstKind::Number(value),
+                span,
+            }),
+            Token::LeftParen => {
+                let inner = self.or()?;
+                let end = self.expect(Token::RightParen)?.end;
+                Ok(Ast {
+                    kind: inner.kind,
+                    span: span.start..end,
+                })
+            }
+            Token::Name(name) if is_reserved(&name) => Err(ExprError::new(
+                span,
+                format!("expected a value, found '{}'", name),
+            )),
+            Token::Name(name) if *self.peek() == Token::LeftParen => {
+                self.advance();
+                let mut args = Vec::new();
+                if *self.peek() != Token::RightParen {
+                    args.push(self.or()?);
+                    while *self.peek() == Token::Comma {
+                        self.advance();
+                        args.push(self.or()?);
+                    }
+                }
+                let end = self.expect(Token::RightParen)?.end;
+                Ok(Ast {
+                    kind: AstKind::Call(name, args),
+                    span: span.start..end,
+                })
+            }
+            Token::Name(name) => Ok(Ast {
+                kind: AstKind::Name(name),
+                span,
+            }),
+            token => Err(ExprError::new(
+                span,
+                format!("expected a value, found {}", token),
+            )),
+        }
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    matches!(
+        name,
+        "and" | "or" | "not" | "crosses_above" | "crosses_below"
+    )
+}
+
+/// What an expression evaluates to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    Boolean,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "a number"),
+            Type::Boolean => write!(f, "a true/false condition"),
+        }
+    }
+}
+
+/// Type-checked condition tree
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Constant(f64),
+    Metric(Metric),
+    Negate(Box<Node>),
+    Not(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+/// Resolve names and check that every operator gets operands of its type
+fn check(ast: &Ast) -> ExprResult<(Node, Type)> {
+    let expect = |ast: &Ast, expected: Type| -> ExprResult<Node> {
+        let (node, found) = check(ast)?;
+        if found != expected {
+            return Err(ExprError::new(
+                ast.span.clone(),
+                format!("expected {}, found {}", expected, found),
+            ));
+        }
+        Ok(node)
+    };
+
+    match &ast.kind {
+        AstKind::Number(value) => Ok((Node::Constant(*value), Type::Number)),
+        AstKind::Name(name) => {
+            if let Some((_, metric)) = VARIABLES.iter().find(|(known, _)| known == name) {
+                Ok((Node::Metric(*metric), Type::Number))
+            } else if FUNCTIONS.iter().any(|(known, _)| known == name) {
+                Err(ExprError::new(
+                    ast.span.clone(),
+                    format!("'{}' needs a period, e.g. {}(20)", name, name),
+                ))
+            } else {
+                Err(ExprError::new(
+                    ast.span.clone(),
+                    format!("unknown value '{}'", name),
+                ))
+            }
+        }
+        AstKind::Call(name, args) => {
+            let Some((_, metric)) = FUNCTIONS.iter().find(|(known, _)| known == name) else {
+                let message = if VARIABLES.iter().any(|(known, _)| known == name) {
+                    format!("'{}' takes no arguments", name)
+                } else {
+                    format!("unknown function '{}'", name)
+                };
+                return Err(ExprError::new(ast.span.clone(), message));
+            };
+            let [arg] = args.as_slice() else {
+                return Err(ExprError::new(
+                    ast.span.clone(),
+                    format!(
+                        "'{}' takes one period, found {} arguments",
+                        name,
+                        args.len()
+                    ),
+                ));
+            };
+            match arg.kind {
+                AstKind::Number(period) if period >= 1.0 && period.fract() == 0.0 => {
+                    Ok((Node::Metric(metric(period as usize)), Type::Number))
+                }
+                _ => Err(ExprError::new(
+                    arg.span.clone(),
+                    format!("period of '{}' must be a whole number of bars", name),
+                )),
+            }
+        }
+        AstKind::Negate(operand) => match expect(operand, Type::Number)? {
+            Node::Constant(value) => Ok((Node::Constant(-value), Type::Number)),
+            node => Ok((Node::Negate(Box::new(node)), Type::Number)),
+        },
+        AstKind::Not(operand) => Ok((
+            Node::Not(Box::new(expect(operand, Type::Boolean)?)),
+            Type::Boolean,
+        )),
+        AstKind::Binary(op, left, right) => {
+            let (operand, result) = if op.is_arithmetic() {
+                (Type::Number, Type::Number)
+            } else if op.is_logical() {
+                (Type::Boolean, Type::Boolean)
+            } else {
+                (Type::Number, Type::Boolean)
+            };
+            let node = Node::Binary(
+                *op,
+                Box::new(expect(left, operand)?),
+                Box::new(expect(right, operand)?),
+            );
+            Ok((node, result))
+        }
+    }
+}
+
+impl Node {
+    fn collect_metrics(&self, metrics: &mut Vec<Metric>) {
+        match self {
+            Node::Constant(_) => {}
+            Node::Metric(metric) => {
+                if !metrics.contains(metric) {
+                    metrics.push(*metric);
+                }
+            }
+            Node::Negate(operand) | Node::Not(operand) => operand.collect_metrics(metrics),
+            Node::Binary(_, left, right) => {
+                left.collect_metrics(metrics);
+                right.collect_metrics(metrics);
+            }
+        }
+    }
+
+    /// Numeric value at bar `i`, `None` where undefined
+    fn number(&self, values: &HashMap<Metric, Vec<Option<f64>>>, i: usize) -> Option<f64> {
+        let value = match self {
+            Node::Constant(value) => *value,
+            Node::Metric(metric) => values[metric][i]?,
+            Node::Negate(operand) => -operand.number(values, i)?,
+            Node::Binary(op, left, right) => {
+                let (left, right) = (left.number(values, i)?, right.number(values, i)?);
+                match op {
+                    BinaryOp::Add => left + right,
+                    BinaryOp::Subtract => left - right,
+                    BinaryOp::Multiply => left * right,
+                    BinaryOp::Divide => left / right,
+                    _ => unreachable!("type checked"),
+                }
+            }
+            Node::Not(_) => unreachable!("type checked"),
+        };
+        value.is_finite().then_some(value)
+    }
+
+    /// Truth at bar `i`, `None` where undefined
+    ///
+    /// `and`/`or` short-circuit over undefined operands, so `false and x` is
+    /// false even while `x` is warming up.
+    fn truth(&self, values: &HashMap<Metric, Vec<Option<f64>>>, i: usize) -> Option<bool> {
+        let Node::Binary(op, left, right) = self else {
+            let Node::Not(operand) = self else {
+                unreachable!("type checked")
+            };
+            return operand.truth(values, i).map(|truth| !truth);
+        };
+        let compare = |i: usize| Some((left.number(values, i)?, right.number(values, i)?));
+
+        match op {
+            BinaryOp::And => match (left.truth(values, i), right.truth(values, i)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            BinaryOp::Or => match (left.truth(values, i), right.truth(values, i)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            BinaryOp::CrossesAbove | BinaryOp::CrossesBelow => {
+                let (left_now, right_now) = compare(i)?;
+                let (left_before, right_before) = compare(i.checked_sub(1)?)?;
+                Some(if *op == BinaryOp::CrossesAbove {
+                    left_before <= right_before && left_now > right_now
+                } else {
+                    left_before >= right_before && left_now < right_now
+                })
+            }
+            _ => {
+                let (left, right) = compare(i)?;
+                Some(match op {
+                    BinaryOp::Less => left < right,
+                    BinaryOp::LessEqual => left <= right,
+                    BinaryOp::Greater => left > right,
+                    BinaryOp::GreaterEqual => left >= right,
+                    BinaryOp::Equal => left == right,
+                    BinaryOp::NotEqual => left != right,
+                    _ => unreachable!("type checked"),
+                })
+            }
+        }
+    }
+
+    /// Write the node, parenthesizing operands that bind differently
+    fn write(&self, f: &mut fmt::Formatter<'_>, parent: Option<(BinaryOp, bool)>) -> fmt::Result {
+        match self {
+            Node::Constant(value) => write!(f, "{}", value),
+            Node::Metric(metric) => write!(f, "{}", metric),
+            Node::Negate(operand) => {
+                write!(f, "-")?;
+                operand.write(f, Some((BinaryOp::Multiply, true)))
+            }
+            Node::Not(operand) => {
+                write!(f, "not ")?;
+                operand.write(f, Some((BinaryOp::And, true)))
+            }
+            Node::Binary(op, left, right) => {
+                let parenthesize = parent.is_some_and(|(parent, is_right)| {
+                    parent.precedence() != op.precedence() || is_right
+                });
+                if parenthesize {
+                    write!(f, "(")?;
+                }
+                left.write(f, Some((*op, false)))?;
+                write!(f, " {} ", op)?;
+                right.write(f, Some((*op, true)))?;
+                if parenthesize {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A parsed and type-checked rule condition
+#[derive(Debug, Clone)]
+pub struct Condition {
+    node: Node,
+    metrics: Vec<Metric>,
+}
+
+/// A condition evaluated at the latest bars
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    /// Whether the condition held at the bar before the latest
+    pub previous: Option<bool>,
+    /// Whether the condition holds at the latest bar
+    pub current: Option<bool>,
+    /// Latest value of every metric the condition uses
+    pub values: Vec<(Metric, Option<f64>)>,
+}
+
+impl FromStr for Condition {
+    type Err = ExprError;
+
+    fn from_str(source: &str) -> ExprResult<Self> {
+        let ast = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        }
+        .parse()?;
+        let node = match check(&ast)? {
+            (node, Type::Boolean) => node,
+            (_, found) => {
+                return Err(ExprError::new(
+                    ast.span,
+                    format!(
+                        "condition must be true or false, found {}; compare it with something, e.g. '> 0'",
+                        found
+                    ),
+                ));
+            }
+        };
+
+        let mut metrics = Vec::new();
+        node.collect_metrics(&mut metrics);
+        Ok(Self { node, metrics })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.node.write(f, None)
+    }
+}
+
+impl Condition {
+    /// Evaluate at the last two bars
+    pub fn evaluate(&self, bars: &[StockData]) -> Evaluation {
+        let values: HashMap<Metric, Vec<Option<f64>>> = self
+            .metrics
+            .iter()
+            .map(|metric| (*metric, metric.values(bars)))
+            .collect();
+        let truth = |i: Option<usize>| self.node.truth(&values, i?);
+
+        let len = bars.len();
+        Evaluation {
+            previous: truth(len.checked_sub(2)),
+            current: truth(len.checked_sub(1)),
+            values: self
+                .metrics
+                .iter()
+                .map(|metric| (*metric, values[metric].last().copied().flatten()))
+                .collect(),
+        }
+    }
+}
+
+/// What happens when a rule starts to hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    /// Emailed as soon as it starts to hold
+    #[default]
+    Alert,
+    /// Listed in the daily report while it holds
+    Signal,
+}
+
+impl fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleKind::Alert => write!(f, "alert"),
+            RuleKind::Signal => write!(f, "signal"),
+        }
+    }
+}
+
+/// A rule from the rules file
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub kind: RuleKind,
+    pub condition: Condition,
+    /// TuShare codes the rule applies to; all instruments when empty
+    pub instruments: Vec<String>,
+}
+
+impl Rule {
+    pub fn applies_to(&self, code: &str) -> bool {
+        self.instruments.is_empty() || self.instruments.iter().any(|instrument| instrument == code)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<RawRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    name: String,
+    condition: String,
+    #[serde(default)]
+    kind: RuleKind,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    instruments: Vec<String>,
+}
+
+/// JSON rules file of the first alert rules, one comparison per rule
+#[derive(Debug, Deserialize)]
+struct JsonRulesFile {
+    rules: Vec<JsonRule>,
+}
+
+/// Alert comparing two operands, e.g.
+/// `{"name": "RSI oversold", "left": "rsi(14)", "op": "<", "right": 30}`
+#[derive(Debug, Deserialize)]
+struct JsonRule {
+    name: String,
+    left: JsonOperand,
+    op: String,
+    right: JsonOperand,
+    #[serde(default)]
+    instruments: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonOperand {
+    Constant(f64),
+    Metric(String),
+    /// A metric times a factor, e.g. twice the average volume
+    Scaled {
+        times: f64,
+        metric: String,
+    },
+}
+
+impl fmt::Display for JsonOperand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonOperand::Constant(value) => write!(f, "{}", value),
+            JsonOperand::Metric(metric) => write!(f, "{}", metric),
+            JsonOperand::Scaled { times, metric } => write!(f, "{} * {}", times, metric),
+        }
+    }
+}
+
+impl From<JsonRulesFile> for RulesFile {
+    fn from(file: JsonRulesFile) -> Self {
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|rule| RawRule {
+                name: rule.name,
+                condition: format!("{} {} {}", rule.left, rule.op, rule.right),
+                kind: RuleKind::Alert,
+                instruments: rule.instruments,
+            })
+            .collect();
+        Self { rules }
+    }
+}
+
+/// Where a diagnostic points
+#[derive(Debug, Clone)]
+enum Location {
+    /// Byte range in the rules file
+    File(Range<usize>),
+    /// Line and column in the rules file, as reported by the YAML parser
+    LineColumn(usize, usize),
+    /// Byte range in a condition that could not be found verbatim in the file
+    Condition(String, Range<usize>),
+    Unknown,
+}
+
+/// A problem found in a rules file
+#[derive(Debug, Clone)]
+struct Diagnostic {
+    rule: Option<String>,
+    location: Location,
+    message: String,
+}
+
+/// Errors of a rules file, displayed with line, column and the offending
+/// source line
+#[derive(Debug)]
+pub struct RulesError {
+    path: PathBuf,
+    source: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl std::error::Error for RulesError {}
+
+/// 1-based line and column of a byte offset, the column counted in characters
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Write a source line with `width` carets from `column` (1-based, in
+/// characters)
+fn write_snippet(
+    f: &mut fmt::Formatter<'_>,
+    line: &str,
+    column: usize,
+    width: usize,
+) -> fmt::Result {
+    // Tabs would shift the carets
+    writeln!(f, "    {}", line.replace('\t', " "))?;
+    writeln!(
+        f,
+        "    {}{}",
+        " ".repeat(column.saturating_sub(1)),
+        "^".repeat(width.max(1))
+    )
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} error(s) in rules file {}",
+            self.diagnostics.len(),
+            self.path.display()
+        )?;
+        for diagnostic in &self.diagnostics {
+            let context = diagnostic
+                .rule
+                .as_ref()
+                .map(|rule| format!(" in rule '{}'", rule))
+                .unwrap_or_default();
+            let source_line = |line: usize| self.source.lines().nth(line - 1).unwrap_or("");
+
+            match &diagnostic.location {
+                Location::File(span) => {
+                    let (line, column) = line_column(&self.source, span.start);
+                    writeln!(
+                        f,
+                        "{}:{}:{}: error{}: {}",
+                        self.path.display(),
+                        line,
+                        column,
+                        context,
+                        diagnostic.message
+                    )?;
+                    let width = self.source[span.clone()]
+                        .lines()
+                        .next()
+                        .map_or(1, |s| s.chars().count());
+                    write_snippet(f, source_line(line), column, width)?;
+                }
+                Location::LineColumn(line, column) => {
+                    writeln!(
+                        f,
+                        "{}:{}:{}: error{}: {}",
+                        self.path.display(),
+                        line,
+                        column,
+                        context,
+                        diagnostic.message
+                    )?;
+                    write_snippet(f, source_line(*line), *column, 1)?;
+                }
+                Location::Condition(condition, span) => {
+                    let (_, column) = line_column(condition, span.start);
+                    writeln!(
+                        f,
+                        "{}: error{}, condition column {}: {}",
+                        self.path.display(),
+                        context,
+                        column,
+                        diagnostic.message
+                    )?;
+                    let width = condition[span.clone()].chars().count();
+                    write_snippet(f, condition, column, width)?;
+                }
+                Location::Unknown => writeln!(
+                    f,
+                    "{}: error{}: {}",
+                    self.path.display(),
+                    context,
+                    diagnostic.message
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rules file format, chosen by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Yaml,
+    /// The deprecated `left`/`op`/`right` format of the first alert rules
+    Json,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    fn parse(self, source: &str) -> std::result::Result<RulesFile, Diagnostic> {
+        let diagnostic = |location, message: String| Diagnostic {
+            rule: None,
+            location,
+            message,
+        };
+        match self {
+            Format::Toml => toml::from_str(source).map_err(|e| {
+                let location = e.span().map_or(Location::Unknown, Location::File);
+                diagnostic(location, e.message().to_string())
+            }),
+            Format::Yaml => serde_yaml_ng::from_str::<Option<RulesFile>>(source)
+                .map(|file| file.unwrap_or(RulesFile { rules: Vec::new() }))
+                .map_err(|e| {
+                    let location = e.location().map_or(Location::Unknown, |location| {
+                        Location::LineColumn(location.line(), location.column())
+                    });
+                    // The YAML error message repeats the position
+                    let message = e.to_string();
+                    let message = message
+                        .split_once(" at line ")
+                        .map_or(message.as_str(), |(message, _)| message);
+                    diagnostic(location, message.to_string())
+                }),
+            Format::Json => serde_json::from_str::<JsonRulesFile>(source)
+                .map(RulesFile::from)
+                .map_err(|e| {
+                    // The JSON error message repeats the position too
+                    let message = e.to_string();
+                    let message = message
+                        .split_once(" at line ")
+                        .map_or(message.as_str(), |(message, _)| message);
+                    diagnostic(
+                        Location::LineColumn(e.line(), e.column()),
+                        message.to_string(),
+                    )
+                }),
+        }
+    }
+}
+
+/// Load and check every rule of a TOML or YAML rules file
+///
+/// JSON files in the format of the first alert rules are still loaded, with
+/// a deprecation warning. All problems are reported at once rather than
+/// stopping at the first.
+pub fn load(path: &Path) -> anyhow::Result<Vec<Rule>> {
+    let Some(format) = Format::of(path) else {
+        anyhow::bail!(
+            "Unsupported rules file {}, expected a .toml, .yaml or .yml file",
+            path.display()
+        );
+    };
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read rules file {}: {}", path.display(), e))?;
+    parse(path, format, &source)
+}
+
+/// Check the rules of a rules file's contents; `path` is only used in
+/// messages
+fn parse(path: &Path, format: Format, source: &str) -> anyhow::Result<Vec<Rule>> {
+    let error = |diagnostics| RulesError {
+        path: path.to_path_buf(),
+        source: source.to_string(),
+        diagnostics,
+    };
+
+    let file = format.parse(source).map_err(|d| error(vec![d]))?;
+    let mut rules = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut names = BTreeSet::new();
+    // Values are looked up in the file after the previous rule's condition,
+    // so that two rules with the same condition point at their own lines
+    let mut cursor = 0;
+
+    for raw in file.rules {
+        let diagnostic = |location, message: String| Diagnostic {
+            rule: Some(raw.name.clone()),
+            location,
+            message,
+        };
+        let rule_start = cursor;
+        let locate = |text: &str| match source[rule_start..].find(text) {
+            Some(offset) if !text.is_empty() => {
+                Location::File(rule_start + offset..rule_start + offset + text.len())
+            }
+            _ => Location::Unknown,
+        };
+
+        if raw.name.trim().is_empty() {
+            diagnostics.push(diagnostic(
+                Location::Unknown,
+                "rule name is empty".to_string(),
+            ));
+        } else if !names.insert(raw.name.clone()) {
+            diagnostics.push(diagnostic(
+                locate(&raw.name),
+                "another rule has the same name".to_string(),
+            ));
+        }
+
+        let condition_start = source[cursor..]
+            .find(&raw.condition)
+            .map(|offset| cursor + offset);
+        if let Some(start) = condition_start {
+            cursor = start + raw.condition.len();
+        }
+        let condition = match raw.condition.parse::<Condition>() {
+            Ok(condition) => Some(condition),
+            Err(e) => {
+                let location = match condition_start {
+                    Some(start) => Location::File(start + e.span.start..start + e.span.end),
+                    None => Location::Condition(raw.condition.clone(), e.span),
+                };
+                diagnostics.push(diagnostic(location, e.message));
+                None
+            }
+        };
+
+        let mut instruments = Vec::new();
+        for code in &raw.instruments {
+            match code.parse::<Instrument>() {
+                Ok(instrument) => instruments.push(instrument.tushare_code()),
+                Err(e) => diagnostics.push(diagnostic(
+                    locate(code),
+                    format!("invalid instrument '{}': {}", code, e),
+                )),
+            }
+        }
+
+        if let Some(condition) = condition {
+            rules.push(Rule {
+                name: raw.name,
+                kind: raw.kind,
+                condition,
+                instruments,
+            });
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(error(diagnostics).into());
+    }
+    if format == Format::Json {
+        warn!(
+            "{} uses the deprecated JSON rules format, convert it with validate-rules",
+            path.display()
+        );
+    }
+    Ok(rules)
+}
+
+/// Rules as the contents of a TOML rules file
+pub fn to_toml(rules: &[Rule]) -> anyhow::Result<String> {
+    let file = RulesFile {
+        rules: rules
+            .iter()
+            .map(|rule| RawRule {
+                name: rule.name.clone(),
+                condition: rule.condition.to_string(),
+                kind: rule.kind,
+                instruments: rule.instruments.clone(),
+            })
+            .collect(),
+    };
+    Ok(toml::to_string(&file)?)
+}
+
+/// Rules from the file at `ALERT_RULES_FILE`, none when it is not set
+pub fn configured_rules() -> anyhow::Result<Vec<Rule>> {
+    match configured_path() {
+        Some(path) => load(&path),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Path of the rules file from `ALERT_RULES_FILE`
+pub fn configured_path() -> Option<PathBuf> {
+    std::env::var("ALERT_RULES_FILE")
+        .ok()
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Names of the signal rules that hold at the latest bar
+///
+/// Rule file errors are logged and yield no signals, so they never fail a
+/// report.
+pub fn active_signals(data: &MarketData) -> Vec<String> {
+    let rules = match configured_rules() {
+        Ok(rules) => rules,
+        Err(e) => {
+            warn!("Failed to load rules: {:#}", e);
+            return Vec::new();
+        }
+    };
+    let code = data.instrument.tushare_code();
+    rules
+        .iter()
+        .filter(|rule| rule.kind == RuleKind::Signal && rule.applies_to(&code))
+        .filter(|rule| rule.condition.evaluate(&data.bars).current == Some(true))
+        .map(|rule| rule.name.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::market_close_utc;
+    use chrono::{Days, NaiveDate};
+
+    fn condition(source: &str) -> Condition {
+        source
+            .parse()
+            .unwrap_or_else(|e: ExprError| panic!("'{}': {}", source, e.message))
+    }
+
+    /// The error of an invalid condition and the text it points at
+    fn error(source: &str) -> (String, &str) {
+        match source.parse::<Condition>() {
+            Ok(condition) => panic!("'{}' parsed as {}", source, condition),
+            Err(e) => (e.message, &source[e.span]),
+        }
+    }
+
+    /// Error message of a rules file
+    fn file_error(name: &str, source: &str) -> String {
+        let path = Path::new(name);
+        let format = Format::of(path).unwrap();
+        match parse(path, format, source) {
+            Ok(rules) => panic!("{} rule(s) loaded from {}", rules.len(), name),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn closes(closes: &[f64]) -> Vec<StockData> {
+        let start = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| StockData {
+                date: market_close_utc(start + Days::new(i as u64)),
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: 1_000,
+                amount: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let parsed =
+            condition("close < SMA(60) and rsi(14) < 30 or not volume > 2 * avg_volume(20)");
+        assert_eq!(
+            parsed.to_string(),
+            "((close < sma(60)) and (rsi(14) < 30)) or not (volume > (2 * avg_volume(20)))"
+        );
+        assert_eq!(
+            parsed.metrics,
+            [
+                Metric::Close,
+                Metric::Sma(60),
+                Metric::Rsi(14),
+                Metric::Volume,
+                Metric::AverageVolume(20),
+            ]
+        );
+
+        // Arithmetic binds tighter than comparisons, and is left-associative
+        assert_eq!(
+            condition("close - 1 - 2 > -(3 + high) / 2").to_string(),
+            "(close - 1 - 2) > (-(3 + high) / 2)"
+        );
+        assert_eq!(
+            condition("close crosses_above kijun").to_string(),
+            "close crosses_above kijun"
+        );
+        for source in [
+            "wma(10) > atr(14)",
+            "cci(20) < -100 and williams_r(14) < -80",
+            "vwap(5) > obv",
+            "plus_di > minus_di and adx > 25",
+            "close > psar and senkou_a > senkou_b and tenkan > kijun",
+            "change_pct <= pct_change(5) and highest(20) >= lowest(20)",
+        ] {
+            condition(source);
+        }
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert_eq!(error(""), ("condition is empty".to_string(), ""));
+        assert_eq!(
+            error("close = 1"),
+            ("use '==' to compare for equality".to_string(), "=")
+        );
+        assert_eq!(error("close < 1 && open > 1").1, "&");
+        assert_eq!(
+            error("close < 1 < 2"),
+            (
+                "comparisons cannot be chained, combine them with 'and'".to_string(),
+                "<"
+            )
+        );
+        assert_eq!(
+            error("close crosses sma(20)").0,
+            "use 'crosses_above' or 'crosses_below'"
+        );
+        assert_eq!(
+            error("close < (sma(20)"),
+            ("expected ')', found end of condition".to_string(), "")
+        );
+        assert_eq!(
+            error("close < and"),
+            ("expected a value, found 'and'".to_string(), "and")
+        );
+        assert_eq!(
+            error("close < 1.2.3"),
+            ("invalid number '1.2.3'".to_string(), "1.2.3")
+        );
+    }
+
+    #[test]
+    fn reports_type_errors() {
+        assert_eq!(
+            error("close + 1"),
+            (
+                "condition must be true or false, found a number; compare it with something, e.g. '> 0'"
+                    .to_string(),
+                "close + 1"
+            )
+        );
+        assert_eq!(
+            error("close and volume > 1"),
+            (
+                "expected a true/false condition, found a number".to_string(),
+                "close"
+            )
+        );
+        assert_eq!(
+            error("(close > 1) * 2 > 1"),
+            (
+                "expected a number, found a true/false condition".to_string(),
+                "(close > 1)"
+            )
+        );
+        assert_eq!(
+            error("close > sma"),
+            ("'sma' needs a period, e.g. sma(20)".to_string(), "sma")
+        );
+        assert_eq!(
+            error("close > sma(2.5)"),
+            (
+                "period of 'sma' must be a whole number of bars".to_string(),
+                "2.5"
+            )
+        );
+        assert_eq!(error("close > sma(0)").1, "0");
+        assert_eq!(
+            error("close > sma(5, 10)"),
+            (
+                "'sma' takes one period, found 2 arguments".to_string(),
+                "sma(5, 10)"
+            )
+        );
+        assert_eq!(
+            error("close(1) > 1"),
+            ("'close' takes no arguments".to_string(), "close(1)")
+        );
+        assert_eq!(
+            error("close > smaa(60)"),
+            ("unknown function 'smaa'".to_string(), "smaa(60)")
+        );
+        assert_eq!(
+            error("closing > 1"),
+            ("unknown value 'closing'".to_string(), "closing")
+        );
+    }
+
+    #[test]
+    fn evaluates_the_last_two_bars() {
+        let crosses = condition("close crosses_above sma(3)");
+        let bars = closes(&[10.0, 9.0, 8.0, 7.0, 9.0]);
+        let evaluation = crosses.evaluate(&bars);
+        assert_eq!(evaluation.previous, Some(false));
+        assert_eq!(evaluation.current, Some(true));
+        assert_eq!(evaluation.values[0], (Metric::Close, Some(9.0)));
+        assert_eq!(evaluation.values[1].0, Metric::Sma(3));
+        assert!((evaluation.values[1].1.unwrap() - 8.0).abs() < 1e-9);
+
+        // Undefined during warm-up
+        let evaluation = crosses.evaluate(&bars[..4]);
+        assert_eq!(evaluation.previous, None);
+        assert_eq!(evaluation.current, Some(false));
+        assert_eq!(condition("rsi(14) < 30").evaluate(&bars).current, None);
+    }
+
+    #[test]
+    fn loads_toml_and_yaml() {
+        let toml = r#"
+[[rules]]
+name = "Oversold"
+condition = "close < sma(60) and rsi(14) < 30"
+
+[[rules]]
+name = "Breakout"
+kind = "signal"
+condition = "close > highest(20)"
+instruments = ["600519"]
+"#;
+        let yaml = r#"
+rules:
+  - name: Oversold
+    condition: close < sma(60) and rsi(14) < 30
+  - name: Breakout
+    kind: signal
+    condition: close > highest(20)
+    instruments: [600519]
+"#;
+        for (name, source) in [("rules.toml", toml), ("rules.yaml", yaml)] {
+            let path = Path::new(name);
+            let rules = parse(path, Format::of(path).unwrap(), source).unwrap();
+            assert_eq!(rules.len(), 2);
+            assert_eq!(rules[0].kind, RuleKind::Alert);
+            assert!(rules[0].applies_to("000300.SH"));
+            assert_eq!(rules[1].kind, RuleKind::Signal);
+            assert_eq!(rules[1].instruments, ["600519.SH"]);
+            assert!(!rules[1].applies_to("000300.SH"));
+        }
+        assert!(Format::of(Path::new("rules.txt")).is_none());
+    }
+
+    #[test]
+    fn loads_json_rules() {
+        let json = r#"{
+  "rules": [
+    {"name": "Close below MA60", "left": "close", "op": "crosses_below", "right": "sma(60)"},
+    {"name": "Daily drop", "left": "change_pct", "op": "<", "right": -2},
+    {"name": "Volume spike", "left": "volume", "op": ">", "right": {"times": 2, "metric": "avg_volume(20)"}},
+    {"name": "Moutai below MA20", "left": "close", "op": "<", "right": "sma(20)", "instruments": ["600519.SH"]}
+  ]
+}"#;
+        let path = Path::new("alert_rules.json");
+        let rules = parse(path, Format::Json, json).unwrap();
+        let conditions: Vec<String> = rules
+            .iter()
+            .map(|rule| rule.condition.to_string())
+            .collect();
+        assert_eq!(
+            conditions,
+            [
+                "close crosses_below sma(60)",
+                "change_pct < -2",
+                "volume > (2 * avg_volume(20))",
+                "close < sma(20)",
+            ]
+        );
+        assert!(rules.iter().all(|rule| rule.kind == RuleKind::Alert));
+        assert_eq!(rules[3].instruments, ["600519.SH"]);
+
+        // The suggested TOML loads to the same rules
+        let equivalent = to_toml(&rules).unwrap();
+        let reloaded = parse(Path::new("rules.toml"), Format::Toml, &equivalent).unwrap();
+        let reloaded: Vec<String> = reloaded
+            .iter()
+            .map(|rule| rule.condition.to_string())
+            .collect();
+        assert_eq!(reloaded, conditions);
+    }
+
+    #[test]
+    fn reports_errors_with_line_and_column() {
+        let toml = r#"[[rules]]
+name = "Oversold below MA60"
+condition = "close < smaa(60) and rsi(14) < 30"
+
+[[rules]]
+name = "Oversold below MA60"
+condition = "close < sma(60)"
+instruments = ["600519", "nope"]
+"#;
+        let message = file_error("alert_rules.toml", toml);
+        assert!(message.starts_with("3 error(s) in rules file alert_rules.toml\n"));
+        assert!(message.contains(
+            "alert_rules.toml:3:22: error in rule 'Oversold below MA60': unknown function 'smaa'\n    \
+             condition = \"close < smaa(60) and rsi(14) < 30\"\n                         ^^^^^^^^\n"
+        ));
+        assert!(message.contains(
+            "alert_rules.toml:6:9: error in rule 'Oversold below MA60': another rule has the same name\n"
+        ));
+        assert!(message.contains(
+            "alert_rules.toml:8:27: error in rule 'Oversold below MA60': invalid instrument 'nope'"
+        ));
+
+        // Syntax errors of the file itself
+        let message = file_error("rules.toml", "[[rules]]\nname = \"A\"\ncondition = close\n");
+        assert!(message.contains("rules.toml:3:13: error:"), "{}", message);
+        let message = file_error(
+            "rules.yaml",
+            "rules:\n  - name: A\n    condtion: close > 1\n",
+        );
+        assert!(message.contains("rules.yaml:3:"), "{}", message);
+        assert!(message.contains("unknown field `condtion`"), "{}", message);
+        let message = file_error("rules.json", "{\"rules\": [\n  {\"name\": \"A\",}\n]}");
+        assert!(message.contains("rules.json:2:16: error:"), "{}", message);
+
+        // Conditions the JSON format builds are not in the file verbatim
+        let json = r#"{"rules": [{"name": "A", "left": "close", "op": "<", "right": "smaa(5)"}]}"#;
+        let message = file_error("rules.json", json);
+        assert!(
+            message.contains(
+                "rules.json: error in rule 'A', condition column 9: unknown function 'smaa'\n    \
+                 close < smaa(5)\n            ^^^^^^^\n"
+            ),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,692 @@
+use crate::calendar::{Period, TradingCalendar};
+use crate::models::{local_datetime, market_timezone};
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use std::fmt;
+use std::str::FromStr;
+
+/// Local market time of a schedule that does not name one, well after the
+/// 15:00 close
+const DEFAULT_RUN_TIME: (u32, u32) = (20, 0);
+
+/// How far ahead to look for the next run before giving up, e.g. for
+/// `0 0 30 2 *` or the 30th trading day of a month
+const MAX_SEARCH_DAYS: i64 = 366 * 5;
+
+const WEEKDAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const MONTH_NAMES: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// When a job runs, in local market time
+///
+/// Parsed from either a 5-field cron expression (`30 15 * * 1-5`) or a small
+/// DSL built around the trading calendar:
+///
+/// ```text
+/// every trading day 15:30
+/// every day 08:00
+/// every friday 20:00
+/// first trading day of week 09:00
+/// last trading day of month 16:00
+/// 3rd trading day of quarter 20:00
+/// ```
+///
+/// The time may be omitted and defaults to 20:00. The names `daily`,
+/// `weekly`, `monthly` and `quarterly` are shorthands for the report modes'
+/// default schedules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    kind: ScheduleKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ScheduleKind {
+    Calendar { days: DayRule, time: NaiveTime },
+    Cron(CronExpr),
+}
+
+/// Which dates a DSL schedule fires on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DayRule {
+    EveryDay,
+    EveryTradingDay,
+    Weekday(Weekday),
+    FirstTradingDay(Period),
+    LastTradingDay(Period),
+    NthTradingDay(Period, usize),
+}
+
+/// Parsed 5-field cron expression, each field a bit set of allowed values
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    /// Bit 0 is Sunday
+    days_of_week: u64,
+    /// Whether both day fields are restricted, in which case a date matching
+    /// either one fires (standard cron semantics). Fields starting with `*`,
+    /// such as `*/2`, do not count as restricted.
+    either_day: bool,
+}
+
+impl Schedule {
+    /// Default schedule of a report mode
+    pub fn for_mode(mode: &str) -> Result<Self> {
+        match mode {
+            "daily" | "weekly" | "monthly" | "quarterly" => mode.parse(),
+            _ => bail!("No default schedule for mode '{}'", mode),
+        }
+    }
+
+    /// The expression the schedule was parsed from
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// First run strictly after `after`
+    ///
+    /// Returns `None` when the schedule does not fire within the next five
+    /// years.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next_after_in(market_timezone(), after)
+    }
+
+    /// First run strictly after `after`, with the schedule read in `tz`
+    fn next_after_in(&self, tz: Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let first_date = after.with_timezone(&tz).date_naive();
+
+        (0..MAX_SEARCH_DAYS)
+            .map(|offset| first_date + Duration::days(offset))
+            .find_map(|date| self.first_run_on(tz, date, after))
+    }
+
+    /// The next `count` runs after `after`
+    pub fn upcoming(&self, after: DateTime<Utc>, count: usize) -> Vec<DateTime<Utc>> {
+        let mut runs = Vec::with_capacity(count);
+        let mut current = after;
+        while runs.len() < count {
+            match self.next_after(current) {
+                Some(next) => {
+                    runs.push(next);
+                    current = next;
+                }
+                None => break,
+            }
+        }
+        runs
+    }
+
+    /// Earliest run on a local date in `tz` that is after `after`
+    fn first_run_on(&self, tz: Tz, date: NaiveDate, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.kind {
+            ScheduleKind::Calendar { days, time } => {
+                let run = local_datetime(tz, date, *time);
+                (run > after && days.matches(date)).then_some(run)
+            }
+            ScheduleKind::Cron(cron) => {
+                if !cron.matches_date(date) {
+                    return None;
+                }
+                cron.times().find_map(|time| {
+                    let run = local_datetime(tz, date, time);
+                    // Skip times a DST transition moved onto another date
+                    (run > after && run.with_timezone(&tz).date_naive() == date).then_some(run)
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let expression = s.trim();
+        let normalized = expression.to_ascii_lowercase();
+        let words: Vec<&str> = normalized.split_whitespace().collect();
+        if words.is_empty() {
+            bail!("Empty schedule");
+        }
+
+        let kind = match normalized.as_str() {
+            "daily" => parse_dsl(&["every", "trading", "day"])?,
+            "weekly" => parse_dsl(&["last", "trading", "day", "of", "week"])?,
+            "monthly" => parse_dsl(&["last", "trading", "day", "of", "month"])?,
+            "quarterly" => parse_dsl(&["last", "trading", "day", "of", "quarter"])?,
+            // Every 5-word DSL form mentions trading days
+            _ if words.len() == 5 && !words.contains(&"trading") => {
+                ScheduleKind::Cron(CronExpr::parse(&words)?)
+            }
+            _ => parse_dsl(&words)?,
+        };
+
+        Ok(Self {
+            expression: expression.to_string(),
+            kind,
+        })
+    }
+}
+
+impl DayRule {
+    fn matches(self, date: NaiveDate) -> bool {
+        let calendar = TradingCalendar::global();
+        match self {
+            DayRule::EveryDay => true,
+            DayRule::EveryTradingDay => calendar.is_trading_day(date),
+            DayRule::Weekday(weekday) => date.weekday() == weekday,
+            DayRule::FirstTradingDay(period) => calendar.is_first_trading_day(period, date),
+            DayRule::LastTradingDay(period) => calendar.is_last_trading_day(period, date),
+            DayRule::NthTradingDay(period, n) => {
+                calendar.nth_trading_day(period, date, n) == Some(date)
+            }
+        }
+    }
+}
+
+/// Parse a lowercase DSL schedule split into words
+fn parse_dsl(words: &[&str]) -> Result<ScheduleKind> {
+    let (time, words) = match words.split_last() {
+        Some((last, rest)) if last.contains(':') => (parse_time(last)?, rest),
+        _ => (
+            NaiveTime::from_hms_opt(DEFAULT_RUN_TIME.0, DEFAULT_RUN_TIME.1, 0).unwrap(),
+            words,
+        ),
+    };
+
+    let days = match words {
+        ["every", "day"] => DayRule::EveryDay,
+        ["every", "trading", "day"] => DayRule::EveryTradingDay,
+        ["every", weekday] => DayRule::Weekday(parse_weekday(weekday)?),
+        [ordinal, "trading", "day", "of", period] => {
+            let period = parse_period(period)?;
+            match *ordinal {
+                "first" => DayRule::FirstTradingDay(period),
+                "last" => DayRule::LastTradingDay(period),
+                ordinal => DayRule::NthTradingDay(period, parse_ordinal(ordinal)?),
+            }
+        }
+        _ => bail!(
+            "Unrecognized schedule '{}', expected e.g. \"every trading day 15:30\", \
+            \"last trading day of month 16:00\" or a 5-field cron expression",
+            words.join(" ")
+        ),
+    };
+
+    Ok(ScheduleKind::Calendar { days, time })
+}
+
+fn parse_time(word: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(word, "%H:%M")
+        .map_err(|_| anyhow!("Invalid time '{}', expected HH:MM", word))
+}
+
+fn parse_weekday(word: &str) -> Result<Weekday> {
+    word.parse::<Weekday>()
+        .map_err(|_| anyhow!("Invalid weekday '{}'", word))
+}
+
+fn parse_period(word: &str) -> Result<Period> {
+    match word {
+        "week" => Ok(Period::Week),
+        "month" => Ok(Period::Month),
+        "quarter" => Ok(Period::Quarter),
+        "year" => Ok(Period::Year),
+        _ => bail!(
+            "Invalid period '{}', expected week, month, quarter or year",
+            word
+        ),
+    }
+}
+
+/// Parse `1st`, `2nd`, `3rd`, `4th`, ... or a bare number
+fn parse_ordinal(word: &str) -> Result<usize> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    match digits.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => bail!(
+            "Invalid ordinal '{}', expected first, last or e.g. 3rd",
+            word
+        ),
+    }
+}
+
+impl CronExpr {
+    fn parse(fields: &[&str]) -> Result<Self> {
+        let [minute, hour, day_of_month, month, day_of_week] = fields else {
+            bail!("Cron expressions need 5 fields, got {}", fields.len());
+        };
+
+        let mut days_of_week =
+            parse_cron_field(day_of_week, "day of week", 0, 7, WEEKDAY_NAMES, 0)?;
+        // Both 0 and 7 are Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_cron_field(minute, "minute", 0, 59, &[], 0)?,
+            hours: parse_cron_field(hour, "hour", 0, 23, &[], 0)?,
+            days_of_month: parse_cron_field(day_of_month, "day of month", 1, 31, &[], 0)?,
+            months: parse_cron_field(month, "month", 1, 12, MONTH_NAMES, 1)?,
+            days_of_week,
+            either_day: !day_of_month.starts_with('*') && !day_of_week.starts_with('*'),
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !has_bit(self.months, date.month()) {
+            return false;
+        }
+
+        let day_of_month = has_bit(self.days_of_month, date.day());
+        let day_of_week = has_bit(self.days_of_week, date.weekday().num_days_from_sunday());
+        if self.either_day {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+
+    /// Matching times of day in ascending order
+    fn times(&self) -> impl Iterator<Item = NaiveTime> + '_ {
+        (0..24)
+            .filter(|hour| has_bit(self.hours, *hour))
+            .flat_map(move |hour| {
+                (0..60)
+                    .filter(|minute| has_bit(self.minutes, *minute))
+                    .map(move |minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap())
+            })
+    }
+}
+
+fn has_bit(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parse a cron field (`*`, `5`, `1-5`, `*/15`, `mon-fri`, `1,15`) into a bit
+/// set of allowed values
+///
+/// `names[i]` is accepted in place of `i + name_offset`.
+fn parse_cron_field(
+    field: &str,
+    label: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    name_offset: u32,
+) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        let parsed = match names.iter().position(|name| *name == s) {
+            Some(index) => index as u32 + name_offset,
+            None => s
+                .parse::<u32>()
+                .map_err(|_| anyhow!("Invalid {} '{}' in cron expression", label, s))?,
+        };
+        if !(min..=max).contains(&parsed) {
+            bail!(
+                "{} {} out of range {}-{} in cron expression",
+                label,
+                parsed,
+                min,
+                max
+            );
+        }
+        Ok(parsed)
+    };
+
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => bail!("Invalid step '{}' in cron {} field", step, label),
+            },
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/10` means every 10 starting at 5
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let single = value(range)?;
+                    (single, single)
+                }
+            },
+        };
+        if start > end {
+            bail!("Invalid range '{}' in cron {} field", range, label);
+        }
+
+        for v in (start..=end).step_by(step as usize) {
+            set |= 1 << v;
+        }
+    }
+
+    Ok(set)
+}
+
+/// Format a run time in the market timezone for previews and logs
+pub fn format_run(run: DateTime<Utc>) -> String {
+    run.with_timezone(&market_timezone())
+        .format("%Y-%m-%d %H:%M %Z (%a)")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::market_datetime;
+    use chrono::NaiveDateTime;
+
+    /// Instant of a local market time such as `2025-09-30 16:00`
+    fn at(local: &str) -> DateTime<Utc> {
+        let local = NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M").unwrap();
+        market_datetime(local.date(), local.time())
+    }
+
+    /// The next `count` runs of a schedule after a local market time
+    fn runs(expression: &str, after: &str, count: usize) -> Vec<String> {
+        let schedule: Schedule = expression.parse().unwrap();
+        schedule
+            .upcoming(at(after), count)
+            .into_iter()
+            .map(format_run)
+            .collect()
+    }
+
+    fn parse_error(expression: &str) -> String {
+        expression.parse::<Schedule>().unwrap_err().to_string()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn every_trading_day_skips_holidays_and_makeup_workdays() {
+        // 2025-09-28 and 2025-10-11 are make-up workdays around the
+        // October 1-8 holiday, both closed
+        assert_eq!(
+            runs("every trading day 15:30", "2025-09-26 16:00", 5),
+            [
+                "2025-09-29 15:30 CST (Mon)",
+                "2025-09-30 15:30 CST (Tue)",
+                "2025-10-09 15:30 CST (Thu)",
+                "2025-10-10 15:30 CST (Fri)",
+                "2025-10-13 15:30 CST (Mon)",
+            ]
+        );
+    }
+
+    #[test]
+    fn next_run_is_strictly_after() {
+        assert_eq!(
+            runs("every trading day 15:30", "2025-09-29 09:00", 1),
+            ["2025-09-29 15:30 CST (Mon)"]
+        );
+        assert_eq!(
+            runs("every trading day 15:30", "2025-09-29 15:30", 1),
+            ["2025-09-30 15:30 CST (Tue)"]
+        );
+    }
+
+    #[test]
+    fn last_trading_day_of_month() {
+        // The Spring Festival closes the exchanges from January 28
+        assert_eq!(
+            runs("last trading day of month 16:00", "2025-01-01 00:00", 3),
+            [
+                "2025-01-27 16:00 CST (Mon)",
+                "2025-02-28 16:00 CST (Fri)",
+                "2025-03-31 16:00 CST (Mon)",
+            ]
+        );
+        assert_eq!(
+            runs("monthly", "2025-09-30 19:59", 2),
+            ["2025-09-30 20:00 CST (Tue)", "2025-10-31 20:00 CST (Fri)"]
+        );
+    }
+
+    #[test]
+    fn trading_days_of_weeks_and_quarters() {
+        // The October holiday ends the week on Tuesday; the next week has no
+        // trading before Thursday and the make-up Saturday stays closed
+        assert_eq!(
+            runs("weekly", "2025-09-29 00:00", 2),
+            ["2025-09-30 20:00 CST (Tue)", "2025-10-10 20:00 CST (Fri)"]
+        );
+        assert_eq!(
+            runs("first trading day of week 09:00", "2025-10-05 00:00", 1),
+            ["2025-10-09 09:00 CST (Thu)"]
+        );
+        assert_eq!(
+            runs("3rd trading day of quarter", "2025-10-01 00:00", 1),
+            ["2025-10-13 20:00 CST (Mon)"]
+        );
+        assert_eq!(
+            runs("quarterly", "2025-10-01 00:00", 1),
+            ["2025-12-31 20:00 CST (Wed)"]
+        );
+    }
+
+    #[test]
+    fn calendar_days_ignore_the_trading_calendar() {
+        assert_eq!(
+            runs("every friday", "2025-10-01 00:00", 1),
+            ["2025-10-03 20:00 CST (Fri)"]
+        );
+        assert_eq!(
+            runs("Every Day 08:00", "2025-10-01 08:00", 2),
+            ["2025-10-02 08:00 CST (Thu)", "2025-10-03 08:00 CST (Fri)"]
+        );
+    }
+
+    #[test]
+    fn schedules_that_never_fire() {
+        let schedule: Schedule = "6th trading day of week".parse().unwrap();
+        assert_eq!(schedule.next_after(at("2025-01-01 00:00")), None);
+        assert!(schedule.upcoming(at("2025-01-01 00:00"), 3).is_empty());
+
+        let schedule: Schedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(schedule.next_after(at("2025-01-01 00:00")), None);
+    }
+
+    #[test]
+    fn cron_ignores_the_trading_calendar() {
+        assert_eq!(
+            runs("30 15 * * mon-fri", "2025-09-30 16:00", 2),
+            ["2025-10-01 15:30 CST (Wed)", "2025-10-02 15:30 CST (Thu)"]
+        );
+    }
+
+    #[test]
+    fn cron_step_day_fields_are_not_restrictions() {
+        // Odd days that are weekdays, not every weekday
+        assert_eq!(
+            runs("0 16 */2 * 1-5", "2025-09-01 00:00", 4),
+            [
+                "2025-09-01 16:00 CST (Mon)",
+                "2025-09-03 16:00 CST (Wed)",
+                "2025-09-05 16:00 CST (Fri)",
+                "2025-09-09 16:00 CST (Tue)",
+            ]
+        );
+    }
+
+    #[test]
+    fn cron_restricted_day_fields_match_either() {
+        assert_eq!(
+            runs("0 9 1,15 * mon", "2025-09-01 10:00", 5),
+            [
+                "2025-09-08 09:00 CST (Mon)",
+                "2025-09-15 09:00 CST (Mon)",
+                "2025-09-22 09:00 CST (Mon)",
+                "2025-09-29 09:00 CST (Mon)",
+                "2025-10-01 09:00 CST (Wed)",
+            ]
+        );
+    }
+
+    #[test]
+    fn cron_times_within_a_day() {
+        assert_eq!(
+            runs("0,30 9 * * 7", "2025-09-07 09:00", 3),
+            [
+                "2025-09-07 09:30 CST (Sun)",
+                "2025-09-14 09:00 CST (Sun)",
+                "2025-09-14 09:30 CST (Sun)",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_cron_fields() {
+        let bits = |values: &[u32]| values.iter().fold(0u64, |set, v| set | 1 << v);
+
+        let minute = |field| parse_cron_field(field, "minute", 0, 59, &[], 0);
+        assert_eq!(minute("*/15").unwrap(), bits(&[0, 15, 30, 45]));
+        assert_eq!(minute("5/20").unwrap(), bits(&[5, 25, 45]));
+        assert_eq!(minute("1-10/3").unwrap(), bits(&[1, 4, 7, 10]));
+        assert_eq!(minute("1,15,59").unwrap(), bits(&[1, 15, 59]));
+        assert_eq!(minute("*").unwrap(), (1u64 << 60) - 1);
+
+        let weekday = parse_cron_field("mon-fri", "day of week", 0, 7, WEEKDAY_NAMES, 0);
+        assert_eq!(weekday.unwrap(), bits(&[1, 2, 3, 4, 5]));
+        let month = parse_cron_field("jan,jul-sep", "month", 1, 12, MONTH_NAMES, 1);
+        assert_eq!(month.unwrap(), bits(&[1, 7, 8, 9]));
+    }
+
+    #[test]
+    fn rejects_invalid_cron_fields() {
+        let minute = |field| {
+            parse_cron_field(field, "minute", 0, 59, &[], 0)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            minute("60"),
+            "minute 60 out of range 0-59 in cron expression"
+        );
+        assert_eq!(minute("*/0"), "Invalid step '0' in cron minute field");
+        assert_eq!(minute("5-1"), "Invalid range '5-1' in cron minute field");
+        assert_eq!(minute("noon"), "Invalid minute 'noon' in cron expression");
+    }
+
+    #[test]
+    fn parses_cron_expressions() {
+        let cron = CronExpr::parse(&["0", "16", "*/2", "*", "1-5"]).unwrap();
+        assert!(!cron.either_day);
+        let cron = CronExpr::parse(&["0", "9", "1", "*", "mon"]).unwrap();
+        assert!(cron.either_day);
+        let cron = CronExpr::parse(&["0", "9", "*", "*", "mon"]).unwrap();
+        assert!(!cron.either_day);
+
+        // 7 is Sunday too
+        let cron = CronExpr::parse(&["0", "9", "*", "*", "5-7"]).unwrap();
+        assert_eq!(cron.days_of_week, 0b110_0001);
+
+        assert_eq!(
+            CronExpr::parse(&["0", "9", "*", "*"])
+                .unwrap_err()
+                .to_string(),
+            "Cron expressions need 5 fields, got 4"
+        );
+    }
+
+    #[test]
+    fn parses_the_dsl() {
+        let parse = |expression: &str| expression.parse::<Schedule>().unwrap().kind;
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+
+        assert_eq!(
+            parse("every friday"),
+            ScheduleKind::Calendar {
+                days: DayRule::Weekday(Weekday::Fri),
+                time: time(20, 0),
+            }
+        );
+        assert_eq!(
+            parse("2nd trading day of year 09:15"),
+            ScheduleKind::Calendar {
+                days: DayRule::NthTradingDay(Period::Year, 2),
+                time: time(9, 15),
+            }
+        );
+        assert_eq!(parse("daily"), parse("every trading day 20:00"));
+        assert_eq!(parse("weekly"), parse("last trading day of week"));
+        assert_eq!(parse("monthly"), parse("last trading day of month"));
+        assert_eq!(parse("quarterly"), parse("last trading day of quarter"));
+
+        let schedule: Schedule = "  Last Trading Day of Month 16:00 ".parse().unwrap();
+        assert_eq!(schedule.expression(), "Last Trading Day of Month 16:00");
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        assert_eq!(parse_error("  "), "Empty schedule");
+        assert_eq!(parse_error("every blursday"), "Invalid weekday 'blursday'");
+        assert_eq!(
+            parse_error("every trading day 25:00"),
+            "Invalid time '25:00', expected HH:MM"
+        );
+        assert_eq!(
+            parse_error("0th trading day of month"),
+            "Invalid ordinal '0th', expected first, last or e.g. 3rd"
+        );
+        assert_eq!(
+            parse_error("last trading day of decade"),
+            "Invalid period 'decade', expected week, month, quarter or year"
+        );
+        assert!(parse_error("whenever").starts_with("Unrecognized schedule 'whenever'"));
+        assert_eq!(
+            Schedule::for_mode("yearly").unwrap_err().to_string(),
+            "No default schedule for mode 'yearly'"
+        );
+    }
+
+    #[test]
+    fn skipped_local_times_run_at_the_next_valid_instant() {
+        // New York skips 02:00-03:00 on 2025-03-09
+        let schedule: Schedule = "30 2 * * *".parse().unwrap();
+        let tz = chrono_tz::America::New_York;
+        assert_eq!(
+            schedule.next_after_in(tz, utc("2025-03-08T17:00:00Z")),
+            Some(utc("2025-03-09T07:00:00Z"))
+        );
+
+        // Repeated local times run once, at the earlier instant
+        let schedule: Schedule = "30 1 * * *".parse().unwrap();
+        let first = schedule.next_after_in(tz, utc("2025-11-01T12:00:00Z"));
+        assert_eq!(first, Some(utc("2025-11-02T05:30:00Z")));
+        assert_eq!(
+            schedule.next_after_in(tz, first.unwrap()),
+            Some(utc("2025-11-03T06:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn skips_runs_a_transition_moves_onto_another_date() {
+        // Samoa skipped 2011-12-30 entirely; its noon run must not fire at
+        // midnight on the 31st
+        let schedule: Schedule = "0 12 * * *".parse().unwrap();
+        let tz = chrono_tz::Pacific::Apia;
+        assert_eq!(
+            schedule.next_after_in(tz, utc("2011-12-29T23:00:00Z")),
+            Some(utc("2011-12-30T22:00:00Z"))
+        );
+    }
+}
@@ -1,164 +1,91 @@
-use chrono::{DateTime, Datelike, Timelike, Utc, Weekday};
+use crate::calendar::{Period, TradingCalendar};
+use crate::models::{market_date, market_timezone};
+use crate::schedule::{Schedule, format_run};
+use chrono::{DateTime, Datelike, Utc, Weekday};
 use std::time::Duration;
+use tokio::sync::watch;
 use tokio::time;
 use tracing::{info, warn};
 
-/// Check if the date is a workday
-#[allow(dead_code)]
+/// Check if the date is a workday, i.e. an A-share trading day
+///
+/// The instant is converted to its market date first. Exchange holidays and
+/// weekend make-up workdays are not trading days.
 pub fn is_workday(date: DateTime<Utc>) -> bool {
-    let weekday = date.weekday();
-    !matches!(weekday, Weekday::Sat | Weekday::Sun)
+    TradingCalendar::global().is_trading_day(market_date(date))
 }
 
-/// Check if the date is the last workday of the month
+/// Check if the date is the last trading day of its month
 #[allow(dead_code)]
 pub fn is_last_workday_of_month(date: DateTime<Utc>) -> bool {
-    let current_month = date.month();
-    let next_month = if current_month == 12 {
-        1
-    } else {
-        current_month + 1
-    };
-    let next_year = if current_month == 12 {
-        date.year() + 1
-    } else {
-        date.year()
-    };
-
-    let next_month_first = Utc::now()
-        .with_year(next_year)
-        .unwrap()
-        .with_month(next_month)
-        .unwrap()
-        .with_day(1)
-        .unwrap()
-        .with_hour(0)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap();
-
-    let days_until_next_month = (next_month_first - date).num_days();
-
-    // If there are 1-3 days until next month and it's a workday, consider it the last workday of the month
-    (1..=3).contains(&days_until_next_month) && is_workday(date)
+    TradingCalendar::global().is_last_trading_day(Period::Month, market_date(date))
 }
 
-/// Check if the date is Friday
+/// Check if the date is Friday in the market timezone
 #[allow(dead_code)]
 pub fn is_friday(date: DateTime<Utc>) -> bool {
-    date.weekday() == Weekday::Fri
+    market_date(date).weekday() == Weekday::Fri
 }
 
 /// Calculate next execution time
-#[allow(dead_code)]
-pub fn get_next_execution_time(mode: &str, current_time: DateTime<Utc>) -> DateTime<Utc> {
-    let target_hour = 20; // 8 PM
-    let target_minute = 0;
-
-    match mode {
-        "daily" => {
-            // Daily execution: next workday at 8 PM
-            let mut next_time = current_time
-                .with_hour(target_hour)
-                .unwrap()
-                .with_minute(target_minute)
-                .unwrap()
-                .with_second(0)
-                .unwrap();
-
-            if next_time <= current_time {
-                // If it's not yet 8 PM today, schedule for tomorrow
-                next_time += chrono::Duration::days(1);
-            }
-
-            // If it's weekend, schedule for next workday
-            while !is_workday(next_time) {
-                next_time += chrono::Duration::days(1);
-            }
-
-            next_time
-        }
-        "weekly" => {
-            // Weekly execution: every Friday
-            let mut next_time = current_time
-                .with_hour(target_hour)
-                .unwrap()
-                .with_minute(target_minute)
-                .unwrap()
-                .with_second(0)
-                .unwrap();
-
-            // Find next Friday
-            while next_time.weekday() != Weekday::Fri || next_time <= current_time {
-                next_time += chrono::Duration::days(1);
-            }
-
-            next_time
-        }
-        "monthly" => {
-            // Monthly execution: last workday of the month
-            let next_time = current_time
-                .with_hour(target_hour)
-                .unwrap()
-                .with_minute(target_minute)
-                .unwrap()
-                .with_second(0)
-                .unwrap();
-
-            // Find first workday of next month
-            let mut check_date = next_time + chrono::Duration::days(1);
-            while !is_last_workday_of_month(check_date) {
-                check_date += chrono::Duration::days(1);
-            }
-
-            check_date
-                .with_hour(target_hour)
-                .unwrap()
-                .with_minute(target_minute)
-                .unwrap()
-                .with_second(0)
-                .unwrap()
-        }
-        _ => {
-            warn!("Unknown mode: {}, using daily mode", mode);
-            get_next_execution_time("daily", current_time)
-        }
-    }
+///
+/// Returns the first run of the schedule strictly after `current_time`, or
+/// `None` when it never fires again.
+pub fn get_next_execution_time(
+    schedule: &Schedule,
+    current_time: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    schedule.next_after(current_time)
 }
 
-/// Start scheduler
-#[allow(dead_code)]
-pub async fn start_scheduler<F, Fut>(mode: &str, mut handler: F) -> !
-where
-    F: FnMut() -> Fut + Send + 'static,
-    Fut: std::future::Future<Output = ()> + Send,
+/// Run `handler` on every run of a job's schedule until shutdown
+///
+/// The handler receives the scheduled time of the run.
+/// Runs of one job never overlap: the next run is computed after the handler
+/// returns, so runs missed while it was busy are skipped. A run in progress
+/// when shutdown is signalled is finished before returning.
+pub async fn start_scheduler<F, Fut>(
+    job: &str,
+    schedule: &Schedule,
+    mut shutdown: watch::Receiver<bool>,
+    mut handler: F,
+) where
+    F: FnMut(DateTime<Utc>) -> Fut,
+    Fut: std::future::Future<Output = ()>,
 {
-    info!("Starting scheduler, mode: {}", mode);
-
-    loop {
-        let now = Utc::now();
-        let next_execution = get_next_execution_time(mode, now);
-
-        let wait_duration = (next_execution - now)
+    info!(job, schedule = %schedule, "Starting scheduler");
+
+    let mut last_run: Option<DateTime<Utc>> = None;
+    while !*shutdown.borrow() {
+        // Never fire the same run twice, even if the clock lags the timer
+        let now = last_run.map_or(Utc::now(), |last| Utc::now().max(last));
+        let Some(next_execution) = get_next_execution_time(schedule, now) else {
+            warn!(job, schedule = %schedule, "Schedule has no further runs, stopping");
+            return;
+        };
+
+        let wait_duration = (next_execution - Utc::now())
             .to_std()
-            .unwrap_or(Duration::from_secs(60));
+            .unwrap_or(Duration::ZERO);
 
         info!(
-            "Next execution time: {}, waiting {} seconds",
-            next_execution.format("%Y-%m-%d %H:%M:%S UTC"),
-            wait_duration.as_secs()
+            job,
+            next_run = %format_run(next_execution),
+            wait_secs = wait_duration.as_secs(),
+            "Next execution scheduled"
         );
 
-        time::sleep(wait_duration).await;
-
-        info!("Executing scheduled task");
-        handler().await;
+        tokio::select! {
+            _ = time::sleep(wait_duration) => {}
+            _ = shutdown.changed() => break,
+        }
 
-        // Brief delay to avoid overly frequent execution
-        time::sleep(Duration::from_secs(5)).await;
+        info!(job, scheduled = %format_run(next_execution), "Executing scheduled task");
+        handler(next_execution).await;
+        last_run = Some(next_execution);
     }
+
+    info!(job, "Scheduler stopped");
 }
 
 /// Get current time information
@@ -176,7 +103,9 @@ pub fn get_time_info(date: DateTime<Utc>) -> (bool, bool, bool) {
 pub fn format_time_info(date: DateTime<Utc>) -> String {
     let (is_workday, is_friday, is_last_workday) = get_time_info(date);
 
-    let weekday_str = match date.weekday() {
+    let local = date.with_timezone(&market_timezone());
+
+    let weekday_str = match local.weekday() {
         Weekday::Mon => "Monday",
         Weekday::Tue => "Tuesday",
         Weekday::Wed => "Wednesday",
@@ -188,10 +117,52 @@ pub fn format_time_info(date: DateTime<Utc>) -> String {
 
     format!(
         "Date: {}, Weekday: {}, Workday: {}, Friday: {}, Last workday of month: {}",
-        date.format("%Y-%m-%d %H:%M:%S"),
+        local.format("%Y-%m-%d %H:%M:%S %Z"),
         weekday_str,
         if is_workday { "Yes" } else { "No" },
         if is_friday { "Yes" } else { "No" },
         if is_last_workday { "Yes" } else { "No" }
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn judges_instants_by_their_market_date() {
+        // 23:59 on Tuesday 2025-09-30 in Shanghai, then 00:30 on the first
+        // day of the National Day holiday
+        let last_session = utc("2025-09-30T15:59:00Z");
+        let holiday = utc("2025-09-30T16:30:00Z");
+
+        assert!(is_workday(last_session));
+        assert!(!is_workday(holiday));
+        assert!(is_last_workday_of_month(last_session));
+        assert!(!is_last_workday_of_month(holiday));
+        assert!(is_last_workday_of_month(utc("2025-09-29T16:00:00Z")));
+        assert!(!is_last_workday_of_month(utc("2025-09-29T15:00:00Z")));
+
+        // Still Thursday in UTC
+        assert!(is_friday(utc("2025-09-25T16:00:00Z")));
+        assert!(!is_friday(utc("2025-09-25T15:59:00Z")));
+    }
+
+    #[test]
+    fn formats_time_info_in_the_market_timezone() {
+        assert_eq!(
+            format_time_info(utc("2025-09-30T16:30:00Z")),
+            "Date: 2025-10-01 00:30:00 CST, Weekday: Wednesday, Workday: No, Friday: No, \
+             Last workday of month: No"
+        );
+        assert_eq!(
+            format_time_info(utc("2025-09-30T07:00:00Z")),
+            "Date: 2025-09-30 15:00:00 CST, Weekday: Tuesday, Workday: Yes, Friday: No, \
+             Last workday of month: Yes"
+        );
+    }
+}
@@ -0,0 +1,470 @@
+use crate::calendar::TradingCalendar;
+use crate::data_source::FetchRange;
+use crate::models::{Instrument, StockData};
+use crate::validator::{ValidationIssue, ValidationReport};
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Directory used for local state when `DATA_DIR` is not set
+const DEFAULT_DATA_DIR: &str = "data";
+
+/// Root directory for all locally persisted state
+pub fn data_dir() -> PathBuf {
+    match std::env::var("DATA_DIR") {
+        Ok(dir) if !dir.trim().is_empty() => PathBuf::from(dir.trim()),
+        _ => PathBuf::from(DEFAULT_DATA_DIR),
+    }
+}
+
+/// Write a file atomically by writing a temporary file and renaming it
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, contents)
+        .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Cached bars of one instrument, together with the date span they cover
+///
+/// `covered_from` and `covered_to` are the earliest and latest dates that
+/// were requested from a provider, so days without bars (weekends, holidays,
+/// pre-listing) are not fetched again.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachedSeries {
+    pub covered_from: Option<NaiveDate>,
+    pub covered_to: Option<NaiveDate>,
+    pub bars: Vec<StockData>,
+    /// Issues validation found when the cached bars were fetched
+    #[serde(default)]
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl CachedSeries {
+    /// Cached bars within the range
+    pub fn range(&self, range: FetchRange) -> Vec<StockData> {
+        self.bars
+            .iter()
+            .filter(|d| range.contains(d))
+            .cloned()
+            .collect()
+    }
+
+    /// Validation of the cached bars within the range, as recorded when they
+    /// were fetched
+    pub fn validation(&self, range: FetchRange) -> ValidationReport {
+        let bars = self.bars.iter().filter(|d| range.contains(d)).count();
+        let issues = self
+            .issues
+            .iter()
+            .filter(|issue| (range.start..=range.end).contains(&issue.date))
+            .cloned()
+            .collect();
+        ValidationReport::from_issues(bars, issues)
+    }
+
+    /// Last cached bar dated before `date`
+    pub fn last_before(&self, date: NaiveDate) -> Option<&StockData> {
+        self.bars.iter().rev().find(|d| d.trade_date() < date)
+    }
+
+    /// The parts of `range` that still have to be fetched from a provider
+    ///
+    /// These are the days before and after the covered span, empty when the
+    /// cache covers the whole range. Days after `latest_close`, the last
+    /// trading day whose close has passed, cannot have bars yet and are not
+    /// missing. Gaps reach up to the covered span so the cache always stays
+    /// contiguous.
+    pub fn missing(&self, range: FetchRange, latest_close: NaiveDate) -> Vec<FetchRange> {
+        let (Some(from), Some(to)) = (self.covered_from, self.covered_to) else {
+            return vec![range];
+        };
+
+        let mut gaps = Vec::new();
+        if range.start < from {
+            gaps.push(FetchRange::new(range.start, from - Duration::days(1)));
+        }
+        if range.end.min(latest_close) > to {
+            gaps.push(FetchRange::new(to + Duration::days(1), range.end));
+        }
+        gaps
+    }
+}
+
+/// Last date a fetch of `range` covers, given the bars cached after it
+///
+/// That is the end of the range, unless its last trading day has no bar,
+/// e.g. because the provider has not published it yet. The fetch then only
+/// covers the days before, so that bar is requested again.
+fn covered_through(range: FetchRange, bars: &BTreeMap<NaiveDate, StockData>) -> NaiveDate {
+    let last_trading_day = TradingCalendar::global().latest_trading_day(range.end);
+    if last_trading_day >= range.start && !bars.contains_key(&last_trading_day) {
+        last_trading_day - Duration::days(1)
+    } else {
+        range.end
+    }
+}
+
+/// Persistent per-instrument store of daily bars
+///
+/// Each instrument is kept as a JSON file under `$DATA_DIR/bars`, keyed by
+/// its TuShare code and deduplicated by date.
+pub struct BarStore {
+    dir: PathBuf,
+}
+
+impl BarStore {
+    /// Open the store under the configured data directory
+    pub fn open() -> Result<Self> {
+        Self::in_dir(data_dir().join("bars"))
+    }
+
+    fn in_dir(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create bar store at {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, instrument: &Instrument) -> PathBuf {
+        self.dir.join(format!("{}.json", instrument.tushare_code()))
+    }
+
+    /// Load the cached series of an instrument, empty when nothing is cached
+    pub fn load(&self, instrument: &Instrument) -> Result<CachedSeries> {
+        let path = self.path(instrument);
+        if !path.exists() {
+            return Ok(CachedSeries::default());
+        }
+
+        let contents =
+            fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_slice(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Merge freshly fetched and validated bars for `fetched_range` into the
+    /// cache
+    ///
+    /// Fetched bars replace cached bars of the same date, and the fetch's
+    /// validation issues replace those recorded for the dates it spans.
+    /// Returns the merged series.
+    pub fn save(
+        &self,
+        instrument: &Instrument,
+        fetched: &[StockData],
+        fetched_range: FetchRange,
+        validation: &ValidationReport,
+    ) -> Result<CachedSeries> {
+        let cached = self.load(instrument)?;
+
+        let mut by_date: BTreeMap<NaiveDate, StockData> = cached
+            .bars
+            .into_iter()
+            .map(|d| (d.trade_date(), d))
+            .collect();
+        for bar in fetched {
+            by_date.insert(bar.trade_date(), bar.clone());
+        }
+
+        let requested_from = fetched_range.start;
+        let covered_from = Some(
+            cached
+                .covered_from
+                .map_or(requested_from, |from| from.min(requested_from)),
+        );
+        let covered_to = Some(covered_through(fetched_range, &by_date)).max(cached.covered_to);
+
+        // Providers may return bars, and so issues, outside the requested range
+        let dates = fetched
+            .iter()
+            .map(|d| d.trade_date())
+            .chain(validation.issues.iter().map(|issue| issue.date));
+        let (span_start, span_end) = dates.fold(
+            (fetched_range.start, fetched_range.end),
+            |(start, end), date| (start.min(date), end.max(date)),
+        );
+        let mut issues: Vec<ValidationIssue> = cached
+            .issues
+            .into_iter()
+            .filter(|issue| !(span_start..=span_end).contains(&issue.date))
+            .chain(validation.issues.iter().cloned())
+            .collect();
+        issues.sort_by_key(|issue| issue.date);
+
+        let series = CachedSeries {
+            covered_from,
+            covered_to,
+            bars: by_date.into_values().collect(),
+            issues,
+        };
+
+        write_atomic(&self.path(instrument), &serde_json::to_vec(&series)?)?;
+        debug!(
+            "Cached {} bars for {} ({} new)",
+            series.bars.len(),
+            instrument,
+            fetched.len()
+        );
+
+        Ok(series)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::market_close_utc;
+    use crate::validator::IssueAction;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn range(start: &str, end: &str) -> FetchRange {
+        FetchRange::new(date(start), date(end))
+    }
+
+    fn bar(day: &str, close: f64) -> StockData {
+        StockData {
+            date: market_close_utc(date(day)),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1_000,
+            amount: None,
+        }
+    }
+
+    fn issue(day: &str, description: &str) -> ValidationIssue {
+        ValidationIssue {
+            date: date(day),
+            action: IssueAction::Flagged,
+            description: description.to_string(),
+        }
+    }
+
+    fn gaps(series: &CachedSeries, range: FetchRange, latest_close: &str) -> Vec<String> {
+        series
+            .missing(range, date(latest_close))
+            .iter()
+            .map(|gap| format!("{}..{}", gap.start, gap.end))
+            .collect()
+    }
+
+    fn closes(series: &CachedSeries) -> Vec<(String, f64)> {
+        series
+            .bars
+            .iter()
+            .map(|bar| (bar.trade_date().to_string(), bar.close))
+            .collect()
+    }
+
+    /// Series covering Monday 2025-09-22 to Friday 2025-09-26
+    fn cached_week() -> CachedSeries {
+        CachedSeries {
+            covered_from: Some(date("2025-09-22")),
+            covered_to: Some(date("2025-09-26")),
+            bars: [
+                "2025-09-22",
+                "2025-09-23",
+                "2025-09-24",
+                "2025-09-25",
+                "2025-09-26",
+            ]
+            .into_iter()
+            .map(|day| bar(day, 100.0))
+            .collect(),
+            issues: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn an_empty_cache_misses_the_whole_range() {
+        let series = CachedSeries::default();
+        assert_eq!(
+            gaps(&series, range("2025-09-22", "2025-09-26"), "2025-09-26"),
+            ["2025-09-22..2025-09-26"]
+        );
+    }
+
+    #[test]
+    fn a_covered_range_misses_nothing() {
+        let series = cached_week();
+        assert!(gaps(&series, range("2025-09-23", "2025-09-25"), "2025-09-26").is_empty());
+        assert!(gaps(&series, range("2025-09-22", "2025-09-26"), "2025-09-30").is_empty());
+    }
+
+    #[test]
+    fn fetches_only_the_head_and_tail_gaps() {
+        let series = cached_week();
+        assert_eq!(
+            gaps(&series, range("2025-09-15", "2025-09-24"), "2025-09-30"),
+            ["2025-09-15..2025-09-21"]
+        );
+        assert_eq!(
+            gaps(&series, range("2025-09-24", "2025-09-30"), "2025-09-30"),
+            ["2025-09-27..2025-09-30"]
+        );
+        assert_eq!(
+            gaps(&series, range("2025-09-15", "2025-09-30"), "2025-09-30"),
+            ["2025-09-15..2025-09-21", "2025-09-27..2025-09-30"]
+        );
+    }
+
+    #[test]
+    fn gaps_reach_up_to_the_covered_span() {
+        let series = cached_week();
+        assert_eq!(
+            gaps(&series, range("2025-09-01", "2025-09-05"), "2025-09-30"),
+            ["2025-09-01..2025-09-21"]
+        );
+        assert_eq!(
+            gaps(&series, range("2025-10-09", "2025-10-10"), "2025-10-10"),
+            ["2025-09-27..2025-10-10"]
+        );
+    }
+
+    #[test]
+    fn days_without_closed_bars_are_not_missing() {
+        let series = cached_week();
+        // Over the weekend the latest close is still Friday's
+        assert!(gaps(&series, range("2025-09-22", "2025-09-28"), "2025-09-26").is_empty());
+        // Likewise before Monday's close
+        assert!(gaps(&series, range("2025-09-22", "2025-09-29"), "2025-09-26").is_empty());
+        assert_eq!(
+            gaps(&series, range("2025-09-22", "2025-09-29"), "2025-09-29"),
+            ["2025-09-27..2025-09-29"]
+        );
+    }
+
+    #[test]
+    fn saves_the_requested_span_as_covered() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BarStore::in_dir(dir.path().to_path_buf()).unwrap();
+        let instrument = Instrument::csi300();
+
+        // A weekend run covers the weekend, so later runs fetch nothing
+        let bars = cached_week().bars;
+        let series = store
+            .save(
+                &instrument,
+                &bars,
+                range("2025-09-22", "2025-09-28"),
+                &ValidationReport::default(),
+            )
+            .unwrap();
+        assert_eq!(series.covered_from, Some(date("2025-09-22")));
+        assert_eq!(series.covered_to, Some(date("2025-09-28")));
+        assert!(gaps(&series, range("2025-09-22", "2025-09-28"), "2025-09-26").is_empty());
+    }
+
+    #[test]
+    fn keeps_fetching_a_trading_day_without_its_bar() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BarStore::in_dir(dir.path().to_path_buf()).unwrap();
+        let instrument = Instrument::csi300();
+
+        // Tuesday's bar is not published yet
+        let bars = vec![bar("2025-09-29", 100.0)];
+        let series = store
+            .save(
+                &instrument,
+                &bars,
+                range("2025-09-29", "2025-09-30"),
+                &ValidationReport::default(),
+            )
+            .unwrap();
+        assert_eq!(series.covered_to, Some(date("2025-09-29")));
+        assert_eq!(
+            gaps(&series, range("2025-09-29", "2025-09-30"), "2025-09-30"),
+            ["2025-09-30..2025-09-30"]
+        );
+
+        // The holiday after it needs no bars
+        let bars = vec![bar("2025-09-30", 101.0)];
+        let series = store
+            .save(
+                &instrument,
+                &bars,
+                range("2025-09-30", "2025-10-05"),
+                &ValidationReport::default(),
+            )
+            .unwrap();
+        assert_eq!(series.covered_to, Some(date("2025-10-05")));
+    }
+
+    #[test]
+    fn merges_fetched_bars_and_issues_into_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BarStore::in_dir(dir.path().to_path_buf()).unwrap();
+        let instrument = Instrument::csi300();
+        assert!(store.load(&instrument).unwrap().bars.is_empty());
+
+        let first = ValidationReport::from_issues(
+            2,
+            vec![issue("2025-09-23", "old"), issue("2025-09-25", "kept")],
+        );
+        store
+            .save(
+                &instrument,
+                &[bar("2025-09-23", 100.0), bar("2025-09-25", 102.0)],
+                range("2025-09-23", "2025-09-25"),
+                &first,
+            )
+            .unwrap();
+
+        // A refetch of the head replaces its bars and issues
+        let second = ValidationReport::from_issues(2, vec![issue("2025-09-22", "new")]);
+        let series = store
+            .save(
+                &instrument,
+                &[bar("2025-09-22", 99.0), bar("2025-09-23", 100.5)],
+                range("2025-09-22", "2025-09-23"),
+                &second,
+            )
+            .unwrap();
+
+        assert_eq!(series.covered_from, Some(date("2025-09-22")));
+        assert_eq!(series.covered_to, Some(date("2025-09-25")));
+        assert_eq!(
+            closes(&series),
+            [
+                ("2025-09-22".to_string(), 99.0),
+                ("2025-09-23".to_string(), 100.5),
+                ("2025-09-25".to_string(), 102.0),
+            ]
+        );
+        let issues: Vec<&str> = series
+            .issues
+            .iter()
+            .map(|issue| issue.description.as_str())
+            .collect();
+        assert_eq!(issues, ["new", "kept"]);
+
+        // What was saved is what loads
+        let loaded = store.load(&instrument).unwrap();
+        assert_eq!(loaded.bars, series.bars);
+        assert_eq!(loaded.covered_from, series.covered_from);
+        assert_eq!(loaded.covered_to, series.covered_to);
+        assert_eq!(loaded.issues.len(), 2);
+
+        let validation = loaded.validation(range("2025-09-24", "2025-09-30"));
+        assert_eq!(validation.output_bars, 1);
+        assert_eq!(validation.flagged(), 1);
+        assert_eq!(
+            loaded.last_before(date("2025-09-25")).map(|bar| bar.close),
+            Some(100.5)
+        );
+    }
+}
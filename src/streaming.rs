@@ -0,0 +1,666 @@
+use crate::indicators::{Bollinger, Kdj, Macd};
+use crate::models::StockData;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// An indicator updated one bar at a time
+///
+/// Every state is serializable, so it can be persisted after a run and
+/// resumed with the next bar instead of replaying the whole history. Updates
+/// take constant time.
+pub trait Indicator {
+    type Output;
+
+    /// Feed the next bar and return the indicator's value at it, `None`
+    /// during warm-up
+    fn update(&mut self, bar: &StockData) -> Option<Self::Output>;
+}
+
+/// Feed every bar and return the indicator's values at the last two, as
+/// `(previous, latest)`
+pub fn last_two<I: Indicator>(
+    mut indicator: I,
+    bars: &[StockData],
+) -> (Option<I::Output>, Option<I::Output>) {
+    bars.iter().fold((None, None), |(_, latest), bar| {
+        (latest, indicator.update(bar))
+    })
+}
+
+/// Sum of the latest `period` values, with the window kept for eviction
+///
+/// Values are stored relative to a shift near their mean, so the sum of
+/// squares does not lose precision to cancellation, and both sums are
+/// recomputed once per full window turnover so that rounding errors of the
+/// running updates do not accumulate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RollingWindow {
+    period: usize,
+    values: VecDeque<f64>,
+    shift: f64,
+    sum: f64,
+    sum_sq: f64,
+    since_refresh: usize,
+}
+
+impl RollingWindow {
+    fn new(period: usize) -> Self {
+        Self {
+            period,
+            values: VecDeque::with_capacity(period + 1),
+            shift: 0.0,
+            sum: 0.0,
+            sum_sq: 0.0,
+            since_refresh: 0,
+        }
+    }
+
+    /// Add a value, dropping the oldest one once the window is full; returns
+    /// whether the window is full
+    fn push(&mut self, value: f64) -> bool {
+        if self.period == 0 {
+            return false;
+        }
+
+        if self.values.is_empty() {
+            self.shift = value;
+        }
+        self.values.push_back(value);
+        let shifted = value - self.shift;
+        self.sum += shifted;
+        self.sum_sq += shifted * shifted;
+
+        if self.values.len() > self.period
+            && let Some(oldest) = self.values.pop_front()
+        {
+            let shifted = oldest - self.shift;
+            self.sum -= shifted;
+            self.sum_sq -= shifted * shifted;
+        }
+
+        self.since_refresh += 1;
+        if self.since_refresh >= self.period {
+            self.refresh();
+        }
+
+        self.is_full()
+    }
+
+    fn refresh(&mut self) {
+        let len = self.values.len() as f64;
+        self.shift = self.values.iter().sum::<f64>() / len;
+        self.sum = 0.0;
+        self.sum_sq = 0.0;
+        for value in &self.values {
+            let shifted = value - self.shift;
+            self.sum += shifted;
+            self.sum_sq += shifted * shifted;
+        }
+        self.since_refresh = 0;
+    }
+
+    fn is_full(&self) -> bool {
+        self.period > 0 && self.values.len() == self.period
+    }
+
+    fn sum(&self) -> f64 {
+        self.shift * self.values.len() as f64 + self.sum
+    }
+
+    fn mean(&self) -> f64 {
+        self.shift + self.sum / self.values.len() as f64
+    }
+
+    /// Population variance
+    fn variance(&self) -> f64 {
+        let len = self.values.len() as f64;
+        let mean = self.sum / len;
+        (self.sum_sq / len - mean * mean).max(0.0)
+    }
+}
+
+/// Highest high and lowest low of the latest `period` bars, kept in
+/// monotonic queues so that each update is amortized constant time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RollingExtremes {
+    period: usize,
+    count: u64,
+    highs: VecDeque<(u64, f64)>,
+    lows: VecDeque<(u64, f64)>,
+}
+
+impl RollingExtremes {
+    fn new(period: usize) -> Self {
+        Self {
+            period,
+            count: 0,
+            highs: VecDeque::new(),
+            lows: VecDeque::new(),
+        }
+    }
+
+    /// Add a bar's high and low; returns the window's highest high and lowest
+    /// low once it covers `period` bars
+    fn push(&mut self, high: f64, low: f64) -> Option<(f64, f64)> {
+        if self.period == 0 {
+            return None;
+        }
+
+        let index = self.count;
+        self.count += 1;
+
+        while self.highs.back().is_some_and(|&(_, h)| h <= high) {
+            self.highs.pop_back();
+        }
+        self.highs.push_back((index, high));
+        while self.lows.back().is_some_and(|&(_, l)| l >= low) {
+            self.lows.pop_back();
+        }
+        self.lows.push_back((index, low));
+
+        let expired = |&(i, _): &(u64, f64)| i + self.period as u64 <= index;
+        while self.highs.front().is_some_and(expired) {
+            self.highs.pop_front();
+        }
+        while self.lows.front().is_some_and(expired) {
+            self.lows.pop_front();
+        }
+
+        (self.count >= self.period as u64).then(|| (self.highs[0].1, self.lows[0].1))
+    }
+}
+
+/// Simple moving average of the close
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmaState {
+    window: RollingWindow,
+}
+
+impl SmaState {
+    pub fn new(period: usize) -> Self {
+        Self {
+            window: RollingWindow::new(period),
+        }
+    }
+
+    /// Feed an arbitrary value rather than a bar's close
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        self.window.push(value).then(|| self.window.mean())
+    }
+}
+
+impl Indicator for SmaState {
+    type Output = f64;
+
+    fn update(&mut self, bar: &StockData) -> Option<f64> {
+        self.push(bar.close)
+    }
+}
+
+/// How a recursive average gets its first value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Seed {
+    /// The SMA of the first `period` values, as TradingView does
+    #[default]
+    Sma,
+    /// The first value, as Wind and Tonghuashun do
+    FirstValue,
+}
+
+/// Recursive average `value = alpha * x + (1 - alpha) * value`
+///
+/// Values are only reported once `period` inputs have been seen, whatever
+/// the seed: a first-value seed starts the recursion at the first input so
+/// that later values match tools that seed this way, but its early values
+/// are dominated by the seed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecursiveAverage {
+    period: usize,
+    alpha: f64,
+    seed: Seed,
+    count: usize,
+    seed_sum: f64,
+    value: Option<f64>,
+}
+
+impl RecursiveAverage {
+    fn new(period: usize, alpha: f64, seed: Seed) -> Self {
+        Self {
+            period,
+            alpha,
+            seed,
+            count: 0,
+            seed_sum: 0.0,
+            value: None,
+        }
+    }
+
+    fn push(&mut self, x: f64) -> Option<f64> {
+        if self.period == 0 {
+            return None;
+        }
+
+        self.count += 1;
+        self.value = match (self.value, self.seed) {
+            (Some(value), _) => Some(self.alpha * x + (1.0 - self.alpha) * value),
+            (None, Seed::FirstValue) => Some(x),
+            (None, Seed::Sma) => {
+                self.seed_sum += x;
+                (self.count == self.period).then(|| self.seed_sum / self.period as f64)
+            }
+        };
+        self.current()
+    }
+
+    /// Latest value once warmed up
+    fn current(&self) -> Option<f64> {
+        self.value.filter(|_| self.count >= self.period)
+    }
+
+    /// Latest value including warm-up
+    fn raw(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Exponential moving average of the close, smoothing `2 / (period + 1)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmaState {
+    average: RecursiveAverage,
+}
+
+impl EmaState {
+    pub fn new(period: usize, seed: Seed) -> Self {
+        Self {
+            average: RecursiveAverage::new(period, 2.0 / (period as f64 + 1.0), seed),
+        }
+    }
+
+    /// Feed an arbitrary value rather than a bar's close
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        self.average.push(value)
+    }
+}
+
+impl Indicator for EmaState {
+    type Output = f64;
+
+    fn update(&mut self, bar: &StockData) -> Option<f64> {
+        self.push(bar.close)
+    }
+}
+
+/// How RSI averages gains and losses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RsiMethod {
+    /// Wilder's smoothing with weight `1 / period`; TradingView seeds it with
+    /// the SMA, Wind and Tonghuashun with the first change
+    Wilder(Seed),
+    /// Cutler's variant: plain averages over the latest `period` changes
+    Cutler,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum RsiAverages {
+    Wilder {
+        gains: RecursiveAverage,
+        losses: RecursiveAverage,
+    },
+    Cutler {
+        gains: RollingWindow,
+        losses: RollingWindow,
+    },
+}
+
+/// Relative strength index; defined from the bar with `period` price changes
+/// behind it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RsiState {
+    prev_close: Option<f64>,
+    averages: RsiAverages,
+}
+
+impl RsiState {
+    pub fn new(period: usize, method: RsiMethod) -> Self {
+        let averages = match method {
+            RsiMethod::Wilder(seed) => {
+                let alpha = 1.0 / period as f64;
+                RsiAverages::Wilder {
+                    gains: RecursiveAverage::new(period, alpha, seed),
+                    losses: RecursiveAverage::new(period, alpha, seed),
+                }
+            }
+            RsiMethod::Cutler => RsiAverages::Cutler {
+                gains: RollingWindow::new(period),
+                losses: RollingWindow::new(period),
+            },
+        };
+        Self {
+            prev_close: None,
+            averages,
+        }
+    }
+}
+
+impl Indicator for RsiState {
+    type Output = f64;
+
+    fn update(&mut self, bar: &StockData) -> Option<f64> {
+        let prev_close = self.prev_close.replace(bar.close)?;
+        let change = bar.close - prev_close;
+        let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+
+        let (gain, loss) = match &mut self.averages {
+            RsiAverages::Wilder { gains, losses } => (gains.push(gain), losses.push(loss)),
+            RsiAverages::Cutler { gains, losses } => {
+                let gains_full = gains.push(gain);
+                let losses_full = losses.push(loss);
+                if gains_full && losses_full {
+                    (Some(gains.sum()), Some(losses.sum()))
+                } else {
+                    (None, None)
+                }
+            }
+        };
+
+        let (gain, loss) = (gain?, loss?);
+        Some(if loss <= 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        })
+    }
+}
+
+/// MACD of the close
+///
+/// Defined once both the slow EMA and the signal line have warmed up. With a
+/// first-value seed the signal line runs from the first bar, as in Wind and
+/// Tonghuashun; with an SMA seed it starts once the MACD line is defined.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacdState {
+    fast: EmaState,
+    slow: EmaState,
+    signal: EmaState,
+}
+
+impl MacdState {
+    pub fn new(fast: usize, slow: usize, signal: usize, seed: Seed) -> Self {
+        Self {
+            fast: EmaState::new(fast, seed),
+            slow: EmaState::new(slow, seed),
+            signal: EmaState::new(signal, seed),
+        }
+    }
+}
+
+impl Indicator for MacdState {
+    type Output = Macd;
+
+    fn update(&mut self, bar: &StockData) -> Option<Macd> {
+        self.fast.push(bar.close);
+        let slow = self.slow.push(bar.close);
+        let macd = self.fast.average.raw()? - self.slow.average.raw()?;
+        let signal = self.signal.push(macd);
+
+        slow?;
+        let signal = signal?;
+        Some(Macd {
+            macd,
+            signal,
+            histogram: macd - signal,
+        })
+    }
+}
+
+/// Bollinger Bands around the SMA of the close
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BollingerState {
+    multiplier: f64,
+    window: RollingWindow,
+}
+
+impl BollingerState {
+    pub fn new(period: usize, multiplier: f64) -> Self {
+        Self {
+            multiplier,
+            window: RollingWindow::new(period),
+        }
+    }
+}
+
+impl Indicator for BollingerState {
+    type Output = Bollinger;
+
+    fn update(&mut self, bar: &StockData) -> Option<Bollinger> {
+        if !self.window.push(bar.close) {
+            return None;
+        }
+
+        let middle = self.window.mean();
+        let deviation = self.window.variance().sqrt() * self.multiplier;
+        let (upper, lower) = (middle + deviation, middle - deviation);
+        Some(Bollinger {
+            middle,
+            upper,
+            lower,
+            percent_b: if upper > lower {
+                (bar.close - lower) / (upper - lower)
+            } else {
+                0.5
+            },
+            bandwidth: if middle != 0.0 {
+                (upper - lower) / middle
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+/// KDJ stochastic oscillator, K and D starting from 50
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdjState {
+    k_smoothing: usize,
+    d_smoothing: usize,
+    extremes: RollingExtremes,
+    k: f64,
+    d: f64,
+}
+
+impl KdjState {
+    pub fn new(period: usize, k_smoothing: usize, d_smoothing: usize) -> Self {
+        Self {
+            k_smoothing,
+            d_smoothing,
+            extremes: RollingExtremes::new(period),
+            k: 50.0,
+            d: 50.0,
+        }
+    }
+}
+
+impl Indicator for KdjState {
+    type Output = Kdj;
+
+    fn update(&mut self, bar: &StockData) -> Option<Kdj> {
+        let (high, low) = self.extremes.push(bar.high, bar.low)?;
+        let rsv = if high > low {
+            (bar.close - low) / (high - low) * 100.0
+        } else {
+            50.0
+        };
+
+        let k_weight = 1.0 / self.k_smoothing as f64;
+        let d_weight = 1.0 / self.d_smoothing as f64;
+        self.k = (1.0 - k_weight) * self.k + k_weight * rsv;
+        self.d = (1.0 - d_weight) * self.d + d_weight * self.k;
+        Some(Kdj {
+            k: self.k,
+            d: self.d,
+            j: 3.0 * self.k - 2.0 * self.d,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators::{self, Series};
+    use crate::models::market_close_utc;
+    use chrono::{Days, NaiveDate};
+    use serde::de::DeserializeOwned;
+    use std::fmt::Debug;
+
+    /// A wavy uptrend long enough for several window refreshes
+    fn bars() -> Vec<StockData> {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        (0..300)
+            .map(|i| {
+                let x = i as f64;
+                let close = 3000.0 + x * 1.5 + (x / 7.0).sin() * 40.0 + (x / 3.0).cos() * 15.0;
+                StockData {
+                    date: market_close_utc(start + Days::new(i)),
+                    open: close - 5.0,
+                    high: close + 10.0 + (x / 5.0).sin().abs() * 8.0,
+                    low: close - 10.0 - (x / 4.0).cos().abs() * 8.0,
+                    close,
+                    volume: 1_000_000 + (i % 17) * 25_000,
+                    amount: None,
+                }
+            })
+            .collect()
+    }
+
+    fn streamed<I: Indicator>(mut indicator: I, bars: &[StockData]) -> Vec<Option<I::Output>> {
+        bars.iter().map(|bar| indicator.update(bar)).collect()
+    }
+
+    fn batch<T: Copy>(series: Series<T>) -> Vec<Option<T>> {
+        series.values().collect()
+    }
+
+    /// Check values agree to `tolerance` relative to their size, with the
+    /// same warm-up
+    fn assert_close(streamed: &[Option<f64>], batch: &[Option<f64>], tolerance: f64) {
+        assert_eq!(streamed.len(), batch.len());
+        for (i, (s, b)) in streamed.iter().zip(batch).enumerate() {
+            match (s, b) {
+                (None, None) => {}
+                (Some(s), Some(b)) => assert!(
+                    (s - b).abs() <= tolerance * b.abs().max(1.0),
+                    "bar {}: streamed {}, batch {}",
+                    i,
+                    s,
+                    b
+                ),
+                _ => panic!("bar {}: streamed {:?}, batch {:?}", i, s, b),
+            }
+        }
+    }
+
+    /// Check that saving the state after each of `splits` bars and resuming
+    /// from the saved JSON gives the same values as an uninterrupted run
+    fn assert_resumes<I>(new: impl Fn() -> I, bars: &[StockData])
+    where
+        I: Indicator + Serialize + DeserializeOwned,
+        I::Output: PartialEq + Debug,
+    {
+        let uninterrupted = streamed(new(), bars);
+        for split in [0, 1, 13, 60, 150, bars.len()] {
+            let mut indicator = new();
+            let mut values: Vec<_> = bars[..split]
+                .iter()
+                .map(|bar| indicator.update(bar))
+                .collect();
+            let saved = serde_json::to_string(&indicator).unwrap();
+            let resumed: I = serde_json::from_str(&saved).unwrap();
+            values.extend(streamed(resumed, &bars[split..]));
+            assert_eq!(values, uninterrupted, "resumed after {} bars", split);
+        }
+    }
+
+    #[test]
+    fn recursive_indicators_match_batch_exactly() {
+        let bars = bars();
+        for seed in [Seed::Sma, Seed::FirstValue] {
+            assert_eq!(
+                streamed(EmaState::new(12, seed), &bars),
+                batch(indicators::ema(&bars, 12, seed))
+            );
+            let method = RsiMethod::Wilder(seed);
+            assert_eq!(
+                streamed(RsiState::new(14, method), &bars),
+                batch(indicators::rsi(&bars, 14, method))
+            );
+            assert_eq!(
+                streamed(MacdState::new(12, 26, 9, seed), &bars),
+                batch(indicators::macd(&bars, 12, 26, 9, seed))
+            );
+        }
+        assert_eq!(
+            streamed(KdjState::new(9, 3, 3), &bars),
+            batch(indicators::kdj(&bars, 9, 3, 3))
+        );
+    }
+
+    #[test]
+    fn windowed_indicators_match_batch() {
+        // Running window sums round differently from summing each window
+        let bars = bars();
+        for period in [1, 5, 20, 60] {
+            assert_close(
+                &streamed(SmaState::new(period), &bars),
+                &batch(indicators::sma(&bars, period)),
+                1e-12,
+            );
+        }
+        assert_close(
+            &streamed(RsiState::new(14, RsiMethod::Cutler), &bars),
+            &batch(indicators::rsi(&bars, 14, RsiMethod::Cutler)),
+            1e-9,
+        );
+
+        let streamed = streamed(BollingerState::new(20, 2.0), &bars);
+        let batch = batch(indicators::bollinger(&bars, 20, 2.0));
+        let fields: [fn(&Bollinger) -> f64; 5] = [
+            |b| b.middle,
+            |b| b.upper,
+            |b| b.lower,
+            |b| b.percent_b,
+            |b| b.bandwidth,
+        ];
+        for get in fields {
+            let values = |series: &[Option<Bollinger>]| -> Vec<Option<f64>> {
+                series.iter().map(|b| b.as_ref().map(get)).collect()
+            };
+            assert_close(&values(&streamed), &values(&batch), 1e-9);
+        }
+    }
+
+    #[test]
+    fn states_resume_from_json() {
+        let bars = bars();
+        assert_resumes(|| SmaState::new(20), &bars);
+        for seed in [Seed::Sma, Seed::FirstValue] {
+            assert_resumes(|| EmaState::new(12, seed), &bars);
+            assert_resumes(|| RsiState::new(14, RsiMethod::Wilder(seed)), &bars);
+            assert_resumes(|| MacdState::new(12, 26, 9, seed), &bars);
+        }
+        assert_resumes(|| RsiState::new(14, RsiMethod::Cutler), &bars);
+        assert_resumes(|| BollingerState::new(20, 2.0), &bars);
+        assert_resumes(|| KdjState::new(9, 3, 3), &bars);
+    }
+
+    #[test]
+    fn last_two_values() {
+        let bars = bars();
+        let sma = batch(indicators::sma(&bars[..25], 20));
+        let (previous, latest) = last_two(SmaState::new(20), &bars[..25]);
+        assert_close(&[previous, latest], &sma[23..], 1e-12);
+        assert_eq!(last_two(SmaState::new(20), &bars[..20]).0, None);
+        assert_eq!(last_two(SmaState::new(20), &[]), (None, None));
+    }
+}
@@ -0,0 +1,495 @@
+use crate::calendar::TradingCalendar;
+use crate::models::StockData;
+use anyhow::{Result, anyhow};
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use tracing::{info, warn};
+
+/// Largest close-to-close move accepted without flagging, as a fraction
+const DEFAULT_MAX_DAILY_MOVE: f64 = 0.2;
+
+/// Largest share of bars that may be dropped before a series is rejected
+const DEFAULT_MAX_DROPPED_RATIO: f64 = 0.2;
+
+/// What the validator did about an issue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IssueAction {
+    /// The bar was fixed and kept
+    Repaired,
+    /// The bar was removed
+    Dropped,
+    /// The bar was kept as is but looks suspicious
+    Flagged,
+}
+
+/// A data quality problem found in a fetched series
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub date: NaiveDate,
+    pub action: IssueAction,
+    pub description: String,
+}
+
+/// Outcome of validating one fetched series
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub input_bars: usize,
+    pub output_bars: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    fn count(&self, action: IssueAction) -> usize {
+        self.issues.iter().filter(|i| i.action == action).count()
+    }
+
+    pub fn repaired(&self) -> usize {
+        self.count(IssueAction::Repaired)
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.count(IssueAction::Dropped)
+    }
+
+    pub fn flagged(&self) -> usize {
+        self.count(IssueAction::Flagged)
+    }
+
+    /// Report for bars that were validated earlier, rebuilt from the issues
+    /// recorded for them
+    ///
+    /// Every bar that was checked was either kept or dropped, so the checked
+    /// count is recovered from the kept bars and the drops.
+    pub fn from_issues(bars: usize, issues: Vec<ValidationIssue>) -> Self {
+        let dropped = issues
+            .iter()
+            .filter(|issue| issue.action == IssueAction::Dropped)
+            .count();
+        Self {
+            input_bars: bars + dropped,
+            output_bars: bars,
+            issues,
+        }
+    }
+
+    /// Add another series' validation to this one
+    pub fn extend(&mut self, other: ValidationReport) {
+        self.input_bars += other.input_bars;
+        self.output_bars += other.output_bars;
+        self.issues.extend(other.issues);
+    }
+
+    fn push(&mut self, date: NaiveDate, action: IssueAction, description: String) {
+        self.issues.push(ValidationIssue {
+            date,
+            action,
+            description,
+        });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bars checked, {} repaired, {} dropped, {} flagged",
+            self.input_bars,
+            self.repaired(),
+            self.dropped(),
+            self.flagged()
+        )
+    }
+}
+
+/// Thresholds used by [`validate`]
+#[derive(Debug, Clone)]
+pub struct ValidationConfig {
+    /// Close-to-close moves above this fraction are flagged
+    pub max_daily_move: f64,
+    /// Series with more than this fraction of invalid bars are rejected
+    pub max_dropped_ratio: f64,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            max_daily_move: DEFAULT_MAX_DAILY_MOVE,
+            max_dropped_ratio: DEFAULT_MAX_DROPPED_RATIO,
+        }
+    }
+}
+
+impl ValidationConfig {
+    /// Load thresholds from `VALIDATION_MAX_DAILY_MOVE_PCT` and
+    /// `VALIDATION_MAX_DROPPED_PCT`, falling back to the defaults
+    pub fn from_env() -> Self {
+        let percent = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .map(|v| v / 100.0)
+        };
+
+        let defaults = Self::default();
+        Self {
+            max_daily_move: percent("VALIDATION_MAX_DAILY_MOVE_PCT")
+                .unwrap_or(defaults.max_daily_move),
+            max_dropped_ratio: percent("VALIDATION_MAX_DROPPED_PCT")
+                .unwrap_or(defaults.max_dropped_ratio),
+        }
+    }
+}
+
+/// Validate fetched bars before they are cached or analyzed
+///
+/// Bars with zero, negative or unparsable prices are dropped, duplicate
+/// dates keep the last bar, and bars whose high/low do not bound the open
+/// and close are repaired. Gaps against the trading calendar and implausible
+/// jumps are flagged. The whole series is rejected with an error when too
+/// many bars have invalid prices.
+///
+/// `previous` is the last accepted bar before `bars`, e.g. the newest cached
+/// one; gaps and jumps are also checked across it, but it is not counted or
+/// returned.
+pub fn validate(
+    bars: Vec<StockData>,
+    previous: Option<&StockData>,
+    config: &ValidationConfig,
+) -> Result<(Vec<StockData>, ValidationReport)> {
+    let mut report = ValidationReport {
+        input_bars: bars.len(),
+        ..Default::default()
+    };
+
+    // Unusable prices, then deduplicate by date (which also sorts)
+    let mut invalid = 0;
+    let mut by_date: BTreeMap<NaiveDate, StockData> = BTreeMap::new();
+    for bar in bars {
+        let date = bar.trade_date();
+        let prices = [bar.open, bar.high, bar.low, bar.close];
+
+        if prices.iter().any(|p| !p.is_finite() || *p <= 0.0) {
+            invalid += 1;
+            report.push(
+                date,
+                IssueAction::Dropped,
+                format!(
+                    "invalid price (open {}, high {}, low {}, close {})",
+                    bar.open, bar.high, bar.low, bar.close
+                ),
+            );
+            continue;
+        }
+
+        if by_date.insert(date, bar).is_some() {
+            report.push(
+                date,
+                IssueAction::Dropped,
+                "duplicate date, kept the last bar".to_string(),
+            );
+        }
+    }
+
+    let mut cleaned: Vec<StockData> = by_date.into_values().collect();
+
+    // OHLC consistency: high >= max(open, close) >= min(open, close) >= low
+    for bar in &mut cleaned {
+        let top = bar.open.max(bar.close);
+        let bottom = bar.open.min(bar.close);
+
+        if bar.high < top || bar.low > bottom {
+            let (high, low) = (bar.high.max(top), bar.low.min(bottom));
+            report.push(
+                bar.trade_date(),
+                IssueAction::Repaired,
+                format!(
+                    "high/low {:.2}/{:.2} did not bound open/close {:.2}/{:.2}, set to {:.2}/{:.2}",
+                    bar.high, bar.low, bar.open, bar.close, high, low
+                ),
+            );
+            bar.high = high;
+            bar.low = low;
+        }
+    }
+
+    let previous = previous.filter(|previous| {
+        cleaned
+            .first()
+            .is_some_and(|first| previous.date < first.date)
+    });
+    let checked: Vec<&StockData> = previous.into_iter().chain(&cleaned).collect();
+
+    for pair in checked.windows(2) {
+        let (previous, current) = (pair[0], pair[1]);
+        let (previous_date, date) = (previous.trade_date(), current.trade_date());
+
+        let missing = missing_trading_days(previous_date, date);
+        if missing > 0 {
+            report.push(
+                date,
+                IssueAction::Flagged,
+                format!("{} trading day(s) missing since {}", missing, previous_date),
+            );
+        }
+
+        let change = current.close / previous.close - 1.0;
+        if change.abs() > config.max_daily_move {
+            report.push(
+                date,
+                IssueAction::Flagged,
+                format!(
+                    "implausible move of {:.2}% from {:.2} to {:.2}",
+                    change * 100.0,
+                    previous.close,
+                    current.close
+                ),
+            );
+        }
+    }
+
+    report.output_bars = cleaned.len();
+
+    for issue in &report.issues {
+        match issue.action {
+            IssueAction::Flagged => info!("Data quality {}: {}", issue.date, issue.description),
+            _ => warn!(
+                "Data quality {}: {:?} - {}",
+                issue.date, issue.action, issue.description
+            ),
+        }
+    }
+
+    let invalid_ratio = invalid as f64 / report.input_bars.max(1) as f64;
+    if invalid_ratio > config.max_dropped_ratio {
+        return Err(anyhow!(
+            "Data rejected: {} of {} bars have invalid prices",
+            invalid,
+            report.input_bars
+        ));
+    }
+
+    Ok((cleaned, report))
+}
+
+/// Number of trading days strictly between two dates
+fn missing_trading_days(from: NaiveDate, to: NaiveDate) -> usize {
+    let mut missing = 0;
+    let mut date = from + Duration::days(1);
+    while date < to {
+        if TradingCalendar::global().is_trading_day(date) {
+            missing += 1;
+        }
+        date += Duration::days(1);
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::market_close_utc;
+
+    fn bar(date: &str, close: f64) -> StockData {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        StockData {
+            date: market_close_utc(date),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1_000,
+            amount: None,
+        }
+    }
+
+    fn ohlc(date: &str, open: f64, high: f64, low: f64, close: f64) -> StockData {
+        StockData {
+            open,
+            high,
+            low,
+            ..bar(date, close)
+        }
+    }
+
+    fn week() -> Vec<StockData> {
+        [
+            "2025-09-22",
+            "2025-09-23",
+            "2025-09-24",
+            "2025-09-25",
+            "2025-09-26",
+        ]
+        .into_iter()
+        .map(|date| bar(date, 100.0))
+        .collect()
+    }
+
+    fn descriptions(report: &ValidationReport, action: IssueAction) -> Vec<String> {
+        report
+            .issues
+            .iter()
+            .filter(|issue| issue.action == action)
+            .map(|issue| format!("{} {}", issue.date, issue.description))
+            .collect()
+    }
+
+    fn flagged(report: &ValidationReport) -> Vec<String> {
+        report
+            .issues
+            .iter()
+            .filter(|issue| issue.action == IssueAction::Flagged)
+            .map(|issue| format!("{} {}", issue.date, issue.description))
+            .collect()
+    }
+
+    #[test]
+    fn checks_the_seam_to_the_previous_bar() {
+        let previous = bar("2025-09-26", 100.0);
+        let bars = vec![bar("2025-09-30", 150.0), bar("2025-10-09", 151.0)];
+
+        let (cleaned, report) =
+            validate(bars, Some(&previous), &ValidationConfig::default()).unwrap();
+
+        assert_eq!(cleaned.len(), 2);
+        assert_eq!(report.input_bars, 2);
+        assert_eq!(report.output_bars, 2);
+        assert_eq!(
+            flagged(&report),
+            [
+                "2025-09-30 1 trading day(s) missing since 2025-09-26",
+                "2025-09-30 implausible move of 50.00% from 100.00 to 150.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_a_previous_bar_that_is_not_before_the_bars() {
+        let previous = bar("2025-09-30", 100.0);
+        let bars = vec![bar("2025-09-30", 150.0)];
+
+        let (_, report) = validate(bars, Some(&previous), &ValidationConfig::default()).unwrap();
+
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn rebuilds_reports_from_recorded_issues() {
+        let mut bars: Vec<StockData> = ["2025-09-22", "2025-09-23", "2025-09-24", "2025-09-25"]
+            .into_iter()
+            .map(|date| bar(date, 100.0))
+            .collect();
+        bars.push(bar("2025-09-29", 100.0));
+        bars.push(bar("2025-09-30", 101.0));
+        bars.push(bar("2025-09-30", 102.0));
+        let mut invalid = bar("2025-09-26", 0.0);
+        invalid.close = f64::NAN;
+        bars.push(invalid);
+
+        let (cleaned, report) = validate(bars, None, &ValidationConfig::default()).unwrap();
+        let rebuilt = ValidationReport::from_issues(cleaned.len(), report.issues.clone());
+
+        assert_eq!(rebuilt.input_bars, report.input_bars);
+        assert_eq!(rebuilt.output_bars, report.output_bars);
+        assert_eq!(rebuilt.to_string(), report.to_string());
+        assert_eq!(
+            rebuilt.to_string(),
+            "8 bars checked, 0 repaired, 2 dropped, 1 flagged"
+        );
+    }
+
+    #[test]
+    fn repairs_high_and_low_that_do_not_bound_open_and_close() {
+        let bars = vec![
+            // High below the close
+            ohlc("2025-09-22", 100.0, 103.0, 99.0, 104.0),
+            // Low above the close
+            ohlc("2025-09-23", 104.0, 105.0, 103.0, 102.0),
+            // Both, with high and low swapped
+            ohlc("2025-09-24", 101.0, 99.0, 103.0, 102.0),
+            ohlc("2025-09-25", 102.0, 103.0, 101.0, 102.5),
+        ];
+
+        let (cleaned, report) = validate(bars, None, &ValidationConfig::default()).unwrap();
+
+        let ranges: Vec<(f64, f64)> = cleaned.iter().map(|bar| (bar.high, bar.low)).collect();
+        assert_eq!(
+            ranges,
+            [
+                (104.0, 99.0),
+                (105.0, 102.0),
+                (102.0, 101.0),
+                (103.0, 101.0)
+            ]
+        );
+        assert_eq!(report.repaired(), 3);
+        assert_eq!(report.output_bars, 4);
+        assert_eq!(
+            descriptions(&report, IssueAction::Repaired),
+            [
+                "2025-09-22 high/low 103.00/99.00 did not bound open/close 100.00/104.00, set to 104.00/99.00",
+                "2025-09-23 high/low 105.00/103.00 did not bound open/close 104.00/102.00, set to 105.00/102.00",
+                "2025-09-24 high/low 99.00/103.00 did not bound open/close 101.00/102.00, set to 102.00/101.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_invalid_prices_and_duplicate_dates() {
+        let mut bars = week();
+        bars[1].low = 0.0;
+        bars.push(bar("2025-09-22", 101.0));
+        bars.reverse();
+
+        let (cleaned, report) = validate(bars, None, &ValidationConfig::default()).unwrap();
+
+        let closes: Vec<(String, f64)> = cleaned
+            .iter()
+            .map(|bar| (bar.trade_date().to_string(), bar.close))
+            .collect();
+        assert_eq!(
+            closes,
+            [
+                ("2025-09-22".to_string(), 100.0),
+                ("2025-09-24".to_string(), 100.0),
+                ("2025-09-25".to_string(), 100.0),
+                ("2025-09-26".to_string(), 100.0),
+            ]
+        );
+        assert_eq!(
+            descriptions(&report, IssueAction::Dropped),
+            [
+                "2025-09-23 invalid price (open 100, high 100, low 0, close 100)",
+                "2025-09-22 duplicate date, kept the last bar",
+            ]
+        );
+        assert_eq!(report.input_bars, 6);
+        assert_eq!(report.output_bars, 4);
+    }
+
+    #[test]
+    fn rejects_a_batch_above_the_invalid_bar_threshold() {
+        // One invalid bar in five is exactly the 20% allowed
+        let mut bars = week();
+        bars[0].close = f64::NAN;
+        let (cleaned, _) = validate(bars.clone(), None, &ValidationConfig::default()).unwrap();
+        assert_eq!(cleaned.len(), 4);
+
+        bars[3].open = -1.0;
+        let error = validate(bars.clone(), None, &ValidationConfig::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Data rejected: 2 of 5 bars have invalid prices"
+        );
+
+        let lenient = ValidationConfig {
+            max_dropped_ratio: 0.5,
+            ..ValidationConfig::default()
+        };
+        let (cleaned, report) = validate(bars, None, &lenient).unwrap();
+        assert_eq!(cleaned.len(), 3);
+        assert_eq!(report.dropped(), 2);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::models::{AssetType, Instrument};
+use chrono::{Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Trailing windows, in years, percentiles are computed over
+pub const PERCENTILE_YEARS: [u32; 2] = [5, 10];
+
+/// Fewest observations a percentile is computed from
+const MIN_OBSERVATIONS: usize = 20;
+
+/// Valuation older than this many days before the analyzed bar is left out
+const MAX_STALENESS_DAYS: i64 = 10;
+
+/// Valuation of an index on one trading day, from TuShare `index_dailybasic`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ValuationPoint {
+    pub date: NaiveDate,
+    pub pe: Option<f64>,
+    pub pe_ttm: Option<f64>,
+    pub pb: Option<f64>,
+    /// Turnover rate in percent
+    pub turnover_rate: Option<f64>,
+}
+
+/// Where a value ranks within a trailing window of its own history
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Percentile {
+    pub years: u32,
+    /// Share of the window's days valued below the latest, ties counted
+    /// half, from 0 to 100
+    pub rank: f64,
+    pub observations: usize,
+    /// First day in the window with a value
+    pub start_date: NaiveDate,
+    /// Whether the history covers the whole window
+    pub complete: bool,
+}
+
+/// A valuation ratio and its historical percentiles
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValuationMetric {
+    pub value: f64,
+    /// One per `PERCENTILE_YEARS` window with enough history
+    pub percentiles: Vec<Percentile>,
+}
+
+/// Valuation of an index at a trading day with historical context
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Valuation {
+    /// Trading day of the valuation, at most the analyzed bar's
+    pub trade_date: NaiveDate,
+    pub pe: Option<ValuationMetric>,
+    pub pe_ttm: Option<ValuationMetric>,
+    pub pb: Option<ValuationMetric>,
+    pub turnover_rate: Option<f64>,
+}
+
+/// Whether TuShare publishes valuation for the instrument; only indexes are
+/// covered
+pub fn is_supported(instrument: &Instrument) -> bool {
+    instrument.asset_type == AssetType::Index
+}
+
+/// Valuation at `date` from a history sorted by date
+///
+/// Uses the latest point on or before `date`, `None` if there is none
+/// within `MAX_STALENESS_DAYS` or it has no ratios.
+pub fn analyze(history: &[ValuationPoint], date: NaiveDate) -> Option<Valuation> {
+    let history = &history[..history.partition_point(|point| point.date <= date)];
+    let latest = history.last()?;
+    if (date - latest.date).num_days() > MAX_STALENESS_DAYS {
+        return None;
+    }
+
+    let metric = |get: fn(&ValuationPoint) -> Option<f64>| {
+        let value = get(latest).filter(|value| value.is_finite())?;
+        let percentiles = PERCENTILE_YEARS
+            .iter()
+            .filter_map(|&years| percentile(history, get, value, latest.date, years))
+            .collect();
+        Some(ValuationMetric { value, percentiles })
+    };
+
+    let valuation = Valuation {
+        trade_date: latest.date,
+        pe: metric(|point| point.pe),
+        pe_ttm: metric(|point| point.pe_ttm),
+        pb: metric(|point| point.pb),
+        turnover_rate: latest.turnover_rate,
+    };
+    (valuation.pe.is_some() || valuation.pe_ttm.is_some() || valuation.pb.is_some())
+        .then_some(valuation)
+}
+
+/// Percentile rank of `value` among the values of the `years` up to `end`
+fn percentile(
+    history: &[ValuationPoint],
+    get: fn(&ValuationPoint) -> Option<f64>,
+    value: f64,
+    end: NaiveDate,
+    years: u32,
+) -> Option<Percentile> {
+    let window_start = end.checked_sub_months(Months::new(years * 12))?;
+    let window: Vec<(NaiveDate, f64)> = history
+        .iter()
+        .filter(|point| point.date > window_start)
+        .filter_map(|point| Some((point.date, get(point).filter(|v| v.is_finite())?)))
+        .collect();
+    if window.len() < MIN_OBSERVATIONS {
+        return None;
+    }
+
+    let below = window.iter().filter(|(_, v)| *v < value).count() as f64;
+    let equal = window.iter().filter(|(_, v)| *v == value).count() as f64;
+    let start_date = window[0].0;
+
+    Some(Percentile {
+        years,
+        rank: (below + equal / 2.0) / window.len() as f64 * 100.0,
+        observations: window.len(),
+        start_date,
+        // Allow for the holidays at the start of a window
+        complete: (start_date - window_start).num_days() <= 31,
+    })
+}
+
+/// English ordinal of a whole number, e.g. `23rd`
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+impl fmt::Display for Percentile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} percentile over {} years",
+            ordinal(self.rank.round() as u32),
+            self.years
+        )?;
+        if !self.complete {
+            write!(f, " (since {})", self.start_date)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ValuationMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}", self.value)?;
+        for (i, percentile) in self.percentiles.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { ", " } else { "; " }, percentile)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Valuation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics = [("PE-TTM", &self.pe_ttm), ("PE", &self.pe), ("PB", &self.pb)];
+        let mut lines: Vec<String> = metrics
+            .iter()
+            .filter_map(|(label, metric)| {
+                metric
+                    .as_ref()
+                    .map(|metric| format!("{}: {}", label, metric))
+            })
+            .collect();
+        if let Some(turnover) = self.turnover_rate {
+            lines.push(format!("Turnover Rate: {:.2}%", turnover));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Days;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn point(date: NaiveDate, pe: f64) -> ValuationPoint {
+        ValuationPoint {
+            date,
+            pe: Some(pe),
+            pe_ttm: None,
+            pb: None,
+            turnover_rate: None,
+        }
+    }
+
+    /// One point per day ending on `end`, valued `values` in order
+    fn daily(end: &str, values: &[f64]) -> Vec<ValuationPoint> {
+        let first = date(end) - Days::new(values.len() as u64 - 1);
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &pe)| point(first + Days::new(i as u64), pe))
+            .collect()
+    }
+
+    fn pe_percentiles(history: &[ValuationPoint], on: &str) -> Vec<Percentile> {
+        analyze(history, date(on)).unwrap().pe.unwrap().percentiles
+    }
+
+    #[test]
+    fn ranks_the_latest_value_by_midrank() {
+        // 9 of 20 values below 10, and 10 itself counted half
+        let values: Vec<f64> = (1..=20).map(f64::from).filter(|v| *v != 10.0).collect();
+        let history = daily("2025-06-30", &[values, vec![10.0]].concat());
+
+        let percentiles = pe_percentiles(&history, "2025-06-30");
+
+        assert_eq!(percentiles.len(), 2);
+        assert_eq!(percentiles[0].years, 5);
+        assert_eq!(percentiles[1].years, 10);
+        assert_eq!(percentiles[0].observations, 20);
+        assert_eq!(percentiles[0].rank, 47.5);
+    }
+
+    #[test]
+    fn counts_ties_half() {
+        let history = daily("2025-06-30", &[[12.0; 10], [8.0; 10]].concat());
+
+        let percentiles = pe_percentiles(&history, "2025-06-30");
+
+        // None below 8 and 10 equal to it
+        assert_eq!(percentiles[0].rank, 25.0);
+        assert_eq!(
+            percentiles[0].to_string(),
+            "25th percentile over 5 years (since 2025-06-11)"
+        );
+
+        let history = daily("2025-06-30", &[12.0; 20]);
+        assert_eq!(pe_percentiles(&history, "2025-06-30")[0].rank, 50.0);
+    }
+
+    #[test]
+    fn needs_enough_observations_for_a_percentile() {
+        let history = daily("2025-06-30", &[10.0; 19]);
+        let pe = analyze(&history, date("2025-06-30")).unwrap().pe.unwrap();
+        assert_eq!(pe.value, 10.0);
+        assert!(pe.percentiles.is_empty());
+        assert_eq!(pe.to_string(), "10.00");
+
+        assert_eq!(
+            pe_percentiles(&daily("2025-06-30", &[10.0; 20]), "2025-06-30").len(),
+            2
+        );
+
+        // Missing and non-finite values do not count
+        let mut history = daily("2025-06-30", &[10.0; 21]);
+        history[0].pe = None;
+        history[1].pe = Some(f64::NAN);
+        assert!(pe_percentiles(&history, "2025-06-30").is_empty());
+    }
+
+    #[test]
+    fn leaves_out_stale_or_empty_valuation() {
+        let history = daily("2025-06-20", &[10.0; 20]);
+
+        // Ten days old is still used, eleven is not
+        assert_eq!(
+            analyze(&history, date("2025-06-30")).unwrap().trade_date,
+            date("2025-06-20")
+        );
+        assert!(analyze(&history, date("2025-07-01")).is_none());
+        // Points after the analyzed day are ignored
+        assert!(analyze(&history, date("2025-05-31")).is_none());
+        let valuation = analyze(&history, date("2025-06-10")).unwrap();
+        assert_eq!(valuation.trade_date, date("2025-06-10"));
+        assert_eq!(valuation.pe.unwrap().percentiles.len(), 0);
+
+        let mut history = daily("2025-06-30", &[10.0; 20]);
+        history[19].pe = None;
+        assert!(analyze(&history, date("2025-06-30")).is_none());
+    }
+
+    #[test]
+    fn treats_a_window_starting_within_a_month_as_complete() {
+        // The 5-year window opens after 2020-06-30
+        let start = |first: &str| {
+            let mut history = vec![point(date(first), 10.0)];
+            history.extend(daily("2025-06-30", &[10.0; 19]));
+            pe_percentiles(&history, "2025-06-30")[0]
+        };
+
+        let complete = start("2020-07-31");
+        assert!(complete.complete);
+        assert_eq!(complete.start_date, date("2020-07-31"));
+        assert_eq!(complete.to_string(), "50th percentile over 5 years");
+
+        let partial = start("2020-08-01");
+        assert!(!partial.complete);
+        assert_eq!(
+            partial.to_string(),
+            "50th percentile over 5 years (since 2020-08-01)"
+        );
+
+        // A point on the window start falls outside it, leaving 19 within
+        // 5 years and 20 within 10
+        let mut history = vec![point(date("2020-06-30"), 10.0)];
+        history.extend(daily("2025-06-30", &[10.0; 19]));
+        let percentiles = pe_percentiles(&history, "2025-06-30");
+        assert_eq!(percentiles.len(), 1);
+        assert_eq!(percentiles[0].years, 10);
+        assert!(!percentiles[0].complete);
+    }
+
+    #[test]
+    fn formats_ordinals() {
+        let ordinals: Vec<String> = [0, 1, 2, 3, 4, 11, 12, 13, 21, 22, 23, 100, 101, 111, 112]
+            .into_iter()
+            .map(ordinal)
+            .collect();
+
+        assert_eq!(
+            ordinals,
+            [
+                "0th", "1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "23rd",
+                "100th", "101st", "111th", "112th"
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_every_available_ratio() {
+        let history: Vec<ValuationPoint> = daily("2025-06-30", &[10.0; 20])
+            .into_iter()
+            .map(|point| ValuationPoint {
+                pb: Some(1.5),
+                turnover_rate: Some(0.42),
+                ..point
+            })
+            .collect();
+
+        let valuation = analyze(&history, date("2025-06-30")).unwrap();
+
+        assert!(valuation.pe_ttm.is_none());
+        assert_eq!(
+            valuation.to_string(),
+            "PE: 10.00, 50th percentile over 5 years (since 2025-06-11); \
+             50th percentile over 10 years (since 2025-06-11)\n\
+             PB: 1.50, 50th percentile over 5 years (since 2025-06-11); \
+             50th percentile over 10 years (since 2025-06-11)\n\
+             Turnover Rate: 0.42%"
+        );
+    }
+}